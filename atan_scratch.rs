@@ -0,0 +1,126 @@
+/*
+ * atan(1/x) without heap allocations during the computation, for repeated computations like the
+ * terms of a formula or a batch of constants, and for callers that can not afford to allocate at
+ * any time. The caller creates an AtanScratch once, which holds all Numbers the computation
+ * needs, and passes it to ataninv_into for every x.
+ *
+ * For nthreads threads, AtanScratch holds exactly 2 max(nthreads, 1) Numbers of limbs digits:
+ * a partial sum and a refterm for each thread, which sums a consecutive range of the terms like
+ * ataninv_range. Afterwards, the other partial sums are added to the one of the first thread,
+ * which is the result. Since every term is truncated the same way in any range, the digits are
+ * the ones of ataninv_scalar_until for any number of threads.
+ *
+ * The terms get shorter with the leading zeros of refterm, so the work for the first k of T terms
+ * is about proportional to k - k^2/(2T), and thread i starts at T (1 - sqrt(1 - i/n)) to give all
+ * of them the same work. Each thread but the first also divides 1/x down to its first term, as
+ * many powers of x^2 at a time as fit into a Digit.
+ *
+ * Only the Numbers are preallocated: spawning threads with thread::scope allocates their handles
+ * and stacks, so the computation is entirely free of allocations only for nthreads <= 1, and
+ * without the profile feature, whose counters are allocated on first use in each thread.
+ */
+use super::*;
+
+pub struct AtanScratch {
+    // The partial sum and refterm of each thread
+    buffers: Vec<(Number, Number)>,
+}
+
+impl AtanScratch {
+    pub fn new(limbs: usize, nthreads: usize) -> Self {
+        // The 2 max(nthreads, 1) Numbers of limbs digits that ataninv_into needs for up to
+        // nthreads threads
+        let buffers = (0..nthreads.max(1))
+            .map(|_| (Number::zero_with_len(limbs), Number::zero_with_len(limbs)))
+            .collect();
+        AtanScratch { buffers }
+    }
+
+    pub fn limbs(&self) -> usize {
+        self.buffers[0].0.len()
+    }
+
+    pub fn threads(&self) -> usize {
+        // The largest nthreads ataninv_into can use with this scratch
+        self.buffers.len()
+    }
+}
+
+fn sum_range(x: Digit, start_term: usize, end_term: usize, result: &mut Number,
+             refterm: &mut Number) {
+    // result = ataninv_range(x, start_term, end_term, limbs) in the given Numbers. They are moved
+    // into a ScalarState and back, replaced by empty Numbers in between, which do not allocate.
+    if end_term <= start_term {
+        result.set_to_zero();
+        return;
+    }
+    let empty = || Number::zero_with_len(0);
+    let state = ScalarState::at_term_in(x, Series::Atan, start_term, mem::replace(result, empty()),
+                                        mem::replace(refterm, empty()));
+    let (sum, stats) = ataninv_scalar_resume(state, |state| state.terms + 1 < end_term);
+    *result = sum;
+    // This is refterm, which ataninv_scalar_resume reuses for the error bound
+    *refterm = stats.error_bound;
+}
+
+pub fn ataninv_into(x: Digit, nthreads: usize, scratch: &mut AtanScratch) -> &Number {
+    // atan(1/x) with scratch.limbs() digits using nthreads threads, computed in the Numbers of
+    // scratch, which must have been created for at least nthreads threads. The result stays valid
+    // until the next call with the same scratch.
+    let n = nthreads.max(1);
+    assert!(n <= scratch.threads(),
+            "{} threads need {} Numbers, but the scratch only has {}",
+            n, 2 * n, 2 * scratch.threads());
+    let terms = atan_term_count(x, scratch.limbs()) as f64;
+    let bound = |i: usize| {
+        if i == n {
+            usize::MAX
+        } else {
+            (terms * (1.0 - (1.0 - i as f64 / n as f64).sqrt())) as usize
+        }
+    };
+    let ((result, refterm), rest) = scratch.buffers[..n].split_first_mut().unwrap();
+    if rest.is_empty() {
+        // thread::scope allocates even without threads
+        sum_range(x, 0, usize::MAX, result, refterm);
+        return result;
+    }
+    thread::scope(|scope| {
+        for (i, (partial, refterm)) in rest.iter_mut().enumerate() {
+            scope.spawn(move || sum_range(x, bound(i + 1), bound(i + 2), partial, refterm));
+        }
+        sum_range(x, 0, bound(1), result, refterm);
+    });
+    for (partial, _) in rest.iter() {
+        result.wrapping_add_assign(partial);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ataninv_into_agrees_with_scalar_for_any_threads() {
+        let limbs = 6;
+        let mut scratch = AtanScratch::new(limbs, 5);
+        assert_eq!((scratch.limbs(), scratch.threads()), (limbs, 5));
+        for x in [2, 5, 239] {
+            let (expected, _) = ataninv_scalar_until(x, limbs, Series::Atan, None);
+            // Also with fewer threads than the scratch has, and with more threads than terms
+            for nthreads in 0..=5 {
+                assert_eq!(ataninv_into(x, nthreads, &mut scratch).digits, expected.digits,
+                           "x = {}, {} threads", x, nthreads);
+            }
+        }
+        // The scratch for no threads has the Numbers of one
+        assert_eq!(AtanScratch::new(limbs, 0).threads(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "3 threads need 6 Numbers, but the scratch only has 4")]
+    fn ataninv_into_rejects_too_small_scratch() {
+        ataninv_into(5, 3, &mut AtanScratch::new(4, 2));
+    }
+}
@@ -0,0 +1,166 @@
+/*
+ * Benchmarks of the experimental alternatives against the code main uses, run with
+ *
+ *     picalc bench <name> [<digits>] [<nthreads>]
+ *
+ * Each one computes the same digits both ways, checks that they agree and reports both times.
+ * The names are
+ * - aligned: the terms of atan(1/5) in Number<AlignedLimbs> against Number, see aligned.rs
+ * - decimal-limbs: compute_pi_dec against Machin's formula with binary limbs and the decimal
+ *   conversion, see decimal_limbs.rs
+ * - decimal-split: decimal_split with nthreads threads against decimal_chunks on the digits of
+ *   pi, see decimal_split.rs
+ * - digits: compute_pi with the Digit of this build, which is the only one that cannot compare
+ *   against the other within one run. Run it once as is and once built with
+ *   --features u32-digits, both report their time, the peak memory of the process and the
+ *   digest of the decimal digits, which must agree.
+ * - scan: add_assign_scan and mul4_scan against add_assign and mul4, SCAN_REPEAT times each on
+ *   Numbers of the given precision, see scan.rs
+ * - scratch: ataninv_into for the arctans of Gauss's formula with one AtanScratch against
+ *   ataninv_until, which allocates its Numbers for each, see atan_scratch.rs
+ */
+use super::*;
+use super::aligned::AlignedLimbs;
+use super::atan_scratch::{ataninv_into, AtanScratch};
+use super::computation::limbs_for_precision;
+use super::decimal_limbs::{compute_pi_dec, digits_for_bits};
+use super::scan::{add_assign_scan, mul4_scan};
+
+// The passes of scan.rs take a fraction of a second even for the full precision
+const SCAN_REPEAT: usize = 100;
+
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn atan_series<S: LimbStore>(x: Digit, limbs: usize) -> Number<S> {
+    // atan(1/x) with one division of refterm by x^2 and one of the term per term, which are the
+    // passes over the digits that dominate ataninv_scalar_until
+    let x2 = x * x;
+    let mut refterm: Number<S> = Number::from_inv_with_len(x, limbs);
+    let mut result = refterm.clone();
+    let mut denom = 1;
+    let mut neg = true;
+    while !refterm.is_zero() {
+        refterm /= x2;
+        denom += 2;
+        result.add_div_assign(&refterm, denom, neg);
+        neg = !neg;
+    }
+    result
+}
+
+fn aligned(digits: usize) -> String {
+    let limbs = limbs_for_precision(digits);
+    let (aligned, aligned_time) = timed(|| atan_series::<AlignedLimbs>(5, limbs));
+    let (vec, vec_time) = timed(|| atan_series::<Vec<Digit>>(5, limbs));
+    assert!((0..limbs).all(|i| aligned.digits.get(i) == vec.digits[i]),
+            "The digits of both differ");
+    format!("AlignedLimbs: {:.3} s\nVec: {:.3} s\n", aligned_time.as_secs_f64(),
+            vec_time.as_secs_f64())
+}
+
+fn decimal_limbs(digits: usize, nthreads: usize) -> String {
+    // pi in base 10^19 limbs with the precision of compute_pi, so both have the same bits
+    let limbs = limbs_for_precision(digits);
+    let ((int_part, dec), dec_time) =
+        timed(|| compute_pi_dec(digits_for_bits(limbs * Digit::BITS as usize)));
+    let (binary, binary_time) = timed(|| {
+        let pi = computation::PiComputation::new().precision_digits(digits).threads(nthreads)
+            .build().run();
+        (pi.int_part, pi.frac.leading_digits_decimal(digits))
+    });
+    let mut dec_digits = Vec::new();
+    dec.write_decimal(&mut dec_digits).unwrap();
+    assert_eq!((int_part as i64, &dec_digits[..digits]), (binary.0, binary.1.as_bytes()),
+               "The digits of both differ");
+    format!("decimal limbs: {:.3} s\nbinary limbs and conversion: {:.3} s\n",
+            dec_time.as_secs_f64(), binary_time.as_secs_f64())
+}
+
+fn decimal_split(digits: usize, nthreads: usize) -> String {
+    let pi = computation::compute_pi(digits, nthreads);
+    let (split, split_time) = timed(|| pi.decimal_split(nthreads));
+    let (chunks, chunks_time) = timed(|| pi.decimal_chunks().collect::<String>());
+    assert_eq!(split, chunks, "The digits of both differ");
+    format!("decimal_split: {:.3} s\ndecimal_chunks: {:.3} s\n", split_time.as_secs_f64(),
+            chunks_time.as_secs_f64())
+}
+
+fn peak_memory() -> Option<usize> {
+    // The peak resident set size of this process in bytes, which only Linux reports in
+    // /proc/self/status
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: usize = line.trim_start_matches("VmHWM:").trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+fn digit_size(digits: usize, nthreads: usize) -> String {
+    // The digest is the one of the decimal digits, since the limbs differ with the digit size
+    use sha2::{Digest, Sha256};
+    let (pi, time) = timed(|| computation::compute_pi(digits, nthreads));
+    let memory = peak_memory().map_or("unknown".to_string(),
+                                      |bytes| format!("{:.1} MiB", bytes as f64 / 1048576.0));
+    let digest: String = Sha256::digest(pi.leading_digits_decimal(digits)).iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("u{} digits: {:.3} s, peak memory {}\ndecimal digest: {}\n", Digit::BITS,
+            time.as_secs_f64(), memory, digest)
+}
+
+fn scan(digits: usize) -> String {
+    // Sums and products of 1/3 and 1/7, whose digits are all carries or none
+    let limbs = limbs_for_precision(digits);
+    let third: Number = Number::from_inv_with_len(3, limbs);
+    let seventh: Number = Number::from_inv_with_len(7, limbs);
+    let run = |add: fn(&mut Number, &Number), mul4: fn(&mut Number) -> Digit| {
+        let mut x = third.clone();
+        let (_, add_time) = timed(|| (0..SCAN_REPEAT).for_each(|_| add(&mut x, &seventh)));
+        let mut int_part: Digit = 0;
+        let (_, mul4_time) = timed(|| {
+            (0..SCAN_REPEAT).for_each(|_| int_part = int_part.wrapping_add(mul4(&mut x)))
+        });
+        (x, int_part, add_time, mul4_time)
+    };
+    let (passes, passes_int, passes_add, passes_mul4) = run(add_assign_scan, mul4_scan);
+    let (loops, loops_int, loops_add, loops_mul4) =
+        run(|x, y| x.wrapping_add_assign(y), |x| x.wrapping_mul4());
+    assert_eq!((passes.digits, passes_int), (loops.digits, loops_int), "The digits of both differ");
+    let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+    format!("add_assign_scan: {:.2} ms, add_assign: {:.2} ms\n\
+             mul4_scan: {:.2} ms, mul4: {:.2} ms\n",
+            ms(passes_add), ms(loops_add), ms(passes_mul4), ms(loops_mul4))
+}
+
+fn scratch(digits: usize, nthreads: usize) -> String {
+    let limbs = limbs_for_precision(digits);
+    let xs = computation::Algo::Gauss.formula().iter().map(|&(_, x)| x);
+    let (into, into_time) = timed(|| {
+        let mut scratch = AtanScratch::new(limbs, nthreads);
+        xs.clone().map(|x| ataninv_into(x, nthreads, &mut scratch).clone()).collect::<Vec<_>>()
+    });
+    let (until, until_time) = timed(|| {
+        xs.map(|x| ataninv_until(x, limbs, nthreads, Series::Atan, None, false, None).0)
+            .collect::<Vec<_>>()
+    });
+    assert!(into.iter().zip(&until).all(|(a, b)| a.digits == b.digits),
+            "The digits of both differ");
+    format!("ataninv_into: {:.3} s\nataninv_until: {:.3} s\n", into_time.as_secs_f64(),
+            until_time.as_secs_f64())
+}
+
+pub fn run_benchmark(name: &str, digits: usize, nthreads: usize) -> Result<String, String> {
+    // The report of the benchmark with the given name
+    match name {
+        "aligned" => Ok(aligned(digits)),
+        "decimal-limbs" => Ok(decimal_limbs(digits, nthreads)),
+        "decimal-split" => Ok(decimal_split(digits, nthreads)),
+        "digits" => Ok(digit_size(digits, nthreads)),
+        "scan" => Ok(scan(digits)),
+        "scratch" => Ok(scratch(digits, nthreads)),
+        _ => Err(format!("Unknown benchmark '{}'", name)),
+    }
+}
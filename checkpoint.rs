@@ -0,0 +1,318 @@
+/*
+ * Checkpoints of the scalar summation, so a long computation of an arctan can be stopped and
+ * resumed later. A checkpoint holds the ScalarState between two terms: one header line
+ *
+ *     picalc checkpoint x=5 series=atan digit_bits=64 limbs=20000 denom=7 stepsize=15625 neg=false
+ *         terms=3 last_divisor=109375 result_zeros=0 refterm_zeros=0 result_ones=640076
+ *         refterm_ones=640000
+ *
+ * (in one line), followed by the digits of result and refterm like the raw output format.
+ *
+ * A checkpoint that was corrupted or belongs to another computation would silently give wrong
+ * digits, so load_checkpoint checks everything that can be checked without redoing the work:
+ * - x, the series, the digit size and the number of limbs are the ones of the computation.
+ * - There are exactly two Numbers of that many limbs after the header.
+ * - The stored zeros and count_ones of both Numbers are the ones of their digits.
+ * - denom is odd and terms = (denom - 1) / 2, and for atan, neg alternates with the terms.
+ * - stepsize is x^(2j) with 2j < denom and last_divisor = denom * stepsize, so refterm is 1/x^n
+ *   with n = denom - 2j.
+ * - The leading bit of refterm is where the one of 1/x^n is: its position below the binary point
+ *   is within one of n log2(x), or refterm is zero and 1/x^n is below the last digit.
+ * Apart from count_ones, the digits of result can not be checked without summing the terms again.
+ */
+use super::*;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+
+const MAGIC: &str = "picalc checkpoint";
+
+fn series_name(series: Series) -> &'static str {
+    match series {
+        Series::Atan => "atan",
+        Series::Atanh => "atanh",
+    }
+}
+
+#[allow(dead_code)]
+pub fn save_checkpoint(state: &ScalarState, path: &str) -> io::Result<()> {
+    // Write the state to path. It is written to a temporary file first and then renamed, so an
+    // interruption leaves the previous checkpoint intact.
+    let tmp = format!("{}.tmp", path);
+    let mut w = BufWriter::new(File::create(&tmp)?);
+    writeln!(w, "{} x={} series={} digit_bits={} limbs={} denom={} stepsize={} neg={} terms={} \
+                 last_divisor={} result_zeros={} refterm_zeros={} result_ones={} refterm_ones={}",
+             MAGIC, state.x, series_name(state.series), Digit::BITS, state.result.len(),
+             state.denom, state.stepsize, state.neg, state.terms, state.last_divisor,
+             state.result.zeros, state.refterm.zeros, state.result.count_ones(),
+             state.refterm.count_ones())?;
+    state.result.write_raw(&mut w)?;
+    state.refterm.write_raw(&mut w)?;
+    w.flush()?;
+    drop(w);
+    fs::rename(&tmp, path)
+}
+
+fn read_number(r: &mut impl Read, limbs: usize, stored_zeros: usize, stored_ones: u64,
+               name: &str) -> Result<Number, String> {
+    let mut bytes = vec![0; limbs * mem::size_of::<Digit>()];
+    r.read_exact(&mut bytes).map_err(|_| format!("{} has fewer than {} limbs", name, limbs))?;
+    let digits: Vec<Digit> = bytes.chunks(mem::size_of::<Digit>())
+        .map(|chunk| Digit::from_be_bytes(chunk.try_into().unwrap()))
+        .collect();
+    let number: Number = Number::from_digits(&digits);
+    if number.zeros != stored_zeros {
+        return Err(format!("{} is stored with {} leading zero limbs, but has {}",
+                           name, stored_zeros, number.zeros));
+    }
+    if number.count_ones() != stored_ones {
+        return Err(format!("{} is stored with {} one bits, but has {}",
+                           name, stored_ones, number.count_ones()));
+    }
+    Ok(number)
+}
+
+fn leading_bit(n: &Number) -> Option<usize> {
+    // Position of the leading one bit below the binary point, 1 for the bit with the value 1/2
+    (!n.is_zero()).then(|| {
+        n.zeros * Digit::BITS as usize + n.digits.get(n.zeros).leading_zeros() as usize + 1
+    })
+}
+
+#[allow(dead_code)]
+pub fn load_checkpoint(path: &str, x: Digit, limbs: usize, series: Series)
+        -> Result<ScalarState, String> {
+    // Read a checkpoint of the computation of atan(1/x) or atanh(1/x) with the given number of
+    // limbs, checking it as described at the top
+    let at = |err: String| format!("Invalid checkpoint '{}': {}", path, err);
+    let file = File::open(path)
+        .map_err(|err| format!("Can not read checkpoint '{}': {}", path, err))?;
+    let mut r = BufReader::new(file);
+    let mut header = String::new();
+    r.read_line(&mut header).map_err(|err| at(err.to_string()))?;
+    let fields = header.trim_end().strip_prefix(MAGIC)
+        .ok_or_else(|| at("No checkpoint header".to_string()))?;
+    let fields: Vec<(&str, &str)> = fields.split_whitespace()
+        .map(|field| {
+            field.split_once('=').ok_or_else(|| at(format!("Malformed field '{}'", field)))
+        })
+        .collect::<Result<_, _>>()?;
+    let field = |key: &str| {
+        fields.iter().find(|(k, _)| *k == key).map(|&(_, value)| value)
+            .ok_or_else(|| at(format!("Missing {}", key)))
+    };
+    let number = |key: &str| -> Result<u128, String> {
+        let value = field(key)?;
+        value.parse().map_err(|_| at(format!("Invalid value '{}' for {}", value, key)))
+    };
+    let expect = |key: &str, value: String, expected: String| {
+        if value != expected {
+            return Err(at(format!("{} is {}, but the computation has {}", key, value, expected)));
+        }
+        Ok(())
+    };
+    expect("x", field("x")?.to_string(), x.to_string())?;
+    expect("series", field("series")?.to_string(), series_name(series).to_string())?;
+    expect("digit_bits", field("digit_bits")?.to_string(), Digit::BITS.to_string())?;
+    expect("limbs", field("limbs")?.to_string(), limbs.to_string())?;
+    let digit = |key: &str| {
+        Digit::try_from(number(key)?).map_err(|_| at(format!("{} does not fit into a Digit", key)))
+    };
+    let (denom, stepsize) = (digit("denom")?, digit("stepsize")?);
+    let last_divisor = digit("last_divisor")?;
+    let terms = number("terms")? as usize;
+    let neg: bool = field("neg")?.parse().map_err(|_| at("Invalid value for neg".to_string()))?;
+
+    let result = read_number(&mut r, limbs, number("result_zeros")? as usize,
+                             number("result_ones")? as u64, "result").map_err(at)?;
+    let refterm = read_number(&mut r, limbs, number("refterm_zeros")? as usize,
+                              number("refterm_ones")? as u64, "refterm").map_err(at)?;
+    if r.read(&mut [0]).map_err(|err| at(err.to_string()))? != 0 {
+        return Err(at(format!("There is more data after the two Numbers of {} limbs", limbs)));
+    }
+
+    if denom.is_multiple_of(2) {
+        return Err(at(format!("denom {} is even", denom)));
+    }
+    if terms as Digit != denom / 2 {
+        return Err(at(format!("{} terms do not end at denom {}", terms, denom)));
+    }
+    if series == Series::Atan && neg != terms.is_multiple_of(2) {
+        return Err(at(format!("The sign of the next term is wrong after {} terms", terms)));
+    }
+    if series == Series::Atanh && neg {
+        return Err(at("neg is set, but the terms of atanh are all added".to_string()));
+    }
+    // stepsize = x^(2j), checked by dividing it down to one
+    let x2 = x_squared(x);
+    let mut lag = 0;
+    let mut power = stepsize;
+    while power > 1 && power.is_multiple_of(x2) {
+        power /= x2;
+        lag += 2;
+    }
+    if power != 1 || lag >= denom {
+        return Err(at(format!("stepsize {} is not a power of x^2 below x^{}", stepsize, denom)));
+    }
+    if last_divisor as Double != denom as Double * stepsize as Double {
+        return Err(at(format!("last_divisor {} is not denom * stepsize", last_divisor)));
+    }
+    // refterm = 1/x^n, whose leading bit is at n log2(x), rounded up unless x is a power of two
+    let expected = (denom - lag) as f64 * (x as f64).log2();
+    match leading_bit(&refterm) {
+        Some(bit) if (bit as f64 - expected).abs() > 1.0 => {
+            return Err(at(format!("refterm has its leading bit at 2^-{}, but 1/x^{} is about \
+                                   2^-{:.1}", bit, denom - lag, expected)));
+        },
+        None if expected + 1.0 < (limbs * Digit::BITS as usize) as f64 => {
+            return Err(at(format!("refterm is zero, but 1/x^{} is not", denom - lag)));
+        },
+        _ => (),
+    }
+    Ok(ScalarState { x, series, result, refterm, denom, stepsize, neg, terms, last_divisor })
+}
+
+#[allow(dead_code)]
+pub fn ataninv_checkpointed(x: Digit, limbs: usize, series: Series, path: &str, every: usize)
+        -> Result<Number, String> {
+    // Same as ataninv_scalar_until without deadline, but resumed from the checkpoint at path if
+    // there is one, and writing a new one every given number of terms
+    let state = if fs::metadata(path).is_ok() {
+        load_checkpoint(path, x, limbs, series)?
+    } else {
+        ScalarState::new(x, limbs, series)
+    };
+    let mut error = None;
+    let (result, _) = ataninv_scalar_resume(state, |state| {
+        if state.terms.is_multiple_of(every) {
+            if let Err(err) = save_checkpoint(state, path) {
+                error = Some(format!("Can not write checkpoint '{}': {}", path, err));
+                return false;
+            }
+        }
+        true
+    });
+    match error {
+        Some(err) => Err(err),
+        None => Ok(result),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let name = format!("picalc-checkpoint-{}-{}", std::process::id(), name);
+        std::env::temp_dir().join(name).to_str().unwrap().to_string()
+    }
+
+    fn save_at(x: Digit, limbs: usize, series: Series, terms: usize, path: &str) {
+        // Write the checkpoint of the summation before the given term
+        ataninv_scalar_resume(ScalarState::new(x, limbs, series), |state| {
+            if state.terms == terms {
+                save_checkpoint(state, path).unwrap();
+                return false;
+            }
+            true
+        });
+    }
+
+    fn corrupted(path: &str, key: &str, value: &str) -> String {
+        // The checkpoint at path with the value of one field of the header replaced, as the
+        // error of loading it
+        let content = fs::read(path).unwrap();
+        let end = content.iter().position(|&b| b == b'\n').unwrap();
+        let header = std::str::from_utf8(&content[..end]).unwrap().split(' ')
+            .map(|field| match field.split_once('=') {
+                Some((k, _)) if k == key => format!("{}={}", k, value),
+                _ => field.to_string(),
+            })
+            .collect::<Vec<_>>().join(" ");
+        let changed = temp_path(&format!("corrupted-{}", key));
+        fs::write(&changed, [header.as_bytes(), &content[end..]].concat()).unwrap();
+        let result = load_checkpoint(&changed, 5, 4, Series::Atan);
+        fs::remove_file(&changed).unwrap();
+        result.err().unwrap_or_else(|| panic!("{}={} was accepted", key, value))
+    }
+
+    #[test]
+    fn resumes_to_same_digits() {
+        let limbs = 4;
+        for series in [Series::Atan, Series::Atanh] {
+            for (x, terms) in [(5, 3), (57, 2), (239, 1)] {
+                let path = temp_path("resume");
+                save_at(x, limbs, series, terms, &path);
+                let state = load_checkpoint(&path, x, limbs, series).unwrap();
+                assert_eq!(state.terms, terms);
+                let (result, _) = ataninv_scalar_resume(state, |_| true);
+                let (expected, _) = ataninv_scalar_until(x, limbs, series, None);
+                assert_eq!(result.digits, expected.digits, "x = {}", x);
+                // The checkpoint is still there, so this resumes from it and overwrites it
+                let result = ataninv_checkpointed(x, limbs, series, &path, 2).unwrap();
+                assert_eq!(result.digits, expected.digits, "x = {}", x);
+                fs::remove_file(&path).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_header() {
+        let path = temp_path("header");
+        save_at(5, 4, Series::Atan, 3, &path);
+        let cases = [
+            ("x", "6", "x is 6, but the computation has 5"),
+            ("series", "atanh", "series is atanh, but the computation has atan"),
+            ("digit_bits", "16", "digit_bits is 16"),
+            ("limbs", "5", "limbs is 5, but the computation has 4"),
+            ("denom", "8", "denom 8 is even"),
+            ("denom", "9", "3 terms do not end at denom 9"),
+            ("denom", "x", "Invalid value 'x' for denom"),
+            ("terms", "2", "2 terms do not end at denom 7"),
+            ("neg", "true", "The sign of the next term is wrong after 3 terms"),
+            ("stepsize", "50", "stepsize 50 is not a power of x^2 below x^7"),
+            ("stepsize", "390625", "stepsize 390625 is not a power of x^2 below x^7"),
+            ("last_divisor", "7", "last_divisor 7 is not denom * stepsize"),
+            ("result_zeros", "1", "result is stored with 1 leading zero limbs, but has 0"),
+            ("refterm_ones", "1", "refterm is stored with 1 one bits"),
+        ];
+        for (key, value, expected) in cases {
+            let err = corrupted(&path, key, value);
+            assert!(err.contains(expected), "{}={}: {}", key, value, err);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_corrupted_digits() {
+        let path = temp_path("digits");
+        save_at(5, 4, Series::Atan, 3, &path);
+        let content = fs::read(&path).unwrap();
+        let end = content.iter().position(|&b| b == b'\n').unwrap() + 1;
+        let check = |name: &str, content: &[u8], expected: &str| {
+            let changed = temp_path(name);
+            fs::write(&changed, content).unwrap();
+            let err = load_checkpoint(&changed, 5, 4, Series::Atan).err().unwrap();
+            fs::remove_file(&changed).unwrap();
+            assert!(err.contains(expected), "{}: {}", name, err);
+        };
+        // A flipped bit in the last digit of result and of refterm
+        let digit = mem::size_of::<Digit>();
+        for (number, name) in [(1, "result"), (2, "refterm")] {
+            let mut flipped = content.clone();
+            flipped[end + number * 4 * digit - 1] ^= 1;
+            check(name, &flipped, &format!("{} is stored with", name));
+        }
+        check("truncated", &content[..content.len() - 1], "refterm has fewer than 4 limbs");
+        check("extra", &[&content[..], &[0]].concat(), "more data after the two Numbers");
+        check("magic", &content[1..], "No checkpoint header");
+        // A refterm of 1/x^n for another n, with consistent zeros and count_ones
+        let mut state = load_checkpoint(&path, 5, 4, Series::Atan).unwrap();
+        state.refterm /= 25;
+        save_checkpoint(&state, &path).unwrap();
+        let err = load_checkpoint(&path, 5, 4, Series::Atan).err().unwrap();
+        assert!(err.contains("refterm has its leading bit at"), "{}", err);
+        fs::remove_file(&path).unwrap();
+        assert!(load_checkpoint(&path, 5, 4, Series::Atan).err().unwrap()
+                .starts_with("Can not read checkpoint"));
+    }
+}
@@ -0,0 +1,757 @@
+/*
+ * Fluent front-end over the lower-level functions, grouping the settings of a computation of pi:
+ *
+ *     let result = PiComputation::new().precision_digits(1000).threads(8)
+ *         .algorithm(Algo::Machin).build().run();
+ *
+ * The precision is rounded up to whole limbs, plus one guard limb that absorbs the truncation
+ * errors of all terms. result.correct_digits is not derived from the precision but from a bound
+ * of these errors, so it is usually, but not always, at least the requested number of digits:
+ * if the digits of pi after them are a long run of nines or zeros, the error bound can reach
+ * into them. compute_pi_adaptive retries with more guard limbs in that case.
+ */
+use super::*;
+use super::continued_fraction::ConvergentCheck;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Limbs beyond the requested precision. The truncation of each term costs at most one unit in the
+// last limb, and there are far fewer than 2^Digit::BITS terms.
+pub const GUARD_LIMBS: usize = 1;
+
+// Number of times compute_pi_adaptive doubles the guard limbs before it gives up
+pub const MAX_RETRIES: usize = 4;
+
+// Longest time between two calls of the callback of run_with_callback
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy)]
+pub enum Algo {
+    // pi/4 = 4atan(1/5) - atan(1/239)
+    Machin,
+    // pi/4 = 12atan(1/18) + 8atan(1/57) - 5atan(1/239)
+    Gauss,
+}
+
+impl FromStr for Algo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "machin" => Ok(Algo::Machin),
+            "gauss" => Ok(Algo::Gauss),
+            _ => Err(format!("Unknown algorithm '{}'", s)),
+        }
+    }
+}
+
+impl Algo {
+    pub fn formula(self) -> &'static [(i64, Digit)] {
+        // Coefficients c and arguments x of pi = sum over c*atan(1/x)
+        match self {
+            Algo::Machin => &[(16, 5), (-4, 239)],
+            Algo::Gauss => &[(48, 18), (32, 57), (-20, 239)],
+        }
+    }
+}
+
+pub struct PiComputationBuilder {
+    limbs: usize,
+    threads: usize,
+    algo: Algo,
+}
+
+pub struct PiComputation {
+    limbs: usize,
+    threads: usize,
+    algo: Algo,
+}
+
+pub struct PiResult {
+    // pi = int_part + frac
+    pub int_part: i64,
+    pub frac: Number,
+    // Number of Taylor terms of all arctans together
+    #[allow(dead_code)]
+    pub terms: usize,
+    // Number of leading decimal digits of frac that are guaranteed to be the ones of pi
+    pub correct_digits: usize,
+    // Largest number of Numbers that were allocated at the same time, and their digits in bytes.
+    // With threads, this grows with the number of tasks in flight, so it depends on the timing.
+    #[allow(dead_code)]
+    pub peak_buffers: usize,
+    #[allow(dead_code)]
+    pub peak_bytes: usize,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    // Terms of all arctans that are summed so far over the ones that do not vanish, which
+    // atan_term_count gives exactly, in [0, 1]
+    pub fraction: f64,
+    // Number of leading decimal digits of pi that the terms that are still missing and the
+    // truncation errors can no longer change, with the same caveat as PiResult.correct_digits
+    pub correct_digits: usize,
+}
+
+fn limbs_for_digits(digits: usize) -> usize {
+    // Limbs needed to resolve the given number of decimal digits, at least one
+    let limbs = (digits as f64 / (Digit::BITS as f64 * 2f64.log10())).ceil() as usize;
+    limbs.max(1)
+}
+
+pub fn limbs_for_precision(digits: usize) -> usize {
+    // Limbs that precision_digits uses for the given number of decimal digits
+    limbs_for_digits(digits) + GUARD_LIMBS
+}
+
+fn guaranteed_digits(frac: &Number, error: Digit) -> usize {
+    // Number of leading decimal digits that all values within error units in the last limb of
+    // frac share, and so also the true value.
+    let mut unit = vec![0; frac.len()];
+    unit[frac.len()-1] = error;
+    let unit: Number = Number::from_digits(&unit);
+    let mut lower = frac.clone();
+    lower.sub_assign(&unit);
+    let mut upper = frac.clone();
+    upper.wrapping_add_assign(&unit);
+    // A wrap around 0 or 1 can not happen for the fractional part of pi, but would make lower and
+    // upper agree on digits that the range between them does not share.
+    if lower.digits[0] > frac.digits[0] || upper.digits[0] < frac.digits[0] {
+        return 0;
+    }
+    lower.decimal_agreement(&upper)
+}
+
+impl PiComputationBuilder {
+    pub fn precision_digits(mut self, digits: usize) -> Self {
+        // Compute at least the given number of correct decimal digits
+        self.limbs = limbs_for_precision(digits);
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        // Number of worker threads in total, 0 computes each arctan with ataninv_scalar_until
+        self.threads = threads;
+        self
+    }
+
+    pub fn algorithm(mut self, algo: Algo) -> Self {
+        self.algo = algo;
+        self
+    }
+
+    pub fn build(self) -> PiComputation {
+        PiComputation { limbs: self.limbs, threads: self.threads, algo: self.algo }
+    }
+}
+
+impl PiComputation {
+    // PiComputation::new() reads better at the call site than PiComputationBuilder::new()
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> PiComputationBuilder {
+        // Start with the defaults: DIGITS limbs, no worker threads and Machin's formula
+        PiComputationBuilder { limbs: DIGITS, threads: 0, algo: Algo::Machin }
+    }
+
+    pub fn run(&self) -> PiResult {
+        let formula = self.algo.formula();
+        let xs: Vec<Digit> = formula.iter().map(|&(_, x)| x).collect();
+        let (atans, buffers): (Vec<Number>, Vec<usize>) =
+            ataninv_batch_timed(&xs, self.limbs, self.threads, Series::Atan, None, false, None)
+                .into_iter().map(|(atan, _, _, buffers)| (atan, buffers))
+                .unzip();
+        self.finish(atans, &buffers)
+    }
+
+    #[allow(dead_code)]
+    pub fn run_with_callback(&self, mut callback: impl FnMut(Progress)) -> PiResult {
+        // Same as run, but the arctans are computed on their own driver threads while the calling
+        // one calls callback at least every PROGRESS_INTERVAL, and once more at the end with
+        // fraction 1 and the correct_digits of the result. fraction and correct_digits do not
+        // decrease from one call to the next, except that the last correct_digits may be lower
+        // if the digits of pi after them are a long run of nines or zeros.
+        // The drivers only store the number of terms they have summed in an atomic counter, so
+        // the computation never waits for the callback, a slow one is just called less often.
+        let formula = self.algo.formula();
+        let xs: Vec<Digit> = formula.iter().map(|&(_, x)| x).collect();
+        let counts = split_threads(&xs, self.threads);
+        let (term_counts, error) = self.error_bound();
+        let summed: Vec<AtomicUsize> = xs.iter().map(|_| AtomicUsize::new(0)).collect();
+        let caller = thread::current();
+        let mut last = Progress { fraction: 0.0, correct_digits: 0 };
+        let (atans, buffers): (Vec<Number>, Vec<usize>) = thread::scope(|scope| {
+            let handles: Vec<_> = xs.iter().zip(&counts).zip(&summed)
+                .map(|((&x, &n), summed)| {
+                    let caller = caller.clone();
+                    scope.spawn(move || {
+                        let result = if n == 0 {
+                            let state = ScalarState::new(x, self.limbs, Series::Atan);
+                            let (atan, _) = ataninv_scalar_resume(state, |state| {
+                                summed.store(state.terms, Ordering::Relaxed);
+                                true
+                            });
+                            (atan, 2)
+                        } else {
+                            let (atan, _, buffers) = ataninv_threaded2_until(
+                                x, self.limbs, n, Series::Atan, None, false, None, None, None,
+                                Some(summed));
+                            (atan, buffers)
+                        };
+                        // Do not let the caller wait for the rest of PROGRESS_INTERVAL
+                        caller.unpark();
+                        result
+                    })
+                })
+                .collect();
+            loop {
+                thread::park_timeout(PROGRESS_INTERVAL);
+                if handles.iter().all(|handle| handle.is_finished()) {
+                    break;
+                }
+                let summed: Vec<usize> = summed.iter()
+                    .map(|summed| summed.load(Ordering::Relaxed))
+                    .collect();
+                let progress = self.progress(&summed, &term_counts, error);
+                last = Progress {
+                    fraction: progress.fraction.max(last.fraction),
+                    correct_digits: progress.correct_digits.max(last.correct_digits),
+                };
+                callback(last);
+            }
+            handles.into_iter().map(|handle| handle.join().unwrap()).unzip()
+        });
+        let result = self.finish(atans, &buffers);
+        callback(Progress { fraction: 1.0, correct_digits: result.correct_digits });
+        result
+    }
+
+    fn error_bound(&self) -> (Vec<usize>, Digit) {
+        // The number of terms of each arctan, and a bound of the error of pi in units of the last
+        // limb. Each term is off by less than one unit in the last limb from its own truncation
+        // and less than another one from the truncation of refterm that it was divided from. The
+        // terms that are left out sum to less than one unit, and atan_term_count may be off by
+        // one, so 2*(terms + 2) bounds the error of one arctan, which is multiplied by |c|.
+        let formula = self.algo.formula();
+        let term_counts: Vec<usize> = formula.iter()
+            .map(|&(_, x)| atan_term_count(x, self.limbs))
+            .collect();
+        let error = formula.iter().zip(&term_counts)
+            .map(|(&(c, _), &terms)| c.unsigned_abs() as Digit * 2 * (terms as Digit + 2))
+            .sum();
+        (term_counts, error)
+    }
+
+    fn progress(&self, summed: &[usize], term_counts: &[usize], error: Digit) -> Progress {
+        // Progress after the given number of terms after 1/x of each arctan. The terms of
+        // atan(1/x) after the first k of them decrease and alternate, so they add up to less
+        // than the next one, which is below 1/x^(2k+3). So pi is off by less than the number of
+        // arctans plus one times the largest of the |c|/x^(2k+3) and the truncation errors,
+        // which is compared in decimal logarithms since it is far below the range of f64.
+        // The fraction counts 1/x as well, since term_counts does, and each arctan only up to
+        // its count. The summation does not stop right at the last term that does not vanish,
+        // but only once refterm, which lags behind, is zero too, which takes a few more zero
+        // terms, up to 13 for atan(1/5) and 5 for atan(1/239) at 10 to 20000 limbs.
+        let done: usize = summed.iter().zip(term_counts).map(|(&k, &count)| min(k + 1, count))
+            .sum();
+        let fraction = done as f64 / term_counts.iter().sum::<usize>() as f64;
+        let truncation = (error as f64).log10()
+            - (self.limbs * Digit::BITS as usize) as f64 * 2f64.log10();
+        let largest = self.algo.formula().iter().zip(summed)
+            .map(|(&(c, x), &k)| {
+                (c.unsigned_abs() as f64).log10() - (2*k + 3) as f64 * (x as f64).log10()
+            })
+            .fold(truncation, f64::max);
+        let digits = -(largest + ((summed.len() + 1) as f64).log10());
+        Progress { fraction, correct_digits: digits.max(0.0) as usize }
+    }
+
+    fn finish(&self, atans: Vec<Number>, buffers: &[usize]) -> PiResult {
+        // Combine the arctans of the formula into the result
+        let scaled: Vec<(i64, Number)> = self.algo.formula().iter().map(|&(c, _)| c)
+            .zip(atans)
+            .collect();
+        let (frac, int_part) = combine_arctans(&scaled);
+        let peak_buffers = peak_buffers(buffers);
+        let (term_counts, error) = self.error_bound();
+        PiResult {
+            int_part,
+            correct_digits: guaranteed_digits(&frac, error),
+            frac,
+            terms: term_counts.iter().sum(),
+            peak_buffers,
+            peak_bytes: buffer_bytes(peak_buffers, self.limbs),
+        }
+    }
+}
+
+pub fn compute_pi(digits: usize, nthreads: usize) -> Number {
+    // Fractional part of pi with at least the given number of correct decimal digits
+    PiComputation::new().precision_digits(digits).threads(nthreads).build().run().frac
+}
+
+#[allow(dead_code)]
+pub fn compute_pi_with_callback(digits: usize, nthreads: usize, callback: impl FnMut(Progress))
+        -> Number {
+    // Same as compute_pi, calling callback with the progress, see run_with_callback
+    PiComputation::new().precision_digits(digits).threads(nthreads).build()
+        .run_with_callback(callback).frac
+}
+
+#[allow(dead_code)]
+pub fn compute_pi_adaptive(digits: usize, nthreads: usize) -> Option<PiResult> {
+    // Compute pi with at least the given number of guaranteed decimal digits, doubling the number
+    // of guard limbs and starting over each time the result falls short, at most MAX_RETRIES
+    // times. None if even the last attempt does not have enough digits.
+    let mut guard = GUARD_LIMBS;
+    for _ in 0..=MAX_RETRIES {
+        let computation = PiComputation {
+            limbs: limbs_for_digits(digits) + guard,
+            threads: nthreads,
+            algo: Algo::Machin,
+        };
+        let result = computation.run();
+        if result.correct_digits >= digits {
+            return Some(result);
+        }
+        guard *= 2;
+    }
+    None
+}
+
+pub struct PrecisionDiff {
+    // Limbs of the lower precision, and how many more the higher one has
+    pub limbs: usize,
+    pub extra_limbs: usize,
+    // Leading limbs and decimal digits of the lower result that the higher one shares
+    pub equal_limbs: usize,
+    pub equal_digits: usize,
+    // Decimal digits of the lower result, and how many of them are guaranteed like in PiResult
+    pub decimal_digits: usize,
+    pub correct_digits: usize,
+    // diff_report of the lower result against the first limbs of the higher one
+    pub report: String,
+}
+
+pub fn analyze_precision(digits: usize, extra_limbs: usize, nthreads: usize) -> PrecisionDiff {
+    // Compute pi with the precision of compute_pi for the given digits and again with extra_limbs
+    // more limbs, and compare them, to see where the lower one stops being correct.
+    // The truncation errors of all terms add up to a few thousand units in the last limb, which
+    // is what the guard limb is for. So the two usually differ only in the low bits of the last
+    // limb of the lower result, and all but its last few decimal digits agree: for 1000 digits,
+    // 1018 of 1021 with the first different bit 3384 of 3392. Only if the bits of pi before the
+    // error are a run of zeros or ones does the difference start earlier, since the carry or
+    // borrow runs through them. equal_digits is still at least correct_digits then, which is
+    // derived from a bound of the error instead of a comparison.
+    let run = |limbs| PiComputation { limbs, threads: nthreads, algo: Algo::Machin }.run();
+    let limbs = limbs_for_precision(digits);
+    let lower = run(limbs);
+    let higher = run(limbs + extra_limbs).frac;
+    let prefix: Number = Number::from_digits(&higher.digits[..limbs]);
+    PrecisionDiff {
+        limbs,
+        extra_limbs,
+        equal_limbs: lower.frac.agreement(&prefix),
+        equal_digits: lower.frac.decimal_agreement(&higher),
+        decimal_digits: lower.frac.decimal_digits(),
+        correct_digits: lower.correct_digits,
+        report: lower.frac.diff_report(&prefix),
+    }
+}
+
+pub struct VotedResult {
+    // pi = int_part + frac, where each limb of frac is the one most computations agree on
+    pub int_part: i64,
+    pub frac: Number,
+    // The smallest correct_digits of the computations
+    pub correct_digits: usize,
+    // Limbs before the guard limbs in which not all computations agree, in increasing order
+    pub disputed: Vec<usize>,
+    // The disputed limbs without a strict majority, which are taken from the first computation
+    pub undecided: Vec<usize>,
+}
+
+fn majority<T: Copy + PartialEq>(values: &[T]) -> (T, bool) {
+    // The most common of values, the first one of them if there is a tie, and whether it is the
+    // one of more than half of them
+    let count = |v: T| values.iter().filter(|&&w| w == v).count();
+    let best = values.iter().copied()
+        .fold(values[0], |best, v| if count(v) > count(best) { v } else { best });
+    (best, 2 * count(best) > values.len())
+}
+
+pub fn compute_pi_voted(digits: usize, nthreads: usize, algos: &[Algo]) -> VotedResult {
+    // Compute pi once for each of algos, which may repeat the same formula to guard against
+    // hardware errors, and combine the results by a majority vote for each limb. The
+    // computations run one after the other, each with nthreads threads.
+    assert!(!algos.is_empty(), "Nothing to vote on");
+    let results: Vec<PiResult> = algos.iter()
+        .map(|&algo| PiComputation::new().precision_digits(digits).threads(nthreads)
+             .algorithm(algo).build().run())
+        .collect();
+    vote(&results)
+}
+
+fn vote(results: &[PiResult]) -> VotedResult {
+    // The majority vote of compute_pi_voted over results of the same number of limbs. The guard
+    // limbs are voted on like the others, but not reported as disputed: different formulas have
+    // different truncation errors there even on perfect hardware, see analyze_precision.
+    let limbs = results[0].frac.len();
+    let mut frac = Vec::with_capacity(limbs);
+    let mut disputed = Vec::new();
+    let mut undecided = Vec::new();
+    for i in 0..limbs {
+        let values: Vec<Digit> = results.iter().map(|result| result.frac.digits[i]).collect();
+        let (value, decided) = majority(&values);
+        if i < limbs - GUARD_LIMBS && values.iter().any(|&v| v != value) {
+            disputed.push(i);
+            if !decided {
+                undecided.push(i);
+            }
+        }
+        frac.push(value);
+    }
+    let int_parts: Vec<i64> = results.iter().map(|result| result.int_part).collect();
+    VotedResult {
+        int_part: majority(&int_parts).0,
+        frac: Number::from_digits(&frac),
+        correct_digits: results.iter().map(|result| result.correct_digits).min().unwrap(),
+        disputed,
+        undecided,
+    }
+}
+
+// The continued fraction of pi - 3 as far as check_pi_convergent compares it, OEIS A001203
+const PI_CONTINUED_FRACTION: [Digit; 25] = [
+    7, 15, 1, 292, 1, 1, 1, 2, 1, 3, 1, 14, 2, 1, 1, 2, 2, 2, 2, 1, 84, 2, 1, 1, 15,
+];
+
+#[allow(dead_code)]
+pub fn check_pi_convergent(digits: usize, nthreads: usize, max_denominator: Digit)
+        -> Result<ConvergentCheck, String> {
+    // Compute pi and check its fractional part with check_convergent, whose coefficients must
+    // also be the known ones of pi as far as PI_CONTINUED_FRACTION goes. p/q agrees with pi in
+    // about log2(q q') bits, so digits must resolve these with some to spare. q fits into a
+    // Digit and the expansion ends at coefficients of 2^31, so 60 digits are always enough.
+    let pi = PiComputation::new().precision_digits(digits).threads(nthreads).build().run();
+    let check = pi.frac.check_convergent(max_denominator)?;
+    for (i, (&a, &expected)) in check.coefficients.iter().zip(&PI_CONTINUED_FRACTION).enumerate() {
+        if a != expected {
+            return Err(format!("Coefficient {} of pi - 3 is {} instead of {}", i + 1, a,
+                               expected));
+        }
+    }
+    Ok(check)
+}
+
+#[allow(dead_code)]
+pub fn compute_inv_pi(digits: usize, nthreads: usize) -> Number {
+    // 1/pi with at least the given number of correct decimal digits, which is in [0, 1) and so
+    // needs no integer part. This takes the reciprocal of compute_pi, whose error shrinks by a
+    // factor of pi^2 and gets one more unit of truncation, which the guard limb covers.
+    let pi = PiComputation::new().precision_digits(digits).threads(nthreads).build().run();
+    let (int_part, inv_pi) = pi.frac.reciprocal(pi.int_part as Digit);
+    assert_eq!(int_part, 0, "Integer part of 1/pi is wrong");
+    inv_pi
+}
+
+#[allow(dead_code)]
+pub fn compute_tau(digits: usize, nthreads: usize) -> Number {
+    // Fractional part of tau = 2pi, with at least the given number of correct decimal digits. Its
+    // integer part is 6. The coefficients of the formula are doubled instead of doubling pi, so
+    // combine_arctans takes care of the carry into the integer part.
+    let computation = PiComputation::new().precision_digits(digits).threads(nthreads).build();
+    let formula: Vec<(i64, Digit)> = computation.algo.formula().iter()
+        .map(|&(c, x)| (2*c, x))
+        .collect();
+    let (frac, int_part) =
+        evaluate_machin_like(&formula, false, computation.limbs, computation.threads);
+    assert_eq!(int_part, 6, "Integer part of tau is wrong");
+    frac
+}
+
+// ln(2) = 14 atanh(1/31) + 10 atanh(1/49) + 6 atanh(1/161), which needs about 20% fewer terms
+// than 2 atanh(1/3). Formulas with larger x need even fewer, but ataninv_threaded2_until needs
+// x^2 (2k+1) to fit into a Digit, which for u32 digits and x = 161 holds up to k = 82000.
+const LN2_FORMULA: [(i64, Digit); 3] = [(14, 31), (10, 49), (6, 161)];
+
+#[allow(dead_code)]
+pub fn compute_ln2(digits: usize, nthreads: usize) -> Number {
+    // ln(2) with at least the given number of correct decimal digits, which is below one
+    let limbs = limbs_for_precision(digits);
+    let (frac, int_part) = evaluate_machin_like(&LN2_FORMULA, true, limbs, nthreads);
+    assert_eq!(int_part, 0, "Integer part of ln(2) is wrong");
+    frac
+}
+
+#[allow(dead_code)]
+pub fn compute_e(digits: usize) -> Number {
+    // Fractional part of e with at least the given number of correct decimal digits, from
+    // e - 2 = sum over k >= 2 of 1/k!. Like in ataninv_scalar_observed, refterm lags behind: it
+    // is 1/m! for some m <= k, and 1/k! is added as refterm / ((m+1)...k) in a single pass until
+    // that product no longer fits into a Digit. By the invariant of div_assign, each term
+    // truncates by less than one unit in the last limb, which the guard limb covers.
+    let limbs = limbs_for_precision(digits);
+    let mut refterm = Number::from_inv_with_len(2, limbs);
+    let mut result = refterm.clone();
+    let mut k: Digit = 2;
+    // (m+1)...k
+    let mut lag: Digit = 1;
+    while !refterm.is_zero() {
+        k += 1;
+        match lag.checked_mul(k) {
+            Some(next) => lag = next,
+            None => {
+                refterm /= lag;
+                lag = k;
+            },
+        }
+        result.add_div_assign(&refterm, lag, false);
+    }
+    result
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum Constant {
+    Pi,
+    Tau,
+    InvPi,
+    Ln2,
+    E,
+}
+
+// Factor of the coefficients, formula and series of a Machin-like constant
+type ConstantFormula = (i64, &'static [(i64, Digit)], Series);
+
+impl Constant {
+    fn formula(self) -> Option<ConstantFormula> {
+        match self {
+            Constant::Pi | Constant::InvPi => Some((1, Algo::Machin.formula(), Series::Atan)),
+            Constant::Tau => Some((2, Algo::Machin.formula(), Series::Atan)),
+            Constant::Ln2 => Some((1, &LN2_FORMULA, Series::Atanh)),
+            Constant::E => None,
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub fn compute_constants(constants: &[Constant], digits: usize, nthreads: usize) -> Vec<Number> {
+    // The fractional parts of all constants with at least the given number of correct decimal
+    // digits, the same as the ones of compute_pi, compute_tau, compute_inv_pi, compute_ln2 and
+    // compute_e. The arctans of all formulas are computed in one batch, each x and series only
+    // once, so the nthreads workers are split among all of them by their number of terms like in
+    // evaluate_machin_like, and there is one driver thread per distinct arctan. e is summed on
+    // one more thread at the same time, without workers.
+    let limbs = limbs_for_precision(digits);
+    let mut terms: Vec<(Digit, Series)> = Vec::new();
+    for (_, formula, series) in constants.iter().filter_map(|constant| constant.formula()) {
+        for &(_, x) in formula {
+            if !terms.contains(&(x, series)) {
+                terms.push((x, series));
+            }
+        }
+    }
+    let (atans, e) = thread::scope(|scope| {
+        let e = constants.iter().any(|c| matches!(c, Constant::E))
+            .then(|| scope.spawn(|| compute_e(digits)));
+        let atans: Vec<Number> = if terms.is_empty() {
+            Vec::new()
+        } else {
+            ataninv_batch_series(&terms, limbs, nthreads, None, false, None)
+                .into_iter().map(|(atan, _, _, _)| atan).collect()
+        };
+        (atans, e.map(|handle| handle.join().unwrap()))
+    });
+    constants.iter().map(|&constant| {
+        let Some((factor, formula, series)) = constant.formula() else {
+            return e.clone().unwrap();
+        };
+        let scaled: Vec<(i64, Number)> = formula.iter()
+            .map(|&(c, x)| {
+                let i = terms.iter().position(|&term| term == (x, series)).unwrap();
+                (factor * c, atans[i].clone())
+            })
+            .collect();
+        let (frac, int_part) = combine_arctans(&scaled);
+        match constant {
+            Constant::InvPi => {
+                let (int_part, inv_pi) = frac.reciprocal(int_part as Digit);
+                assert_eq!(int_part, 0, "Integer part of 1/pi is wrong");
+                inv_pi
+            },
+            _ => frac,
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The first 60 decimals of each constant, from mpmath
+    const PI_DECIMALS: &str = "141592653589793238462643383279502884197169399375105820974944";
+    const TAU_DECIMALS: &str = "283185307179586476925286766559005768394338798750211641949889";
+    const INV_PI_DECIMALS: &str = "318309886183790671537767526745028724068919291480912897495334";
+    const E_DECIMALS: &str = "718281828459045235360287471352662497757247093699959574966967";
+    const LN2_DECIMALS: &str = "693147180559945309417232121458176568075500134360255254120680";
+
+    fn decimals(x: &Number, n: usize) -> String {
+        x.decimal_chunks().collect::<String>()[..n].to_string()
+    }
+
+    #[test]
+    fn compute_pi_adaptive_guarantees_digits() {
+        // Pi has no long run of nines or zeros this early, so the first attempt is enough
+        let reference = compute_pi(400, 0).leading_digits_decimal(300);
+        for digits in [1, 50, 300] {
+            let result = compute_pi_adaptive(digits, 2).unwrap();
+            assert_eq!(result.int_part, 3);
+            assert!(result.correct_digits >= digits, "{} digits", digits);
+            assert_eq!(result.frac.len(), limbs_for_digits(digits) + GUARD_LIMBS);
+            assert_eq!(result.frac.leading_digits_decimal(digits), reference[..digits]);
+        }
+    }
+
+    #[test]
+    fn compute_tau_is_twice_pi() {
+        for nthreads in [0, 2] {
+            let tau = compute_tau(60, nthreads);
+            assert_eq!(decimals(&tau, 60), TAU_DECIMALS);
+            let pi = compute_pi(60, nthreads);
+            assert_eq!(decimals(&pi, 60), PI_DECIMALS);
+            // 2 (pi - 3) = tau - 6, up to the truncation errors
+            let mut twice = pi.clone();
+            twice.wrapping_add_assign(&pi);
+            assert!(twice.agreement(&tau) >= tau.len() - 1);
+        }
+    }
+
+    #[test]
+    fn reciprocal_of_inv_pi_is_pi() {
+        for nthreads in [0, 2] {
+            let inv_pi = compute_inv_pi(60, nthreads);
+            assert_eq!(decimals(&inv_pi, 60), INV_PI_DECIMALS);
+            // Up to the guard limb, which absorbs the truncation errors of both reciprocals
+            let (int_part, pi) = inv_pi.reciprocal(0);
+            assert_eq!(int_part, 3);
+            assert!(pi.agreement(&compute_pi(60, nthreads)) >= pi.len() - GUARD_LIMBS);
+        }
+    }
+
+    #[test]
+    fn compute_constants_agrees_with_each_alone() {
+        use Constant::*;
+        // Pi twice and 1/pi share their arctans, ln(2) has its own, e none
+        let constants = [Pi, E, Ln2, Tau, InvPi, Pi];
+        for nthreads in [0, 1, 3] {
+            let batch = compute_constants(&constants, 60, nthreads);
+            let alone = [compute_pi(60, nthreads), compute_e(60), compute_ln2(60, nthreads),
+                         compute_tau(60, nthreads), compute_inv_pi(60, nthreads),
+                         compute_pi(60, nthreads)];
+            for (i, (b, a)) in batch.iter().zip(&alone).enumerate() {
+                assert_eq!(b.digits, a.digits, "constant {} with {} threads", i, nthreads);
+            }
+            let expected = [PI_DECIMALS, E_DECIMALS, LN2_DECIMALS, TAU_DECIMALS, INV_PI_DECIMALS,
+                            PI_DECIMALS];
+            for (b, expected) in batch.iter().zip(expected) {
+                assert_eq!(decimals(b, 60), expected);
+            }
+        }
+        assert!(compute_constants(&[], 60, 2).is_empty());
+        assert_eq!(compute_constants(&[E], 60, 2)[0].digits, compute_e(60).digits);
+    }
+
+    #[test]
+    fn callback_progress_increases() {
+        // A slow callback is called less often, but the result is the same. How many calls come
+        // before the last one depends on the speed, in a debug build there are usually some.
+        for (nthreads, delay) in [(0, 0), (2, 0), (2, 150)] {
+            let mut calls: Vec<Progress> = Vec::new();
+            let pi = compute_pi_with_callback(4000, nthreads, |progress| {
+                calls.push(progress);
+                thread::sleep(Duration::from_millis(delay));
+            });
+            assert_eq!(pi.digits, compute_pi(4000, nthreads).digits);
+            let last = calls.pop().unwrap();
+            assert_eq!(last.fraction, 1.0);
+            assert!(last.correct_digits >= 4000);
+            for pair in calls.windows(2) {
+                assert!(pair[0].fraction <= pair[1].fraction);
+                assert!(pair[0].correct_digits <= pair[1].correct_digits);
+            }
+            // The estimate of the last correct_digits may be higher than the one of the result
+            assert!(calls.iter().all(|progress| (0.0..=1.0).contains(&progress.fraction)));
+        }
+    }
+
+    #[test]
+    fn progress_follows_summed_terms() {
+        let computation = PiComputation::new().precision_digits(1000).build();
+        let (term_counts, error) = computation.error_bound();
+        let mut last = computation.progress(&[0, 0], &term_counts, error);
+        for k in 1..=term_counts[0] + 5 {
+            let summed = [k, k * term_counts[1] / term_counts[0]];
+            let progress = computation.progress(&summed, &term_counts, error);
+            assert!(progress.fraction >= last.fraction);
+            assert!(progress.correct_digits >= last.correct_digits);
+            last = progress;
+        }
+        assert_eq!(last.fraction, 1.0);
+        assert!(last.correct_digits >= 1000);
+    }
+
+    #[test]
+    fn voting_on_identical_results_finds_no_disagreement() {
+        let pi = compute_pi(300, 0);
+        for algos in [&[Algo::Machin][..], &[Algo::Machin; 3], &[Algo::Machin, Algo::Gauss]] {
+            let voted = compute_pi_voted(300, 0, algos);
+            assert_eq!((voted.int_part, voted.disputed, voted.undecided), (3, vec![], vec![]));
+            // Up to the guard limb, where the formulas may differ
+            assert!(voted.frac.agreement(&pi) >= pi.len() - GUARD_LIMBS);
+            assert!(voted.correct_digits >= 300);
+        }
+    }
+
+    #[test]
+    fn voting_outvotes_corrupted_limbs() {
+        let computation = PiComputation::new().precision_digits(100).build();
+        let result = || computation.run();
+        let corrupted = |limbs: &[usize], bit: u32| {
+            let mut result = result();
+            for &i in limbs {
+                result.frac.digits[i] ^= 1 << bit;
+            }
+            result
+        };
+        let pi = result().frac;
+        // One of three is wrong in limbs 0 and 2, and in the guard limb, which is not reported
+        let last = pi.len() - 1;
+        let voted = vote(&[result(), corrupted(&[0, 2, last], 5), result()]);
+        assert_eq!((voted.disputed, voted.undecided), (vec![0, 2], vec![]));
+        assert_eq!(voted.frac.digits, pi.digits);
+        // Two of three are wrong in the same way, so they outvote the right one
+        let voted = vote(&[corrupted(&[0, 1], 3), corrupted(&[0, 1], 3), result()]);
+        assert_eq!((voted.disputed, voted.undecided), (vec![0, 1], vec![]));
+        assert_eq!(voted.frac.digits[0], pi.digits[0] ^ 1 << 3);
+        // Two of three are wrong in different ways, so there is no majority and the first one is
+        // taken
+        let voted_wrong = vote(&[corrupted(&[1], 7), corrupted(&[1], 9), result()]);
+        assert_eq!((voted_wrong.disputed, voted_wrong.undecided), (vec![1], vec![1]));
+        assert_eq!(voted_wrong.frac.digits[1], pi.digits[1] ^ 1 << 7);
+    }
+
+    #[test]
+    fn pi_convergents_check_out() {
+        // 60 digits are enough for every bound that fits into a Digit
+        for bound in [1, 7, 113, 1 << 20, Digit::MAX] {
+            let check = check_pi_convergent(60, 0, bound).unwrap();
+            assert!(check.q <= bound);
+            assert!(check.coefficients.iter().zip(&PI_CONTINUED_FRACTION).all(|(a, b)| a == b));
+        }
+        // A single digit is too few for the largest bound with either digit size
+        let err = check_pi_convergent(1, 0, Digit::MAX).err().unwrap();
+        assert!(err.contains("are too few to check"), "{}", err);
+    }
+}
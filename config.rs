@@ -0,0 +1,184 @@
+/*
+ * Settings of a run of main, read from a file given with --config path and then overridden by the
+ * command line. The file has one "key = value" per line, empty lines and lines starting with #
+ * are ignored:
+ *
+ *     threads = 8               number of worker threads, like the first argument
+ *     digits = 100000           decimal digits, rounded up like precision_digits, default
+ *                               DIGITS limbs
+ *     algorithm = machin        machin or gauss, see computation::Algo
+ *     window = 16               like --window
+ *     overlap = true            like --overlap, true or false
+ *     check_digits = false      like --check-digits
+ *     timing = false            like --timing
+ *     output = pi.txt:decimal   like --output, may be given more than once
+ *     range = 760:780           like --range, print the decimal digits at these positions
+ *
+ * Each key except output may only be given once. On the command line, --digits and --algorithm
+ * set the same values as the keys, --digit n is the same as --range n:n, and if any --output is
+ * given, it replaces the outputs of the file instead of adding to them.
+ */
+use super::*;
+use super::computation::{Algo, limbs_for_precision};
+use std::fs;
+use std::str::FromStr;
+
+pub struct Config {
+    pub threads: Option<usize>,
+    pub limbs: usize,
+    pub algo: Algo,
+    pub max_window: Option<usize>,
+    pub overlap: bool,
+    pub check_digits: bool,
+    pub timing: bool,
+    pub outputs: Vec<OutputSpec>,
+    // First and last position of the decimal digits to print, counted from one
+    pub range: Option<(usize, usize)>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        // The behavior without a config file
+        Config {
+            threads: None,
+            limbs: DIGITS,
+            algo: Algo::Machin,
+            max_window: None,
+            overlap: false,
+            check_digits: false,
+            timing: false,
+            outputs: Vec::new(),
+            range: None,
+        }
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value '{}' for {}", value, key))
+}
+
+impl Config {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        // Set the value of one key of the file format
+        match key {
+            "threads" => self.threads = Some(parse(key, value)?),
+            "digits" => {
+                let digits: usize = parse(key, value)?;
+                if digits == 0 {
+                    return Err("digits must be positive".to_string());
+                }
+                self.limbs = limbs_for_precision(digits);
+            },
+            "algorithm" => self.algo = value.parse()?,
+            "window" => {
+                let terms: usize = parse(key, value)?;
+                if terms == 0 {
+                    return Err("window must be positive".to_string());
+                }
+                self.max_window = Some(terms);
+            },
+            "overlap" => self.overlap = parse(key, value)?,
+            "check_digits" => self.check_digits = parse(key, value)?,
+            "timing" => self.timing = parse(key, value)?,
+            "output" => self.outputs.push(value.parse()?),
+            "range" => {
+                let (first, last) = value.split_once(':')
+                    .ok_or_else(|| format!("range '{}' is not of the form first:last", value))?;
+                let (first, last): (usize, usize) = (parse(key, first)?, parse(key, last)?);
+                if first == 0 || first > last {
+                    return Err(format!("range {}:{} is empty or does not start at 1 or later",
+                                       first, last));
+                }
+                self.range = Some((first, last));
+            },
+            _ => return Err(format!("Unknown key '{}'", key)),
+        }
+        Ok(())
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Can not read config '{}': {}", path, err))?;
+        let mut config = Config::default();
+        let mut seen = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let at = |err: String| format!("{}, line {}: {}", path, i+1, err);
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| at(format!("'{}' is not of the form key = value", line)))?;
+            let (key, value) = (key.trim(), value.trim());
+            if key != "output" && seen.contains(&key) {
+                return Err(at(format!("{} is given more than once", key)));
+            }
+            seen.push(key);
+            config.set(key, value).map_err(at)?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_content(name: &str, content: &str) -> Result<Config, String> {
+        // Config::from_file on a file with the given content
+        let name = format!("picalc-config-{}-{}", std::process::id(), name);
+        let path = std::env::temp_dir().join(name);
+        let path = path.to_str().unwrap();
+        fs::write(path, content).unwrap();
+        let config = Config::from_file(path);
+        fs::remove_file(path).unwrap();
+        config.map_err(|err| err.replace(path, "config"))
+    }
+
+    #[test]
+    fn reads_all_keys() {
+        let config = from_content("all", "# comment\n\nthreads = 3\ndigits = 1000\n\
+                                   algorithm = gauss\nwindow = 16\noverlap = true\n\
+                                   check_digits = true\ntiming = true\noutput = a.txt:decimal\n\
+                                   output = b.txt:raw\nrange = 760:780\n").unwrap();
+        assert_eq!(config.threads, Some(3));
+        assert_eq!(config.limbs, limbs_for_precision(1000));
+        assert!(matches!(config.algo, Algo::Gauss));
+        assert_eq!(config.max_window, Some(16));
+        assert!(config.overlap && config.check_digits && config.timing);
+        assert_eq!(config.outputs.len(), 2);
+        assert_eq!(config.range, Some((760, 780)));
+    }
+
+    #[test]
+    fn rejects_unknown_key() {
+        assert_eq!(from_content("unknown", "threads = 2\nthread = 3\n").err().unwrap(),
+                   "config, line 2: Unknown key 'thread'");
+    }
+
+    #[test]
+    fn rejects_duplicate_key() {
+        assert_eq!(from_content("duplicate", "digits = 10\n# again\ndigits = 20\n").err().unwrap(),
+                   "config, line 3: digits is given more than once");
+        // Except for output, which may be given more than once
+        let outputs = "output = a.txt:decimal\noutput = a.txt:decimal\n";
+        assert_eq!(from_content("outputs", outputs).unwrap().outputs.len(), 2);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut config = Config::default();
+        assert_eq!(config.set("threads", "many").err().unwrap(),
+                   "Invalid value 'many' for threads");
+        assert!(config.set("digits", "0").is_err());
+        assert!(config.set("window", "0").is_err());
+        assert!(config.set("overlap", "yes").is_err());
+        assert!(config.set("algorithm", "leibniz").is_err());
+        assert!(config.set("range", "780").is_err());
+        assert!(config.set("range", "0:10").is_err());
+        assert!(config.set("range", "20:10").is_err());
+        assert_eq!(from_content("syntax", "threads 2\n").err().unwrap(),
+                   "config, line 1: 'threads 2' is not of the form key = value");
+        assert!(Config::from_file("/nonexistent/picalc.conf").is_err());
+    }
+}
@@ -0,0 +1,274 @@
+/*
+ * Continued fraction expansion and best rational approximations of a Number, interpreted as an
+ * unsigned fraction x in [0, 1) like in decimal.rs, so x = 1/(a1 + 1/(a2 + ...)).
+ *
+ * The coefficients are found with the Euclidean algorithm on the remainders r(-1) = 1, r(0) = x and
+ * r(k+1) = r(k-1) - a(k+1) r(k). All remainders are multiples of the last digit, so each step is
+ * exact, but it expands the stored value, not the value the Number approximates. For pi, both agree
+ * while the denominators of the convergents are well below 2^(bits/2).
+ *
+ * check_convergent turns this into a check of a Number and of the expansion itself: for each
+ * convergent p/q of x but the last, with q' the denominator of the next one,
+ *
+ *     1 / (q (q + q')) < |x - p/q| < 1 / (q q'),
+ *
+ * so p/q agrees with x in about log2(q q') bits, which is twice the bits of the denominator bound
+ * and more where the next coefficient is large. The bound must leave enough bits of x below that
+ * for the difference to be found from p/q as a Number of the same length.
+ */
+use super::*;
+use std::cmp::Ordering;
+
+// Bits of x that check_convergent needs below the ones in which p/q agrees with it
+const CHECK_BITS: f64 = 8.0;
+
+// Quotients are estimated in f64 and then corrected, which takes only a few steps as long as they
+// are far below 2^52, and they must fit into a u32 Digit. The expansion ends at a larger one.
+const MAX_QUOTIENT: f64 = (1u64 << 31) as f64;
+
+#[allow(dead_code)]
+pub struct ConvergentCheck {
+    // The last convergent p/q with q at most the bound, its coefficients a1, ..., and the
+    // denominator of the next one
+    pub p: Digit,
+    pub q: Digit,
+    pub coefficients: Vec<Digit>,
+    pub next_q: Double,
+    // -log2 |x - p/q| with p/q as a Number, and the bounds from the inequality at the top
+    pub error_bits: f64,
+    pub min_bits: f64,
+    pub max_bits: f64,
+}
+
+#[allow(dead_code)]
+pub struct ContinuedFraction<S: LimbStore> {
+    // Iterator over the coefficients a1, a2, ... of a Number. prev_int + prev is r(k-1), where
+    // prev_int is only non-zero for r(-1) = 1, and cur is r(k).
+    prev_int: Digit,
+    prev: Number<S>,
+    cur: Number<S>,
+}
+
+impl<S: LimbStore> Iterator for ContinuedFraction<S> {
+    type Item = Digit;
+
+    fn next(&mut self) -> Option<Digit> {
+        if self.cur.is_zero() {
+            return None;
+        }
+        let (a, rem) = self.cur.quotient(self.prev_int, &self.prev)?;
+        self.prev = std::mem::replace(&mut self.cur, rem);
+        self.prev_int = 0;
+        Some(a)
+    }
+}
+
+impl<S: LimbStore> Number<S> {
+    fn compare(&self, other: &Self) -> Ordering {
+        // Compare both values as unsigned fractions
+        let i = self.agreement(other);
+        if i == self.len() {
+            return Ordering::Equal;
+        }
+        self.digits.get(i).cmp(&other.digits.get(i))
+    }
+
+    fn leading(&self, pos: usize) -> f64 {
+        // Approximate value of the digits from pos on, scaled by 2^(Digit::BITS*(pos+1))
+        let weight = |i: usize| 2f64.powi(-((Digit::BITS as usize * (i-pos)) as i32));
+        (pos..min(pos + 4, self.len())).map(|i| self.digits.get(i) as f64 * weight(i)).sum()
+    }
+
+    fn quotient(&self, int: Digit, num: &Self) -> Option<(Digit, Self)> {
+        // Return floor((int + num) / self) and the remainder, where self is not zero and the
+        // remainder must stay below one. None if the quotient is MAX_QUOTIENT or more.
+        let pos = self.zeros.saturating_sub(1);
+        if num.zeros < pos {
+            return None;
+        }
+        let scale = 2f64.powi((Digit::BITS as usize * (pos+1)) as i32);
+        let estimate = ((int as f64 * scale + num.leading(pos)) / self.leading(pos)).floor();
+        if estimate.is_nan() || estimate >= MAX_QUOTIENT {
+            return None;
+        }
+        let mut a = estimate as Digit;
+        loop {
+            // rem = int + num - a*self, which is negative if there is anything left of int
+            let mut prod = self.clone();
+            let prod_int = prod.wrapping_mul_small(a);
+            let mut rem = num.clone();
+            let borrow = (num.compare(&prod) == Ordering::Less) as Digit;
+            rem.sub_assign(&prod);
+            if prod_int + borrow > int {
+                a -= 1;
+            } else if prod_int + borrow < int || rem.compare(self) != Ordering::Less {
+                a += 1;
+            } else {
+                return Some((a, rem));
+            }
+        }
+    }
+
+    pub fn continued_fraction(&self) -> ContinuedFraction<S> {
+        // Iterate over the coefficients a1, a2, ... This works on a copy of the Number.
+        ContinuedFraction {
+            prev_int: 1,
+            prev: Number { digits: S::zeroed(self.len()), zeros: self.len() },
+            cur: self.clone(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn to_continued_fraction(&self, max_terms: usize) -> Vec<Digit> {
+        // The first max_terms coefficients, or fewer if the expansion ends earlier
+        self.continued_fraction().take(max_terms).collect()
+    }
+
+    fn distance(&self, p: Digit, q: Digit) -> Self {
+        // |q*self - p|, which must be below one
+        let mut result = self.clone();
+        if result.wrapping_mul_small(q) != p {
+            result.negate();
+        }
+        result
+    }
+
+    fn closer(&self, (p1, q1): (Digit, Digit), (p2, q2): (Digit, Digit)) -> bool {
+        // Whether p1/q1 is strictly closer to self than p2/q2, by comparing |q1*self - p1| * q2
+        // with |q2*self - p2| * q1
+        let mut d1 = self.distance(p1, q1);
+        let mut d2 = self.distance(p2, q2);
+        let int1 = d1.wrapping_mul_small(q2);
+        let int2 = d2.wrapping_mul_small(q1);
+        (int1, d1.compare(&d2)) < (int2, Ordering::Equal)
+    }
+
+    #[allow(dead_code)]
+    pub fn best_rational(&self, max_denominator: Digit) -> (Digit, Digit) {
+        // The fraction p/q closest to self with 1 <= q <= max_denominator, preferring the smaller
+        // q if two are equally close. This is either a convergent of the continued fraction or a
+        // semiconvergent (p(k-1) + t p(k)) / (q(k-1) + t q(k)) with 0 < t < a(k+1).
+        let (mut p2, mut q2): (Double, Double) = (1, 0);
+        let (mut p1, mut q1): (Double, Double) = (0, 1);
+        for a in self.continued_fraction() {
+            let p = a as Double * p1 + p2;
+            let q = a as Double * q1 + q2;
+            if q > max_denominator as Double {
+                let t = (max_denominator as Double - q2) / q1;
+                let best = (p1 as Digit, q1 as Digit);
+                let semi = ((t * p1 + p2) as Digit, (t * q1 + q2) as Digit);
+                return if t > 0 && self.closer(semi, best) { semi } else { best };
+            }
+            (p2, q2) = (p1, q1);
+            (p1, q1) = (p, q);
+        }
+        (p1 as Digit, q1 as Digit)
+    }
+
+    pub fn check_convergent(&self, max_denominator: Digit) -> Result<ConvergentCheck, String> {
+        // Find the last convergent p/q with q <= max_denominator and check that its distance from
+        // self as a Number is within the bounds at the top. p/q is truncated to the length of
+        // self, which moves it by less than one unit in the last digit, less than 2^-CHECK_BITS
+        // of the distance, so the bounds get a margin of 0.01 bits.
+        let (mut p2, mut q2): (Double, Double) = (1, 0);
+        let (mut p1, mut q1): (Double, Double) = (0, 1);
+        let mut next_q = None;
+        let mut coefficients = Vec::new();
+        for a in self.continued_fraction() {
+            let q = a as Double * q1 + q2;
+            if q > max_denominator as Double {
+                next_q = Some(q);
+                break;
+            }
+            (p2, q2, p1, q1) = (p1, q1, a as Double * p1 + p2, q);
+            coefficients.push(a);
+        }
+        let Some(next_q) = next_q else {
+            return Err(format!("The expansion ends below the denominator {}", max_denominator));
+        };
+        let min_bits = (q1 as f64).log2() + (next_q as f64).log2();
+        let max_bits = (q1 as f64).log2() + ((q1 + next_q) as f64).log2();
+        let bits = (self.len() * Digit::BITS as usize) as f64;
+        if max_bits > bits - CHECK_BITS {
+            return Err(format!("{} digits are too few to check p/q with q' = {}", self.len(),
+                               next_q));
+        }
+        let (p, q) = (p1 as Digit, q1 as Digit);
+        let mut digits = vec![0; self.len()];
+        inv_digits(q, p, &mut digits);
+        let mut diff = self.clone();
+        diff.sub_assign(&Number::from_digits(&digits));
+        let error_bits = -diff.approx_log2();
+        if error_bits <= min_bits - 0.01 || error_bits >= max_bits + 0.01 {
+            return Err(format!("{}/{} is 2^-{:.2} away, not between 2^-{:.2} and 2^-{:.2}",
+                               p, q, error_bits, max_bits, min_bits));
+        }
+        Ok(ConvergentCheck { p, q, coefficients, next_q, error_bits, min_bits, max_bits })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pi_fraction(limbs: usize) -> Number {
+        let (pi, _) = evaluate_machin_like(&[(16, 5), (-4, 239)], false, limbs, 0);
+        pi
+    }
+
+    #[test]
+    fn continued_fraction_of_pi() {
+        let pi = pi_fraction(256 / Digit::BITS as usize);
+        assert_eq!(pi.to_continued_fraction(12), [7, 15, 1, 292, 1, 1, 1, 2, 1, 3, 1, 14]);
+        // 355/113 and 311/99, which is a semiconvergent between 22/7 and 333/106
+        assert_eq!(pi.best_rational(113), (16, 113));
+        assert_eq!(pi.best_rational(1000), (16, 113));
+        assert_eq!(pi.best_rational(100), (14, 99));
+        assert_eq!(pi.best_rational(7), (1, 7));
+        assert_eq!(pi.best_rational(1), (0, 1));
+    }
+
+    #[test]
+    fn continued_fraction_of_exact_fractions() {
+        // 3/8 = 1/(2 + 1/(1 + 1/2)) ends after three coefficients
+        let three_eighths: Number = Number::from_digits(&[3 << (Digit::BITS - 3), 0]);
+        assert_eq!(three_eighths.to_continued_fraction(10), [2, 1, 2]);
+        assert_eq!(three_eighths.best_rational(8), (3, 8));
+        assert_eq!(three_eighths.best_rational(1000), (3, 8));
+        // The closest with q <= 3 are 1/3 and 1/2, 1/24 and 1/8 away
+        assert_eq!(three_eighths.best_rational(3), (1, 3));
+        let zero: Number = Number::zero_with_len(2);
+        assert!(zero.to_continued_fraction(5).is_empty());
+    }
+
+    #[test]
+    fn check_convergent_of_pi() {
+        let pi = pi_fraction(256 / Digit::BITS as usize);
+        // 16/113 is followed by 4687/33102, so it agrees with pi - 3 in about log2(113 * 33102)
+        let check = pi.check_convergent(113).unwrap();
+        assert_eq!((check.p, check.q, check.next_q), (16, 113, 33102));
+        assert_eq!(check.coefficients, [7, 15, 1]);
+        assert!(check.min_bits < check.error_bits && check.error_bits < check.max_bits);
+        assert!((check.min_bits - (113f64 * 33102.0).log2()).abs() < 1e-9);
+        // Larger bounds give later convergents that agree in more bits
+        let mut bits = check.error_bits;
+        for bound in [1000, 33102, 1 << 20, 1 << 30] {
+            let check = pi.check_convergent(bound).unwrap();
+            assert!(check.q <= bound && check.next_q > bound as Double);
+            assert!(check.error_bits >= bits, "bound {}", bound);
+            bits = check.error_bits;
+        }
+    }
+
+    #[test]
+    fn check_convergent_rejects_unresolved_and_finite_expansions() {
+        // 64 bits can not resolve a convergent that agrees in about 60 bits
+        let pi = pi_fraction(64 / Digit::BITS as usize);
+        assert!(pi.check_convergent(1 << 30).err().unwrap().contains("are too few to check"));
+        assert!(pi.check_convergent(113).is_ok());
+        // The expansion of 3/8 ends before the bound
+        let three_eighths: Number = Number::from_digits(&[3 << (Digit::BITS - 3), 0]);
+        assert_eq!(three_eighths.check_convergent(100).err().unwrap(),
+                   "The expansion ends below the denominator 100");
+    }
+}
@@ -0,0 +1,461 @@
+/*
+ * Conversion of Numbers to decimal. The digits are interpreted as an unsigned fraction in [0, 1),
+ * which is what is left of pi after main drops the integer part. Decimal digits are produced by
+ * repeatedly multiplying by DEC_CHUNK and taking the integer part that overflows, which yields
+ * DEC_CHUNK_DIGITS decimal digits per pass over the Number.
+ */
+use super::*;
+use super::ramanujan::mul_big;
+use std::io::{self, Write};
+
+// Largest power of ten that fits into a Digit
+pub const DEC_CHUNK_DIGITS: usize = Digit::MAX.ilog10() as usize;
+pub const DEC_CHUNK: Digit = (10 as Digit).pow(DEC_CHUNK_DIGITS as u32);
+
+pub struct DecimalChunks<S: LimbStore> {
+    // Iterator over the decimal digits of a Number, DEC_CHUNK_DIGITS at a time. Only the last
+    // chunk may be shorter.
+    x: Number<S>,
+    // Remaining digits
+    remaining: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LastDigitInfo {
+    // How the last digit of to_decimal_string relates to the value: it has no further digits, the
+    // ones that were dropped are below half a unit of the last digit, or they are above, so the
+    // last digit was increased. A tie is rounded to an even last digit.
+    Exact,
+    RoundedDown,
+    RoundedUp,
+}
+
+impl<S: LimbStore> Iterator for DecimalChunks<S> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let len = min(DEC_CHUNK_DIGITS, self.remaining);
+        self.remaining -= len;
+        let mut digits = format!("{:01$}", self.x.wrapping_mul_small(DEC_CHUNK), DEC_CHUNK_DIGITS);
+        digits.truncate(len);
+        Some(digits)
+    }
+}
+
+impl<S: LimbStore> Number<S> {
+    pub fn add_int_div(&mut self, int: Digit, d: Digit) {
+        // self = (int + self) / d, where int < d so the result stays below one.
+        let d = d as Double;
+        let mut rem = int as Double;
+        for i in 0..self.len() {
+            let num = (rem << Digit::BITS) + self.digits.get(i) as Double;
+            self.digits.set(i, (num / d) as Digit);
+            rem = num % d;
+        }
+        self.update_zeros();
+    }
+
+    pub fn from_decimal_string(s: &str) -> Option<Self> {
+        // Parse a string of the form [-]0.ddd into a Number, truncating digits beyond the
+        // precision. Returns None if the string is malformed or the value is outside of the
+        // representable range [-0.5, 0.5).
+        let (neg, s) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let digits = s.strip_prefix("0.")?.as_bytes();
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        // 0.d1d2... = (c1 + (c2 + ...) / 10^len2) / 10^len1, where the chunks ci have at most
+        // DEC_CHUNK_DIGITS digits. So start with the last chunk.
+        let mut result = Self::zero();
+        for chunk in digits.chunks(DEC_CHUNK_DIGITS).rev() {
+            let int = chunk.iter().fold(0, |acc, d| acc * 10 + (d - b'0') as Digit);
+            result.add_int_div(int, (10 as Digit).pow(chunk.len() as u32));
+        }
+        // The top bit must not be set, except for exactly -0.5.
+        let top = result.digits.get(0) >> (Digit::BITS - 1);
+        let half = top == 1 && result.digits.get(0) << 1 == 0
+            && (1..result.len()).all(|i| result.digits.get(i) == 0);
+        if top == 1 && !(neg && half) {
+            return None;
+        }
+        if neg {
+            let mut negated = Self::zero();
+            negated.sub_assign(&result);
+            result = negated;
+        }
+        Some(result)
+    }
+
+    #[allow(dead_code)]
+    pub fn matches_decimal(&self, s: &str, tolerance_limbs: usize) -> bool {
+        // Check that the value agrees with the decimal string s in all but the last
+        // tolerance_limbs digits. If s has fewer decimal digits than the precision, only the limbs
+        // that are fully determined by s are compared.
+        let expected = match Self::from_decimal_string(s) {
+            Some(x) => x,
+            None => return false,
+        };
+        let decimals = s.len() - s.find('.').unwrap() - 1;
+        let limbs = (decimals as f64 * 10f64.log2()) as usize / Digit::BITS as usize;
+        self.agreement(&expected) + tolerance_limbs >= min(self.len(), limbs)
+    }
+
+    pub fn decimal_digits(&self) -> usize {
+        // Number of decimal digits resolved by the binary precision, i.e. the largest n with
+        // 10^-n >= 2^-bits.
+        (self.len() as f64 * Digit::BITS as f64 * 2f64.log10()) as usize
+    }
+
+    pub fn decimal_chunks(&self) -> DecimalChunks<S> {
+        // Iterate over the decimal_digits() decimal digits. This works on a copy of the Number.
+        DecimalChunks {
+            x: self.clone(),
+            remaining: self.decimal_digits(),
+        }
+    }
+
+    pub fn decimal_agreement(&self, other: &Self) -> usize {
+        // Number of leading decimal_digits() decimal digits that are equal in both Numbers. other
+        // may have a higher precision, then only its first decimal digits are compared.
+        let mut count = 0;
+        for (a, b) in self.decimal_chunks().zip(other.decimal_chunks()) {
+            let equal = a.bytes().zip(b.bytes()).take_while(|(a, b)| a == b).count();
+            count += equal;
+            if equal < a.len() {
+                break;
+            }
+        }
+        count
+    }
+
+    pub fn write_decimal(&self, w: &mut impl Write) -> io::Result<()> {
+        // Write the decimal digits without holding all of them in memory.
+        for chunk in self.decimal_chunks() {
+            w.write_all(chunk.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn to_decimal_string(&self, digits: usize) -> (String, LastDigitInfo) {
+        // The first digits decimal digits, correctly rounded, and how the last one was rounded.
+        // The truncated digits are the ones of decimal_chunks, they differ from the rounded ones
+        // only for RoundedUp. The last chunk is multiplied by a smaller power of ten, so the
+        // remainder after the last digit is left in x. Rounding up all nines wraps around to
+        // zeros, like the value modulo one.
+        assert!(digits <= self.decimal_digits(), "Only {} decimal digits are resolved",
+                self.decimal_digits());
+        let mut x = self.clone();
+        let mut result = String::with_capacity(digits);
+        let mut remaining = digits;
+        while remaining > 0 {
+            let len = min(DEC_CHUNK_DIGITS, remaining);
+            remaining -= len;
+            let chunk = x.wrapping_mul_small((10 as Digit).pow(len as u32));
+            result.push_str(&format!("{:01$}", chunk, len));
+        }
+        // mul_small does not update zeros when the leading digit becomes zero, so scan the digits
+        let low_zero = (1..x.len()).all(|i| x.digits.get(i) == 0);
+        let top = x.digits.get(0) >> (Digit::BITS - 1);
+        let tie = top == 1 && x.digits.get(0) << 1 == 0 && low_zero;
+        let odd = result.bytes().last().is_some_and(|d| (d - b'0') % 2 == 1);
+        let info = if x.digits.get(0) == 0 && low_zero {
+            LastDigitInfo::Exact
+        } else if top == 1 && (!tie || odd) {
+            LastDigitInfo::RoundedUp
+        } else {
+            LastDigitInfo::RoundedDown
+        };
+        if info == LastDigitInfo::RoundedUp {
+            let mut bytes = result.into_bytes();
+            for d in bytes.iter_mut().rev() {
+                if *d == b'9' {
+                    *d = b'0';
+                } else {
+                    *d += 1;
+                    break;
+                }
+            }
+            result = String::from_utf8(bytes).unwrap();
+        }
+        (result, info)
+    }
+
+    #[allow(dead_code)]
+    pub fn decimal_window(&self, start: usize, len: usize) -> String {
+        // The len decimal digits after the first start ones, the same as the ones of
+        // decimal_chunks there. Instead of one pass over the Number per DEC_CHUNK_DIGITS skipped
+        // digits, the value is multiplied by 10^start at once and the integer part is dropped,
+        // which is exact. 10^start is found by repeated squaring with mul_big of ramanujan.rs.
+        // Only the products of digits that end up below the binary point are computed, but with
+        // about start / DEC_CHUNK_DIGITS digits in the power, that is as many as the passes have.
+        // So this is not faster than decimal_range: for 20000 limbs, 100 digits starting at
+        // 190,010 take 0.24 s here and 0.20 s there. It needs subquadratic multiplication.
+        assert!(start + len <= self.decimal_digits(), "Digits {} to {} are not within the {} \
+                decimal digits", start + 1, start + len, self.decimal_digits());
+        let mut power = vec![1];
+        let mut base = vec![10];
+        let mut exp = start;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                power = mul_big(&power, &base);
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = mul_big(&base, &base);
+            }
+        }
+        // Digit i of self times digit j of the power, least significant first, is at digit i - j
+        // below the binary point, the ones with j > i are integer part. The sums are split into
+        // low and high digits like in combine_scaled.
+        let n = self.len();
+        let mut x = Self::zero_with_len(n);
+        let mut carry: Double = 0;
+        for r in (0..n).rev() {
+            let mut low = carry;
+            let mut high: Double = 0;
+            for (j, &p) in power.iter().enumerate().take(n - r) {
+                let product = self.digits.get(r + j) as Double * p as Double;
+                low += product as Digit as Double;
+                high += product >> Digit::BITS;
+            }
+            x.digits.set(r, low as Digit);
+            carry = (low >> Digit::BITS) + high;
+        }
+        x.update_zeros();
+        let mut result = String::with_capacity(len);
+        let mut remaining = len;
+        while remaining > 0 {
+            let len = min(DEC_CHUNK_DIGITS, remaining);
+            remaining -= len;
+            let chunk = x.wrapping_mul_small((10 as Digit).pow(len as u32));
+            result.push_str(&format!("{:01$}", chunk, len));
+        }
+        result
+    }
+
+    pub fn decimal_range(&self, first: usize, last: usize) -> String {
+        // The decimal digits at the positions first to last after the decimal point, counted
+        // from one, so 762 to 767 are the six nines of pi at the Feynman point. Each decimal digit
+        // depends on all digits of the Number, so the ones before first are still converted, with
+        // one pass over the Number per DEC_CHUNK_DIGITS digits, but nothing after last. Only
+        // binary or hexadecimal digits of pi could be computed at a position without the ones
+        // before it, with the BBP formula, which is not implemented here.
+        assert!(1 <= first && first <= last && last <= self.decimal_digits(),
+                "Digits {} to {} are not within the {} decimal digits", first, last,
+                self.decimal_digits());
+        let mut result = String::with_capacity(last - first + 1);
+        let mut pos = 0;
+        for chunk in self.decimal_chunks() {
+            if pos >= last {
+                break;
+            }
+            let start = (first - 1).saturating_sub(pos).min(chunk.len());
+            let end = (last - pos).min(chunk.len());
+            result.push_str(&chunk[start..end]);
+            pos += chunk.len();
+        }
+        result
+    }
+
+    pub fn leading_digits_decimal(&self, n: usize) -> String {
+        // The first n decimal digits, the same as the ones of decimal_chunks, from only as many
+        // leading limbs as needed. The value is between the one truncated to these limbs and that
+        // plus one unit in their last limb, so where the digits of both agree, the other limbs
+        // can not change them. The first try resolves two more digits than n, and the limbs are
+        // only doubled if those are not enough, which needs a run of nines or zeros after the
+        // first n digits.
+        assert!(n <= self.decimal_digits(), "Only {} decimal digits are resolved",
+                self.decimal_digits());
+        let leading = |x: &Number| -> String {
+            x.decimal_chunks().flat_map(String::into_bytes).take(n).map(char::from).collect()
+        };
+        let mut limbs = ((n + 2) as f64 / (Digit::BITS as f64 * 2f64.log10())).ceil() as usize;
+        loop {
+            limbs = limbs.clamp(1, self.len());
+            let digits: Vec<Digit> = (0..limbs).map(|i| self.digits.get(i)).collect();
+            let lower: Number = Number::from_digits(&digits);
+            let result = leading(&lower);
+            if limbs == self.len() {
+                return result;
+            }
+            let mut unit = vec![0; limbs];
+            unit[limbs - 1] = 1;
+            let mut upper = lower.clone();
+            upper.wrapping_add_assign(&Number::from_digits(&unit));
+            // If upper wraps around to zero, its digits differ from all nines
+            if leading(&upper) == result {
+                return result;
+            }
+            limbs *= 2;
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn decimal_tail(&self, count: usize) -> String {
+        // Return the last count of decimal_digits() decimal digits. The low digits depend on the
+        // whole value, so this still needs one multiplication pass per DEC_CHUNK_DIGITS digits
+        // over the full Number, but only the tail is formatted.
+        let skip = self.decimal_digits().saturating_sub(count);
+        let mut result = String::new();
+        let mut pos = 0;
+        for chunk in self.decimal_chunks() {
+            if pos + chunk.len() > skip {
+                result.push_str(&chunk[skip.saturating_sub(pos)..]);
+            }
+            pos += chunk.len();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_digits(x: &Number) -> String {
+        x.decimal_chunks().collect()
+    }
+
+    #[test]
+    fn decimal_tail_is_suffix_of_all_digits() {
+        for limbs in 1..=4 {
+            let x: Number = Number::from_inv_with_len(7, limbs);
+            let digits = all_digits(&x);
+            assert_eq!(digits.len(), x.decimal_digits());
+            for count in 0..=digits.len() + 2 {
+                let start = digits.len().saturating_sub(count);
+                assert_eq!(x.decimal_tail(count), digits[start..], "{} limbs", limbs);
+            }
+        }
+        // 1/4 is exact, so all digits after 25 are zero
+        let quarter = Number::from_digits(&[1 << (Digit::BITS - 2), 0]);
+        assert_eq!(quarter.decimal_tail(3), "000");
+        assert!(all_digits(&quarter).starts_with("25000"));
+    }
+
+    #[test]
+    fn matches_decimal_compares_determined_limbs() {
+        let x: Number = Number::from_inv_with_len(7, 3);
+        let s = format!("0.{}", all_digits(&x));
+        assert!(x.matches_decimal(&s, 0));
+        // Also with only the first 20 digits, which determine fewer limbs
+        assert!(x.matches_decimal(&s[..22], 0));
+        // The digits determine all limbs but the last, so a difference in the middle one needs a
+        // tolerance of one limb
+        let mut y = x.clone();
+        y.digits[1] ^= 1 << (Digit::BITS - 1);
+        let wrong = format!("0.{}", all_digits(&y));
+        assert!(!x.matches_decimal(&wrong, 0));
+        assert!(x.matches_decimal(&wrong, 1));
+        assert!(!x.matches_decimal("0.1x2", 0));
+        assert!(!x.matches_decimal("1.42", 0));
+    }
+
+    #[test]
+    fn to_decimal_string_reports_last_digit() {
+        use LastDigitInfo::*;
+        let x = |digits: &[Digit]| -> Number { Number::from_digits(digits) };
+        let top = |bits: Digit| bits << (Digit::BITS - 4);
+        // 1/4 and 3/8 end within the digits
+        assert_eq!(x(&[top(0b0100), 0]).to_decimal_string(2), ("25".to_string(), Exact));
+        assert_eq!(x(&[top(0b0110), 0]).to_decimal_string(4), ("3750".to_string(), Exact));
+        // 1/3 and 2/3 are rounded down and up
+        let third = x(&[Digit::MAX / 3; 2]);
+        assert_eq!(third.to_decimal_string(5), ("33333".to_string(), RoundedDown));
+        let two_thirds = x(&[Digit::MAX / 3 * 2; 2]);
+        assert_eq!(two_thirds.to_decimal_string(5), ("66667".to_string(), RoundedUp));
+        // 1/8 and 3/8 are ties, rounded to an even last digit
+        assert_eq!(x(&[top(0b0010), 0]).to_decimal_string(2), ("12".to_string(), RoundedDown));
+        assert_eq!(x(&[top(0b0110), 0]).to_decimal_string(2), ("38".to_string(), RoundedUp));
+        // Rounding up many nines carries through all of them and wraps around
+        let below_one = x(&[Digit::MAX; 2]);
+        assert_eq!(below_one.to_decimal_string(3), ("000".to_string(), RoundedUp));
+        assert_eq!(x(&[0, 0]).to_decimal_string(0), (String::new(), Exact));
+        // The truncated digits are the ones of decimal_chunks
+        let digits = all_digits(&third);
+        assert_eq!(third.to_decimal_string(digits.len()).0, digits);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only 19 decimal digits are resolved")]
+    fn to_decimal_string_rejects_unresolved_digits() {
+        // 64 bits resolve 19 decimal digits
+        let x: Number = Number::from_digits(&[1; 64 / Digit::BITS as usize]);
+        x.to_decimal_string(x.decimal_digits() + 1);
+    }
+
+    #[test]
+    fn decimal_string_round_trips() {
+        // A Number with one or two limbs through all of its decimals and back, which are
+        // truncated twice, so rounding them gives the same decimals
+        let mut seed: u64 = 0xa54ff53a5f1d36f1;
+        for limbs in 1..=2 {
+            for _ in 0..50 {
+                let digits: Vec<Digit> = (0..limbs).map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    seed as Digit
+                }).collect();
+                let mut x: Number = Number::from_digits(&digits);
+                x.digits[0] >>= 1;
+                let decimals = all_digits(&x);
+                let s = format!("0.{}", decimals);
+                let parsed: Number = Number::from_decimal_string(&s).unwrap();
+                assert_eq!(parsed.to_decimal_string(decimals.len()).0, decimals);
+            }
+        }
+        // Strings that the binary digits represent exactly. Negative ones are stored as
+        // 1 - |value| like after negate, so they come back after negating.
+        for s in ["0.25", "0.375", "0.0625", "0.4990234375", "-0.25", "-0.5"] {
+            let mut x: Number = Number::from_decimal_string(s).unwrap();
+            let abs = s.trim_start_matches('-');
+            if s.starts_with('-') {
+                let mut negated = Number::zero();
+                negated.sub_assign(&x);
+                x = negated;
+            }
+            let (digits, info) = x.to_decimal_string(abs.len() - 2);
+            assert_eq!((format!("0.{}", digits), info), (abs.to_string(), LastDigitInfo::Exact));
+        }
+    }
+
+    #[test]
+    fn from_decimal_string_rejects_malformed_and_out_of_range() {
+        for s in ["", "0.", ".5", "1.25", "0.1a", "0.-1", "--0.1", "0.5", "0.75", "-0.50001",
+                  "-0.6"] {
+            assert!(Number::<Vec<Digit>>::from_decimal_string(s).is_none(), "{}", s);
+        }
+        assert!(Number::<Vec<Digit>>::from_decimal_string("0.4999").is_some());
+    }
+
+    #[test]
+    fn decimal_window_is_slice_of_pi() {
+        let pi = computation::compute_pi(850, 0);
+        // Decimals 751 to 790 of pi from mpmath, around the Feynman point
+        assert_eq!(pi.decimal_window(750, 40), "5187072113499999983729780499510597317328");
+        assert_eq!(pi.decimal_window(761, 6), pi.decimal_range(762, 767));
+        assert_eq!(pi.decimal_window(761, 6), "999999");
+        let digits = all_digits(&pi);
+        for (start, len) in [(0, 0), (0, 30), (1, 1), (18, 3), (19, 40), (500, 77), (512, 1)] {
+            assert_eq!(pi.decimal_window(start, len), digits[start..start + len],
+                       "{} digits from {}", len, start);
+        }
+        let end = digits.len();
+        assert_eq!(pi.decimal_window(end - 5, 5), digits[end - 5..]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Digits 19 to 20 are not within the 19 decimal digits")]
+    fn decimal_window_rejects_unresolved_digits() {
+        let x: Number = Number::from_inv_with_len(7, 64 / Digit::BITS as usize);
+        x.decimal_window(18, 2);
+    }
+}
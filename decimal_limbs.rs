@@ -0,0 +1,206 @@
+/*
+ * Experimental fixed point numbers with digits of base DEC_CHUNK, the largest power of ten that
+ * fits into a Digit (10^19 for u64 digits), instead of 2^Digit::BITS. The decimal digits can then
+ * be written directly, while converting a Number needs one multiplication pass over all digits
+ * per DEC_CHUNK_DIGITS decimal digits. In exchange, the arithmetic can not use the carry of the
+ * Double and has to reduce by DEC_CHUNK, and each digit holds about 1.2% fewer bits of
+ * information, so more digits are needed for the same precision.
+ *
+ * This does not pay off: the conversion takes about 1% of the total time of the binary version,
+ * while computing 385,000 digits with compute_pi_dec takes about 30% longer than with
+ * ataninv_scalar_until and combine_arctans.
+ *
+ * Only what computing pi with Machin's formula needs is implemented, for positive values in
+ * [0, 1) and without LimbStore or threading.
+ */
+use super::*;
+use super::decimal::{DEC_CHUNK, DEC_CHUNK_DIGITS};
+
+const BASE: Double = DEC_CHUNK as Double;
+
+#[derive(Clone)]
+pub struct DecNumber {
+    zeros: usize, // At least the first N digits are zeros
+    digits: Vec<Digit>,
+}
+
+impl DecNumber {
+    pub fn zero(len: usize) -> Self {
+        DecNumber { zeros: len, digits: vec![0; len] }
+    }
+
+    pub fn len(&self) -> usize {
+        self.digits.len()
+    }
+
+    pub fn from_inv(x: Digit, len: usize) -> Self {
+        // 1/x by long division in base DEC_CHUNK
+        let mut result = Self::zero(len);
+        let x = x as Double;
+        let mut rem: Double = 1;
+        for digit in &mut result.digits {
+            let num = rem * BASE;
+            *digit = (num / x) as Digit;
+            rem = num % x;
+        }
+        result.update_zeros_min(0);
+        result
+    }
+
+    fn update_zeros_min(&mut self, min: usize) {
+        self.zeros = (min..self.len()).find(|&i| self.digits[i] != 0).unwrap_or(self.len());
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.zeros == self.len()
+    }
+
+    pub fn set_to_div(&mut self, x: &Self, d: Digit) {
+        // self = x / d
+        let d = d as Double;
+        let mut rem: Double = 0;
+        for i in self.zeros..x.zeros {
+            self.digits[i] = 0;
+        }
+        for i in x.zeros..self.len() {
+            let num = rem * BASE + x.digits[i] as Double;
+            self.digits[i] = (num / d) as Digit;
+            rem = num % d;
+        }
+        self.update_zeros_min(x.zeros);
+    }
+
+    pub fn div_assign(&mut self, d: Digit) {
+        // self /= d
+        let d = d as Double;
+        let mut rem: Double = 0;
+        for i in self.zeros..self.len() {
+            let num = rem * BASE + self.digits[i] as Double;
+            self.digits[i] = (num / d) as Digit;
+            rem = num % d;
+        }
+        self.update_zeros_min(self.zeros);
+    }
+
+    pub fn add_assign(&mut self, rhs: &Self) {
+        // self += rhs, modulo one
+        let mut carry = 0;
+        let mut i = self.len();
+        while i > rhs.zeros || (carry != 0 && i > 0) {
+            i -= 1;
+            let res = self.digits[i] as Double + rhs.digits[i] as Double + carry;
+            carry = (res >= BASE) as Double;
+            self.digits[i] = (res - carry * BASE) as Digit;
+        }
+        self.update_zeros_min(min(self.zeros, i));
+    }
+
+    pub fn sub_assign(&mut self, rhs: &Self) -> bool {
+        // self -= rhs, modulo one. Returns whether a borrow went beyond the first digit, which
+        // means the result wrapped around.
+        let mut borrow = 0;
+        let mut i = self.len();
+        while i > rhs.zeros || (borrow != 0 && i > 0) {
+            i -= 1;
+            let sub = rhs.digits[i] as Double + borrow;
+            let digit = self.digits[i] as Double;
+            borrow = (digit < sub) as Double;
+            self.digits[i] = (digit + borrow * BASE - sub) as Digit;
+        }
+        self.update_zeros_min(min(self.zeros, i));
+        borrow != 0
+    }
+
+    pub fn mul_small(&mut self, m: Digit) -> Digit {
+        // Multiply by m modulo one and return the integer part that overflows
+        let mut carry: Double = 0;
+        for digit in self.digits.iter_mut().rev() {
+            carry += m as Double * *digit as Double;
+            *digit = (carry % BASE) as Digit;
+            carry /= BASE;
+        }
+        self.update_zeros_min(0);
+        carry as Digit
+    }
+
+    pub fn write_decimal(&self, w: &mut impl Write) -> io::Result<()> {
+        // The decimal digits are the digits, each padded to DEC_CHUNK_DIGITS
+        for digit in &self.digits {
+            write!(w, "{:01$}", digit, DEC_CHUNK_DIGITS)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn ataninv_dec(x: Digit, len: usize) -> DecNumber {
+    // Same as ataninv_scalar_until for atan, including the lagging refterm
+    let x2 = x*x;
+    let mut result = DecNumber::from_inv(x, len);
+    let mut refterm = result.clone();
+    let mut tmp = DecNumber::zero(len);
+    let mut denom: Digit = 1;
+    let mut stepsize: Digit = 1;
+    let mut neg = true;
+    while !refterm.is_zero() {
+        denom += 2;
+        if stepsize.checked_mul(x2).is_none() {
+            refterm.div_assign(stepsize);
+            stepsize = 1;
+        }
+        stepsize *= x2;
+        let mut divisor = denom as Double * stepsize as Double;
+        if divisor > Digit::MAX.into() {
+            refterm.div_assign(stepsize);
+            stepsize = 1;
+            divisor = denom as Double;
+        }
+        tmp.set_to_div(&refterm, divisor as Digit);
+        if neg {
+            result.sub_assign(&tmp);
+        } else {
+            result.add_assign(&tmp);
+        }
+        neg = !neg;
+    }
+    result
+}
+
+pub fn compute_pi_dec(len: usize) -> (Digit, DecNumber) {
+    // pi = 16atan(1/5) - 4atan(1/239) with len digits, as integer and fractional part
+    let mut pi = ataninv_dec(5, len);
+    let mut atan239 = ataninv_dec(239, len);
+    let int5 = pi.mul_small(16);
+    let int239 = atan239.mul_small(4);
+    let borrow = pi.sub_assign(&atan239);
+    (int5 - int239 - borrow as Digit, pi)
+}
+
+pub fn digits_for_bits(bits: usize) -> usize {
+    // Number of DecNumber digits with at least the given binary precision
+    (bits as f64 / (DEC_CHUNK as f64).log2()).ceil() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimals(x: &DecNumber) -> String {
+        let mut digits = Vec::new();
+        x.write_decimal(&mut digits).unwrap();
+        String::from_utf8(digits).unwrap()
+    }
+
+    #[test]
+    fn compute_pi_dec_gives_pi() {
+        let len = digits_for_bits(256);
+        let (int_part, pi) = compute_pi_dec(len);
+        assert_eq!(int_part, 3);
+        assert_eq!(decimals(&pi).len(), len * DEC_CHUNK_DIGITS);
+        assert!(decimals(&pi).starts_with("14159265358979323846264338327950288419716939937510"));
+        // The same digits as with binary limbs, as far as those resolve them
+        let (binary, _) = ataninv_scalar_until(5, 256 / Digit::BITS as usize, Series::Atan, None);
+        let binary: String = binary.decimal_chunks().collect();
+        let decimal = decimals(&ataninv_dec(5, len));
+        assert_eq!(decimal[..binary.len() - 3], binary[..binary.len() - 3]);
+    }
+}
@@ -0,0 +1,220 @@
+/*
+ * Computation of atan(1/x) distributed over processes, possibly on other machines, using
+ * ataninv_range. A coordinator splits the terms into consecutive ranges with split_ranges and
+ * sends each worker one request line
+ *
+ *     picalc range x=5 start=0 end=4000 limbs=20000 digit_bits=64
+ *
+ * which the worker answers with compute_range_request: the same line with "partial" instead of
+ * "range", followed by the digits of the partial sum like the raw output format. The coordinator
+ * reads the answers with read_partial and adds them up with combine_partials, which gives the
+ * digits of ataninv_scalar_until since every term is truncated the same way in any range.
+ *
+ * The crate has no network code, the requests and partial sums go through files or pipes, for
+ * example with
+ *
+ *     ssh worker picalc range-worker < request > partial
+ *
+ * which runs compute_range_request on stdin and stdout. The coordinator side, split_ranges and
+ * combine_partials, is only a library API.
+ */
+use super::*;
+use std::io::BufRead;
+
+const REQUEST: &str = "picalc range";
+const PARTIAL: &str = "picalc partial";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangeRequest {
+    // The terms start_term <= k < end_term of atan(1/x) with limbs digits
+    pub x: Digit,
+    pub start_term: usize,
+    pub end_term: usize,
+    pub limbs: usize,
+}
+
+impl RangeRequest {
+    fn write_header(&self, w: &mut impl Write, magic: &str) -> io::Result<()> {
+        writeln!(w, "{} x={} start={} end={} limbs={} digit_bits={}",
+                 magic, self.x, self.start_term, self.end_term, self.limbs, Digit::BITS)
+    }
+
+    #[allow(dead_code)]
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        // Send the request to a worker
+        self.write_header(w, REQUEST)?;
+        w.flush()
+    }
+
+    fn read_header(r: &mut impl BufRead, magic: &str) -> Result<Self, String> {
+        let mut line = String::new();
+        r.read_line(&mut line).map_err(|err| err.to_string())?;
+        let fields = line.trim_end().strip_prefix(magic)
+            .ok_or_else(|| format!("Expected a line starting with '{}'", magic))?;
+        let mut values = [None; 5];
+        let keys = ["x", "start", "end", "limbs", "digit_bits"];
+        for field in fields.split_whitespace() {
+            let (key, value) = field.split_once('=')
+                .ok_or_else(|| format!("Malformed field '{}'", field))?;
+            let i = keys.iter().position(|&k| k == key)
+                .ok_or_else(|| format!("Unknown key '{}'", key))?;
+            let value: usize = value.parse()
+                .map_err(|_| format!("Invalid value '{}' for {}", value, key))?;
+            values[i] = Some(value);
+        }
+        let [x, start_term, end_term, limbs, digit_bits] =
+            [0, 1, 2, 3, 4].map(|i| values[i].ok_or_else(|| format!("Missing {}", keys[i])));
+        let digit_bits = digit_bits?;
+        if digit_bits != Digit::BITS as usize {
+            return Err(format!("The digits have {} bits, but the request is for {}",
+                               Digit::BITS, digit_bits));
+        }
+        let x = Digit::try_from(x?).map_err(|_| "x does not fit into a Digit".to_string())?;
+        Ok(RangeRequest { x, start_term: start_term?, end_term: end_term?, limbs: limbs? })
+    }
+}
+
+#[allow(dead_code)]
+pub fn split_ranges(x: Digit, limbs: usize, workers: usize) -> Vec<RangeRequest> {
+    // Requests for workers ranges with about the same number of terms. The terms are zero from
+    // 1/x^(2k+1) < 2^-(Digit::BITS limbs) on, the last range includes all terms up to there.
+    assert!(workers > 0, "No workers");
+    let bits = (limbs * Digit::BITS as usize) as f64;
+    let terms = (bits / (2.0 * (x as f64).log2())).ceil() as usize;
+    let bounds: Vec<usize> = (0..workers).map(|i| terms * i / workers)
+        .chain([usize::MAX])
+        .collect();
+    bounds.windows(2)
+        .map(|pair| RangeRequest { x, start_term: pair[0], end_term: pair[1], limbs })
+        .collect()
+}
+
+pub fn compute_range_request(r: &mut impl BufRead, w: &mut impl Write) -> Result<(), String> {
+    // Worker side: read one request and write the partial sum for it
+    let request = RangeRequest::read_header(r, REQUEST)?;
+    let partial = ataninv_range(request.x, request.start_term, request.end_term, request.limbs);
+    let mut w = BufWriter::new(w);
+    request.write_header(&mut w, PARTIAL)
+        .and_then(|_| partial.write_raw(&mut w))
+        .and_then(|_| w.flush())
+        .map_err(|err| format!("Can not write partial sum: {}", err))
+}
+
+#[allow(dead_code)]
+pub fn read_partial(r: &mut impl BufRead) -> Result<(RangeRequest, Number), String> {
+    // Coordinator side: read the answer of a worker
+    let request = RangeRequest::read_header(r, PARTIAL)?;
+    let mut bytes = vec![0; request.limbs * mem::size_of::<Digit>()];
+    r.read_exact(&mut bytes)
+        .map_err(|_| format!("The partial sum has fewer than {} limbs", request.limbs))?;
+    let digits: Vec<Digit> = bytes.chunks(mem::size_of::<Digit>())
+        .map(|chunk| Digit::from_be_bytes(chunk.try_into().unwrap()))
+        .collect();
+    Ok((request, Number::from_digits(&digits)))
+}
+
+#[allow(dead_code)]
+pub fn combine_partials(x: Digit, limbs: usize, mut partials: Vec<(RangeRequest, Number)>)
+        -> Result<Number, String> {
+    // Sum of the partial sums of atan(1/x), which must be for consecutive ranges from the first
+    // term on up to usize::MAX like the ones of split_ranges, in any order. With more workers than
+    // terms, some ranges are empty, and they come before the one that starts at the same term.
+    partials.sort_by_key(|(request, _)| (request.start_term, request.end_term));
+    let mut result: Number = Number::zero_with_len(limbs);
+    let mut next = 0;
+    for (request, partial) in &partials {
+        if request.x != x || request.limbs != limbs {
+            return Err(format!("Partial sum for x={} with {} limbs in the sum for x={} with {}",
+                               request.x, request.limbs, x, limbs));
+        }
+        if request.start_term != next {
+            return Err(format!("The partial sums have the terms up to {}, but the next one starts \
+                                at {}", next, request.start_term));
+        }
+        result.wrapping_add_assign(partial);
+        next = request.end_term;
+    }
+    if next != usize::MAX {
+        return Err(format!("The terms from {} on are missing", next));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulated_worker(request: &RangeRequest) -> (RangeRequest, Number) {
+        // The request through compute_range_request and the answer through read_partial, like
+        // over a pipe
+        let mut sent = Vec::new();
+        request.write(&mut sent).unwrap();
+        let mut answer = Vec::new();
+        compute_range_request(&mut &sent[..], &mut answer).unwrap();
+        let mut r = &answer[..];
+        let partial = read_partial(&mut r).unwrap();
+        assert!(r.is_empty(), "Data after the partial sum");
+        partial
+    }
+
+    #[test]
+    fn combined_partials_agree_with_scalar() {
+        for x in [2, 5, 239] {
+            for limbs in [1, 4] {
+                let (expected, _) = ataninv_scalar_until(x, limbs, Series::Atan, None);
+                for workers in [1, 2, 3, 7] {
+                    let requests = split_ranges(x, limbs, workers);
+                    assert_eq!(requests.len(), workers);
+                    // The answers may come back in any order
+                    let partials: Vec<_> = requests.iter().rev().map(simulated_worker).collect();
+                    assert_eq!(partials.iter().map(|(r, _)| *r).rev().collect::<Vec<_>>(),
+                               requests);
+                    let result = combine_partials(x, limbs, partials).unwrap();
+                    assert_eq!(result.digits, expected.digits, "x = {}, {} workers", x, workers);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn combine_partials_rejects_gaps_and_mismatches() {
+        let requests = split_ranges(5, 2, 3);
+        let partials: Vec<_> = requests.iter().map(simulated_worker).collect();
+        let without = |i: usize| {
+            let mut partials = partials.clone();
+            partials.remove(i);
+            combine_partials(5, 2, partials).err().unwrap()
+        };
+        assert!(without(0).starts_with("The partial sums have the terms up to 0"));
+        assert!(without(1).contains("but the next one starts at"));
+        assert!(without(2).starts_with("The terms from"));
+        assert!(combine_partials(239, 2, partials.clone()).err().unwrap()
+                .starts_with("Partial sum for x=5 with 2 limbs in the sum for x=239"));
+        assert!(combine_partials(5, 3, partials).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        let read = |line: &str| {
+            let mut out = Vec::new();
+            compute_range_request(&mut line.as_bytes(), &mut out).err().unwrap()
+        };
+        let bits = Digit::BITS;
+        assert!(read("picalc partial x=5 start=0 end=1 limbs=1").starts_with("Expected a line"));
+        assert_eq!(read("picalc range x=5 start=0 end=1 limbs"), "Malformed field 'limbs'");
+        assert_eq!(read("picalc range x=5 stop=1"), "Unknown key 'stop'");
+        assert_eq!(read("picalc range x=five"), "Invalid value 'five' for x");
+        assert_eq!(read(&format!("picalc range x=5 start=0 end=1 digit_bits={}", bits)),
+                   "Missing limbs");
+        assert!(read("picalc range x=5 start=0 end=1 limbs=1 digit_bits=16")
+                .ends_with("but the request is for 16"));
+        // A partial sum that was cut off
+        let mut sent = Vec::new();
+        RangeRequest { x: 5, start_term: 0, end_term: 9, limbs: 2 }.write(&mut sent).unwrap();
+        let mut answer = Vec::new();
+        compute_range_request(&mut &sent[..], &mut answer).unwrap();
+        answer.pop();
+        assert_eq!(read_partial(&mut &answer[..]).err().unwrap(),
+                   "The partial sum has fewer than 2 limbs");
+    }
+}
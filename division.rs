@@ -0,0 +1,178 @@
+/*
+ * Division by a Number instead of a single Digit, with Knuth's algorithm D (TAOCP vol. 2, 4.3.1).
+ * Dividend and divisor are taken as big integers of digits like in mod_small, and each step finds
+ * the next digit of the quotient from an estimate by the two leading digits of the remainder and
+ * the leading digit of the divisor, which is at most two too large if the divisor is normalized
+ * so its leading bit is set. The quotient is the exact floor, so the result is truncated like the
+ * one of set_to_div. It takes O(len^2) digit operations.
+ *
+ * The digits are handled least significant first here, unlike in Number, which keeps the indices
+ * the same as in the description of the algorithm.
+ */
+use super::*;
+
+const BASE: Double = 1 << Digit::BITS;
+
+fn shl(digits: &[Digit], shift: u32, extra: usize) -> Vec<Digit> {
+    // digits * 2^shift with shift < Digit::BITS, with extra more digits on top for the overflow
+    let mut result = vec![0; digits.len() + extra];
+    let mut carry = 0;
+    for (r, &d) in result.iter_mut().zip(digits) {
+        let wide = (d as Double) << shift;
+        *r = wide as Digit | carry;
+        carry = (wide >> Digit::BITS) as Digit;
+    }
+    if extra > 0 {
+        result[digits.len()] = carry;
+    }
+    result
+}
+
+pub fn div_limbs(u: &[Digit], v: &[Digit]) -> Vec<Digit> {
+    // floor(u / v) for least significant first digits, where the last digit of v is not zero and
+    // v has at most as many digits as u. The quotient has u.len() - v.len() + 1 digits.
+    let n = v.len();
+    let m = u.len() - n;
+    let mut q = vec![0; m + 1];
+    if n == 1 {
+        let d = v[0] as Double;
+        let mut rem: Double = 0;
+        for j in (0..u.len()).rev() {
+            let num = (rem << Digit::BITS) + u[j] as Double;
+            q[j] = (num / d) as Digit;
+            rem = num % d;
+        }
+        return q;
+    }
+    let shift = v[n-1].leading_zeros();
+    let vn = shl(v, shift, 0);
+    let mut un = shl(u, shift, 1);
+    let top = vn[n-1] as Double;
+    for j in (0..=m).rev() {
+        // Estimate the quotient digit and correct it with the second digit of the divisor
+        let num = ((un[j+n] as Double) << Digit::BITS) + un[j+n-1] as Double;
+        let mut qhat = num / top;
+        let mut rhat = num % top;
+        while qhat >= BASE
+                || qhat * vn[n-2] as Double > (rhat << Digit::BITS) + un[j+n-2] as Double {
+            qhat -= 1;
+            rhat += top;
+            if rhat >= BASE {
+                break;
+            }
+        }
+        // Subtract qhat * vn from the digits j..=j+n of the remainder
+        let mut carry: Double = 0;
+        let mut borrow: Double = 0;
+        for i in 0..=n {
+            let prod = if i < n { qhat * vn[i] as Double + carry } else { carry };
+            carry = prod >> Digit::BITS;
+            let sub = (prod as Digit) as Double + borrow;
+            let cur = un[i+j] as Double;
+            borrow = (cur < sub) as Double;
+            un[i+j] = (cur + borrow * BASE - sub) as Digit;
+        }
+        if borrow != 0 {
+            // qhat was still one too large, which is rare, so add the divisor back
+            qhat -= 1;
+            let mut carry: Double = 0;
+            for i in 0..n {
+                let sum = un[i+j] as Double + vn[i] as Double + carry;
+                un[i+j] = sum as Digit;
+                carry = sum >> Digit::BITS;
+            }
+            un[j+n] = (un[j+n] as Double + carry) as Digit;
+        }
+        q[j] = qhat as Digit;
+    }
+    q
+}
+
+impl<S: LimbStore> Number<S> {
+    #[allow(dead_code)]
+    pub fn reciprocal(&self, int_part: Digit) -> (Digit, Self) {
+        // 1/(int_part + self), interpreting self as an unsigned fraction in [0, 1), as integer
+        // part and fraction with the same number of digits, truncated. The value must not be
+        // zero, and the integer part of the result must fit into a Digit.
+        let len = self.len();
+        // The divisor is (int_part + self) * 2^(Digit::BITS*len) and the dividend the square of
+        // the unit, so the quotient is the reciprocal scaled by 2^(Digit::BITS*len) again.
+        let mut v: Vec<Digit> = (0..len).rev().map(|i| self.digits.get(i)).collect();
+        v.push(int_part);
+        while v.last() == Some(&0) {
+            v.pop();
+        }
+        assert!(!v.is_empty(), "Reciprocal of zero");
+        let mut u = vec![0; 2*len + 1];
+        u[2*len] = 1;
+        let q = div_limbs(&u, &v);
+        assert!(q[len+1..].iter().all(|&d| d == 0), "Integer part of the reciprocal is too large");
+        let frac: Vec<Digit> = q[..len].iter().rev().copied().collect();
+        (q[len], Self::from_digits(&frac))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_u128(digits: &[Digit]) -> u128 {
+        // Least significant first, like div_limbs takes them
+        digits.iter().rev().fold(0, |acc, &d| (acc << Digit::BITS) | d as u128)
+    }
+
+    #[test]
+    fn div_limbs_agrees_with_u128() {
+        let len = 128 / Digit::BITS as usize;
+        let mut seed: u64 = 0x1f83d9abfb41bd6b;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for n in 1..=len {
+            for _ in 0..200 {
+                let u: Vec<Digit> = (0..len).map(|_| next() as Digit).collect();
+                // Small leading digits of v need the most corrections of qhat
+                let mut v: Vec<Digit> = (0..n).map(|_| next() as Digit).collect();
+                v[n-1] = match next() % 3 {
+                    0 => 1,
+                    1 => Digit::MAX,
+                    _ => (next() as Digit >> (next() % Digit::BITS as u64)).max(1),
+                };
+                let q = div_limbs(&u, &v);
+                assert_eq!(q.len(), len - n + 1);
+                assert_eq!(to_u128(&q), to_u128(&u) / to_u128(&v), "{:x?} / {:x?}", u, v);
+            }
+        }
+    }
+
+    #[test]
+    fn reciprocal_of_simple_values() {
+        let half: Number = Number::from_digits(&[1 << (Digit::BITS - 1), 0]);
+        let (int_part, inv) = half.reciprocal(0);
+        assert_eq!((int_part, &inv.digits[..]), (2, &[0, 0][..]));
+        // 1/(1 + 1/2) = 2/3 = 0.aaaa... in hexadecimal, truncated
+        let (int_part, inv) = half.reciprocal(1);
+        assert_eq!((int_part, &inv.digits[..]), (0, &[Digit::MAX / 3 * 2; 2][..]));
+        // 1/4 is represented exactly
+        let zero: Number = Number::zero_with_len(2);
+        let (int_part, inv) = zero.reciprocal(4);
+        assert_eq!((int_part, &inv.digits[..]), (0, &[1 << (Digit::BITS - 2), 0][..]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Reciprocal of zero")]
+    fn reciprocal_rejects_zero() {
+        let zero: Number = Number::zero_with_len(2);
+        zero.reciprocal(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Integer part of the reciprocal is too large")]
+    fn reciprocal_rejects_large_result() {
+        let tiny: Number = Number::from_digits(&[0, 1]);
+        tiny.reciprocal(0);
+    }
+}
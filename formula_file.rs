@@ -0,0 +1,227 @@
+/*
+ * Bulk validation of Machin-like formulas with rational arguments, read from a file with one
+ * formula per line, for example
+ *
+ *     # Machin and Euler
+ *     4 1/5, -1 1/239
+ *     1 1/2, 1 1/3
+ *     2 1/3, 1 1/7
+ *
+ * Each term is a coefficient c and a fraction b/a with 0 < b < a and a^2 fitting into a Digit,
+ * for c*atan(b/a). Empty lines and lines starting with # are skipped. A formula passes if its sum
+ * is n*pi/4 for an integer n within 10^-digits, where n is the nearest multiple of pi/4 to an f64
+ * estimate of the sum. So any multiple passes, including zero for identities between arctans,
+ * and the report says which one it is.
+ *
+ * The arctans are computed with ataninv_rational and combined with combine_arctans, with four
+ * times the coefficients and -n times the fractional part of pi, so the sum is 4S - n*pi up to
+ * the integer part 3n. pi is computed once for all formulas with Machin's formula. Both have two
+ * guard limbs on top of the precision of the digits for the truncation errors. Those of
+ * ataninv_rational grow with b^2 a^2 / (a^2 - b^2), so for large arguments they can reach into
+ * the digits, and a formula only passes if their bound is below 10^-digits too.
+ */
+use super::*;
+use super::computation::limbs_for_precision;
+use super::gaussian::ataninv_rational_counted;
+use std::fs;
+
+// Guard limbs on top of the one of limbs_for_precision
+const FORMULA_GUARD_LIMBS: usize = 1;
+
+// Arguments b/a whose series needs more terms than this times the bits of the precision are
+// rejected, since the terms only shrink by (b/a)^2 each. This allows b/a up to about 0.98.
+const MAX_TERMS_PER_BIT: f64 = 16.0;
+
+// One term c*atan(b/a) as (c, b, a)
+pub type RationalTerm = (i64, Digit, Digit);
+
+pub struct FormulaCheck {
+    // Line of the formula in the file, starting with 1
+    pub line: usize,
+    pub terms: Vec<RationalTerm>,
+    // The sum is about multiple*pi/4
+    pub multiple: i64,
+    // Number of Taylor terms after b/a that each arctan needed
+    pub term_counts: Vec<usize>,
+    // log2 of |S - multiple*pi/4| as computed, -inf if it is zero at this precision
+    pub error_log2: f64,
+    // log2 of the bound of the truncation errors in it
+    pub bound_log2: f64,
+    pub pass: bool,
+}
+
+pub fn parse_formula(line: &str) -> Result<Vec<RationalTerm>, String> {
+    // The terms of a formula in the format described at the top
+    line.split(',')
+        .map(|term| {
+            let (c, fraction) = term.trim().split_once(char::is_whitespace)
+                .ok_or_else(|| format!("Expected 'c b/a' instead of '{}'", term.trim()))?;
+            let (b, a) = fraction.trim().split_once('/')
+                .ok_or_else(|| format!("Expected a fraction b/a instead of '{}'", fraction))?;
+            let c: i64 = c.parse().map_err(|_| format!("Invalid coefficient '{}'", c))?;
+            let number = |s: &str| {
+                s.trim().parse::<Digit>().map_err(|_| format!("Invalid number '{}'", s))
+            };
+            let (b, a) = (number(b)?, number(a)?);
+            if b == 0 || b >= a {
+                return Err(format!("atan({}/{}) is not in (0, pi/4)", b, a));
+            }
+            if a.checked_mul(a).is_none() {
+                return Err(format!("{}^2 does not fit into a Digit", a));
+            }
+            Ok((c, b, a))
+        })
+        .collect()
+}
+
+fn check_formula(line: usize, terms: Vec<RationalTerm>, pi: &Number, pi_error: f64,
+                 digits: usize) -> FormulaCheck {
+    // Check one formula against pi, the fractional part with the same number of limbs, which is
+    // off by less than pi_error units in the last limb
+    let estimate: f64 = terms.iter()
+        .map(|&(c, b, a)| c as f64 * (b as f64 / a as f64).atan())
+        .sum();
+    let multiple = (estimate / std::f64::consts::FRAC_PI_4).round() as i64;
+    let mut scaled = Vec::with_capacity(terms.len() + 1);
+    let mut term_counts = Vec::with_capacity(terms.len());
+    // In units in the last limb, see ataninv_rational
+    let mut bound = multiple.unsigned_abs() as f64 * pi_error;
+    for &(c, b, a) in &terms {
+        let (atan, count) = ataninv_rational_counted(b, a, pi.len());
+        scaled.push((4*c, atan));
+        term_counts.push(count);
+        let (a2, b2) = ((a as f64).powi(2), (b as f64).powi(2));
+        bound += 4.0 * c.unsigned_abs() as f64 * (count + 1) as f64 * b2 * a2 / (a2 - b2);
+    }
+    scaled.push((-multiple, pi.clone()));
+    let (diff, int_part) = combine_arctans(&scaled);
+    // 4S - n*pi = int_part - 3n + diff. Within [-0.5, 0.5), this is diff as a signed value, with
+    // an integer part of -1 if it is negative.
+    let int_part = int_part - 3*multiple;
+    let error_log2 = if int_part == -(diff.sign_bit() as i64) {
+        diff.approx_log2() - 2.0
+    } else {
+        let leading = diff.digits[0] as f64 / 2f64.powi(Digit::BITS as i32);
+        (int_part as f64 + leading).abs().log2() - 2.0
+    };
+    let bound_log2 = bound.log2() - (pi.len() * Digit::BITS as usize) as f64 - 2.0;
+    let threshold = -(digits as f64) * 10f64.log2();
+    let pass = error_log2 < threshold && bound_log2 < threshold;
+    FormulaCheck { line, terms, multiple, term_counts, error_log2, bound_log2, pass }
+}
+
+pub fn check_formula_file(path: &str, digits: usize, nthreads: usize)
+        -> Result<Vec<FormulaCheck>, String> {
+    // Check all formulas in the file at path with the given number of decimal digits. nthreads
+    // workers compute pi, the arctans of the formulas are summed one after another.
+    let content = fs::read_to_string(path)
+        .map_err(|err| format!("Can not read formula file '{}': {}", path, err))?;
+    let formulas: Vec<(usize, Vec<RationalTerm>)> = content.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| {
+            parse_formula(line).map(|terms| (i + 1, terms))
+                .map_err(|err| format!("Invalid formula in line {} of '{}': {}", i + 1, path, err))
+        })
+        .collect::<Result<_, _>>()?;
+    let limbs = limbs_for_precision(digits) + FORMULA_GUARD_LIMBS;
+    let bits = (limbs * Digit::BITS as usize) as f64;
+    for (line, terms) in &formulas {
+        for &(_, b, a) in terms {
+            let estimate = bits / (2.0 * (a as f64 / b as f64).log2());
+            if estimate > MAX_TERMS_PER_BIT * bits {
+                return Err(format!("atan({}/{}) in line {} of '{}' needs about {:.0} terms",
+                                   b, a, line, path, estimate));
+            }
+        }
+    }
+    let (pi, int_part) = evaluate_machin_like(&[(16, 5), (-4, 239)], false, limbs, nthreads);
+    assert_eq!(int_part, 3, "Integer part of pi is wrong");
+    // Each arctan is off by less than one unit per term, scaled by the coefficients
+    let pi_error = (16 * (atan_term_count(5, limbs) + 1) + 4 * (atan_term_count(239, limbs) + 1))
+        as f64;
+    Ok(formulas.into_iter()
+        .map(|(line, terms)| check_formula(line, terms, &pi, pi_error, digits))
+        .collect())
+}
+
+pub fn format_formula(terms: &[RationalTerm]) -> String {
+    // The terms like in the file
+    terms.iter().map(|&(c, b, a)| format!("{} {}/{}", c, b, a)).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_content(name: &str, content: &str, digits: usize)
+            -> Result<Vec<FormulaCheck>, String> {
+        // check_formula_file on a file with the given content
+        let name = format!("picalc-formulas-{}-{}", std::process::id(), name);
+        let path = std::env::temp_dir().join(name);
+        let path = path.to_str().unwrap();
+        fs::write(path, content).unwrap();
+        let checks = check_formula_file(path, digits, 2);
+        fs::remove_file(path).unwrap();
+        checks.map_err(|err| err.replace(path, "formulas"))
+    }
+
+    #[test]
+    fn parses_terms() {
+        assert_eq!(parse_formula("4 1/5, -1 1/239").unwrap(), vec![(4, 1, 5), (-1, 1, 239)]);
+        assert_eq!(parse_formula("  3 1/4 ,1   5 / 99 ").unwrap(), vec![(3, 1, 4), (1, 5, 99)]);
+        assert_eq!(format_formula(&parse_formula("3 1/4, 1 5/99").unwrap()), "3 1/4, 1 5/99");
+    }
+
+    #[test]
+    fn rejects_invalid_terms() {
+        let large = format!("1 1/{}", Digit::MAX / 2);
+        for (line, expected) in [
+            ("4", "Expected 'c b/a' instead of '4'"),
+            ("4 1/5,", "Expected 'c b/a' instead of ''"),
+            ("4 5", "Expected a fraction b/a instead of '5'"),
+            ("x 1/5", "Invalid coefficient 'x'"),
+            ("4 1/y", "Invalid number 'y'"),
+            ("4 -1/5", "Invalid number '-1'"),
+            ("4 0/5", "atan(0/5) is not in (0, pi/4)"),
+            ("4 5/5", "atan(5/5) is not in (0, pi/4)"),
+            (&large, "does not fit into a Digit"),
+        ] {
+            let err = parse_formula(line).unwrap_err();
+            assert!(err.contains(expected), "'{}' gives '{}'", line, err);
+        }
+    }
+
+    #[test]
+    fn checks_multiples_of_pi() {
+        let content = "# Machin, Euler, Hutton and two with rational arguments\n\
+                       4 1/5, -1 1/239\n\n1 1/2, 1 1/3\n2 1/3, 1 1/7\n3 1/4, 1 5/99\n\
+                       8 1/5, -2 1/239\n\
+                       # An identity between arctans and one that is close to pi/4\n\
+                       1 1/2, -1 1/3, -1 1/7\n4 1/5, -1 1/240\n";
+        let checks = check_content("multiples", content, 40).unwrap();
+        let lines: Vec<_> = checks.iter().map(|check| check.line).collect();
+        assert_eq!(lines, [2, 4, 5, 6, 7, 9, 10]);
+        let multiples: Vec<_> = checks.iter().map(|check| check.multiple).collect();
+        assert_eq!(multiples, [1, 1, 1, 1, 2, 0, 1]);
+        let passes: Vec<_> = checks.iter().map(|check| check.pass).collect();
+        assert_eq!(passes, [true, true, true, true, true, true, false]);
+        for check in &checks[..6] {
+            assert_eq!(check.term_counts.len(), check.terms.len());
+            assert!(check.bound_log2 < -40.0 * 10f64.log2());
+        }
+        // About 40 digits / log10(25) terms for 1/5, fewer for 1/239
+        assert_eq!(checks[0].term_counts.len(), 2);
+        assert!(checks[0].term_counts[0] > checks[0].term_counts[1]);
+        assert!((checks[6].error_log2 - 1.7433e-5f64.log2()).abs() < 0.01);
+    }
+
+    #[test]
+    fn reports_invalid_lines_and_slow_arguments() {
+        let err = check_content("invalid", "4 1/5, -1 1/239\n\n4 1/5 -1 1/239\n", 40)
+            .err().unwrap();
+        assert_eq!(err, "Invalid formula in line 3 of 'formulas': Invalid number '5 -1 1/239'");
+        let err = check_content("slow", "1 999/1000\n", 40).err().unwrap();
+        assert!(err.starts_with("atan(999/1000) in line 1 of 'formulas' needs about"), "{}", err);
+        assert!(check_content("missing", "", 40).unwrap().is_empty());
+    }
+}
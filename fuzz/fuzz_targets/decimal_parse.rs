@@ -0,0 +1,11 @@
+// Run with: cargo fuzz run decimal_parse
+// The invariants are described in fuzzing.rs.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../picalc.rs"]
+#[allow(dead_code)]
+mod picalc;
+
+fuzz_target!(|data: &[u8]| picalc::fuzzing::check_parse(data));
@@ -0,0 +1,81 @@
+/*
+ * Checks run by the fuzz targets in fuzz/, which include picalc.rs as a module. This is only
+ * compiled with cfg(fuzzing), which cargo fuzz sets, and then DIGITS is small. The checks panic
+ * if an invariant is violated, which the fuzzer reports as a crash.
+ *
+ * check_parse feeds arbitrary input to from_decimal_string:
+ * - It never panics.
+ * - It only accepts strings of the form [-]0.ddd with at least one ASCII digit.
+ * - Such a string is accepted if the first digit is below 5 and rejected if it is above 5. With
+ *   a 5, [-0.5, 0.5) decides, after truncating to the precision.
+ * - An accepted value without sign has the top bit clear. With a sign, it is the negation of the
+ *   value without sign, if that one is accepted.
+ *
+ * check_roundtrip parses a fraction in [0, 0.5), formats it with decimal_chunks and parses the
+ * result again. Formatting truncates to n = decimal_digits() digits, which loses less than
+ * 10^-n, and parsing truncates to the precision, which loses less than one unit in the last
+ * digit. Since 10^-n < 10 * 2^-bits, the value may decrease by at most 10 units in the last
+ * digit and must never increase.
+ */
+use super::*;
+
+fn well_formed(s: &str) -> bool {
+    let s = s.strip_prefix('-').unwrap_or(s);
+    match s.strip_prefix("0.") {
+        Some(digits) => !digits.is_empty() && digits.bytes().all(|d| d.is_ascii_digit()),
+        None => false,
+    }
+}
+
+pub fn check_parse(data: &[u8]) {
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+    let parsed = Number::<Vec<Digit>>::from_decimal_string(s);
+    if !well_formed(s) {
+        assert!(parsed.is_none(), "Accepted malformed input {:?}", s);
+        return;
+    }
+    let (neg, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let first = unsigned.as_bytes()[2];
+    if first < b'5' {
+        assert!(parsed.is_some(), "Rejected {:?}", s);
+    } else if first > b'5' {
+        assert!(parsed.is_none(), "Accepted {:?} outside of the range", s);
+    }
+    let Some(x) = parsed else {
+        return;
+    };
+    let top = x.digits.get(0) >> (Digit::BITS - 1);
+    if !neg {
+        assert_eq!(top, 0, "Positive {:?} has the top bit set", s);
+        return;
+    }
+    assert!(top == 1 || x.is_zero(), "Negative {:?} has the top bit clear", s);
+    if let Some(pos) = Number::<Vec<Digit>>::from_decimal_string(unsigned) {
+        let mut sum = x.clone();
+        sum.wrapping_add_assign(&pos);
+        assert!(sum.is_zero(), "{:?} is not the negation of {:?}", s, unsigned);
+    }
+}
+
+pub fn check_roundtrip(data: &[u8]) {
+    // The first byte selects a digit below 5, so the fraction is always in the range
+    let Some((&first, rest)) = data.split_first() else {
+        return;
+    };
+    let mut s = format!("0.{}", first % 5);
+    s.extend(rest.iter().map(|b| (b'0' + b % 10) as char));
+    let x = Number::<Vec<Digit>>::from_decimal_string(&s).unwrap();
+    let formatted = format!("0.{}", x.decimal_chunks().collect::<String>());
+    let y = Number::<Vec<Digit>>::from_decimal_string(&formatted)
+        .unwrap_or_else(|| panic!("Formatted {:?} as {:?}, which does not parse", s, formatted));
+    let mut diff = x.clone();
+    diff.sub_assign(&y);
+    let last = diff.len() - 1;
+    assert!(diff.zeros >= last && diff.digits.get(last) <= 10,
+        "Round trip of {:?} through {:?} changed the value", s, formatted);
+}
@@ -0,0 +1,240 @@
+/*
+ * Arguments of Gaussian integers, the number-theoretic basis of Machin-like formulas.
+ *
+ * The argument of a + bi with 0 < b < a is atan(b/a), and arguments add up when Gaussian integers
+ * are multiplied. So sum over c*atan(1/x) is the argument of the product of (x + i)^c, taken
+ * modulo 2pi, and a formula for pi/4 is a product that is a real multiple of 1 + i. Whether it is
+ * can be read off from the factorizations into Gaussian primes: 1 + i, the rational primes
+ * p = 3 mod 4, and for each rational prime p = 1 mod 4 a pair u + vi and u - vi with
+ * u^2 + v^2 = p. x + i is not divisible by any rational integer but one, so for each p = 1 mod 4
+ * it contains at most one of the pair. The product is a unit times a power of 1 + i times a real
+ * number exactly if the exponents of both primes of each pair cancel. For Machin's formula,
+ *
+ *     5 + i = (1 + i)(3 - 2i)  and  239 + i = i (1 + i)(3 - 2i)^4,
+ *
+ * so (5 + i)^4 / (239 + i) = (1 + i)^3 / i = 2 + 2i, which has the argument pi/4.
+ *
+ * The factorizations use trial division of the norm a^2 + b^2, so they are only meant for the
+ * arguments of Machin-like formulas, not for large numbers.
+ */
+use super::*;
+
+// A Gaussian integer re + im i, wide enough for the products of components up to a Digit
+type Gaussian = (i128, i128);
+
+#[allow(dead_code)]
+pub fn ataninv_rational(b: Digit, a: Digit, limbs: usize) -> Number {
+    // atan(b/a) with the given number of digits, for 0 < b < a where a^2 fits into a Digit, by the
+    // Taylor series sum over (-1)^k (b/a)^(2k+1) / (2k+1). For b = 1 this is ataninv_scalar_until
+    // with a single division per term. The next power of b/a is divided by a^2 before it is
+    // multiplied by b^2 to stay below one, so the powers are off by less than
+    // b^2 a^2 / (a^2 - b^2) units in the last limb and the result by less than terms + 1 times
+    // that, which guard limbs have to cover.
+    ataninv_rational_counted(b, a, limbs).0
+}
+
+pub fn ataninv_rational_counted(b: Digit, a: Digit, limbs: usize) -> (Number, usize) {
+    // Same as ataninv_rational, also returning the number of terms after b/a
+    assert!(0 < b && b < a, "atan({}/{}) is not in (0, pi/4)", b, a);
+    let (a2, b2) = (a.checked_mul(a).expect("a^2 does not fit into a Digit"), b*b);
+    let mut power = Number::from_inv_with_len(a, limbs);
+    power.wrapping_mul_small(b);
+    let mut result = power.clone();
+    let mut denom: Digit = 1;
+    let mut neg = true;
+    let mut terms = 0;
+    while !power.is_zero() {
+        power /= a2;
+        power.wrapping_mul_small(b2);
+        denom += 2;
+        result.add_div_assign(&power, denom, neg);
+        neg = !neg;
+        terms += 1;
+    }
+    (result, terms)
+}
+
+#[allow(dead_code)]
+pub fn gaussian_arg(a: Digit, b: Digit, limbs: usize) -> Number {
+    // The argument of a + bi, see ataninv_rational
+    ataninv_rational(b, a, limbs)
+}
+
+fn factor(mut n: u128) -> Vec<(u128, u32)> {
+    // Rational prime factors of n with their exponents, by trial division
+    let mut factors = Vec::new();
+    let mut p = 2;
+    while p * p <= n {
+        let mut exp = 0;
+        while n.is_multiple_of(p) {
+            n /= p;
+            exp += 1;
+        }
+        if exp > 0 {
+            factors.push((p, exp));
+        }
+        p += 1;
+    }
+    if n > 1 {
+        factors.push((n, 1));
+    }
+    factors
+}
+
+fn div_exact(z: Gaussian, d: Gaussian) -> Option<Gaussian> {
+    // z / d if d divides z
+    let norm = d.0 * d.0 + d.1 * d.1;
+    let re = z.0 * d.0 + z.1 * d.1;
+    let im = z.1 * d.0 - z.0 * d.1;
+    (re % norm == 0 && im % norm == 0).then_some((re / norm, im / norm))
+}
+
+fn two_squares(p: u128) -> Gaussian {
+    // u + vi with u > v > 0 and u^2 + v^2 = p, for a prime p = 1 mod 4
+    let u = (1..=p.isqrt()).rev()
+        .find(|&u| (p - u*u).isqrt().pow(2) == p - u*u)
+        .unwrap();
+    (u as i128, (p - u*u).isqrt() as i128)
+}
+
+#[allow(dead_code)]
+pub fn gaussian_factors(a: Digit, b: Digit) -> (u32, Vec<(Gaussian, u32)>) {
+    // Factorization of a + bi, not zero, as i^unit times the product of the Gaussian primes with
+    // their exponents. The primes are 1 + i, rational primes p = 3 mod 4, and u + vi or u - vi
+    // with u > v > 0 for p = 1 mod 4, so 3 - 2i is a factor of 5 + i.
+    let mut z = (a as i128, b as i128);
+    let mut factors = Vec::new();
+    for (p, _) in factor(a as u128 * a as u128 + b as u128 * b as u128) {
+        let candidates = match p % 4 {
+            2 => vec![(1, 1)],
+            // Its norm is p^2, so it appears with half the exponent
+            3 => vec![(p as i128, 0)],
+            _ => {
+                let (u, v) = two_squares(p);
+                vec![(u, v), (u, -v)]
+            },
+        };
+        for prime in candidates {
+            let mut count = 0;
+            while let Some(quotient) = div_exact(z, prime) {
+                z = quotient;
+                count += 1;
+            }
+            if count > 0 {
+                factors.push((prime, count));
+            }
+        }
+    }
+    // What is left is a unit
+    let unit = match z {
+        (1, 0) => 0,
+        (0, 1) => 1,
+        (-1, 0) => 2,
+        (0, -1) => 3,
+        _ => unreachable!("{} + {}i remains after dividing out all primes", z.0, z.1),
+    };
+    (unit, factors)
+}
+
+#[allow(dead_code)]
+pub fn machin_multiple(formula: &[(i64, Digit)]) -> Option<i64> {
+    // n such that sum over c*atan(1/x) for all pairs (c, x) is n*pi/4, or None if the sum is not
+    // a multiple of pi/4. The product of (x + i)^c has the argument n*pi/4 modulo 2pi exactly if
+    // the exponents of the primes u + vi and u - vi cancel for each p = 1 mod 4, see the top. Then
+    // it is i^unit (1 + i)^m times a real number, which is positive since the norms are, and n
+    // is 2*unit + m modulo 8. n itself is found from an f64 estimate of the sum, which is off
+    // by far less than one.
+    let mut unit: i64 = 0;
+    let mut exponents: Vec<(Gaussian, i64)> = Vec::new();
+    for &(c, x) in formula {
+        let (u, factors) = gaussian_factors(x, 1);
+        unit += c * u as i64;
+        for (prime, exp) in factors {
+            match exponents.iter_mut().find(|(q, _)| *q == prime) {
+                Some((_, total)) => *total += c * exp as i64,
+                None => exponents.push((prime, c * exp as i64)),
+            }
+        }
+    }
+    let exponent = |prime: Gaussian| {
+        exponents.iter().find(|&&(q, _)| q == prime).map_or(0, |&(_, total)| total)
+    };
+    let cancels = exponents.iter()
+        .all(|&((u, v), total)| v == 0 || u == v || total == exponent((u, -v)));
+    if !cancels {
+        return None;
+    }
+    let residue = (2*unit + exponent((1, 1))).rem_euclid(8);
+    let estimate: f64 = formula.iter().map(|&(c, x)| c as f64 * (1.0 / x as f64).atan()).sum();
+    let n = (estimate / std::f64::consts::FRAC_PI_4).round() as i64;
+    assert_eq!(n.rem_euclid(8), residue, "The f64 estimate of the sum is too coarse");
+    Some(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn factors_of_machin_arguments() {
+        // 5 + i = (1 + i)(3 - 2i) and 239 + i = i (1 + i)(3 - 2i)^4
+        assert_eq!(gaussian_factors(5, 1), (0, vec![((1, 1), 1), ((3, -2), 1)]));
+        assert_eq!(gaussian_factors(239, 1), (1, vec![((1, 1), 1), ((3, -2), 4)]));
+        // 3 is a Gaussian prime, 2 = -i (1 + i)^2
+        assert_eq!(gaussian_factors(3, 0), (0, vec![((3, 0), 1)]));
+        assert_eq!(gaussian_factors(2, 0), (3, vec![((1, 1), 2)]));
+    }
+
+    #[test]
+    fn machin_multiple_of_known_formulas() {
+        // Machin, Euler, Hermann and Gauss
+        assert_eq!(machin_multiple(&[(4, 5), (-1, 239)]), Some(1));
+        assert_eq!(machin_multiple(&[(1, 2), (1, 3)]), Some(1));
+        assert_eq!(machin_multiple(&[(2, 3), (1, 7)]), Some(1));
+        assert_eq!(machin_multiple(&[(12, 18), (8, 57), (-5, 239)]), Some(1));
+        // Multiples, and the identity atan(1/2) - atan(1/3) - atan(1/7) = 0
+        assert_eq!(machin_multiple(&[(8, 5), (-2, 239)]), Some(2));
+        assert_eq!(machin_multiple(&[(1, 2), (-1, 3), (-1, 7)]), Some(0));
+        assert_eq!(machin_multiple(&[(-4, 5), (1, 239)]), Some(-1));
+    }
+
+    #[test]
+    fn machin_multiple_rejects_invalid_formulas() {
+        assert_eq!(machin_multiple(&[(4, 5), (-1, 238)]), None);
+        assert_eq!(machin_multiple(&[(3, 5), (-1, 239)]), None);
+        assert_eq!(machin_multiple(&[(1, 2), (1, 5)]), None);
+    }
+
+    #[test]
+    fn arguments_of_machin_factors_give_pi() {
+        // 4 arg(5 + i) - arg(239 + i) = arg(2 + 2i) = pi/4
+        let limbs = 192 / Digit::BITS as usize;
+        let scaled = [(16, gaussian_arg(5, 1, limbs)), (-4, gaussian_arg(239, 1, limbs))];
+        let (pi, int_part) = combine_arctans(&scaled);
+        assert_eq!(int_part, 3);
+        let digits: String = pi.decimal_chunks().collect();
+        assert!(digits.starts_with("14159265358979323846264338327950288"), "{}", digits);
+    }
+
+    #[test]
+    fn ataninv_rational_agrees_with_ataninv_scalar_until() {
+        for x in [2, 5, 239] {
+            for limbs in [1, 3, 8] {
+                let (expected, _) = ataninv_scalar_until(x, limbs, Series::Atan, None);
+                let result = ataninv_rational(1, x, limbs);
+                assert!(result.agreement(&expected) >= limbs - 1, "x = {}, {} limbs", x, limbs);
+            }
+        }
+        // atan(2/3) = atan(1/2) + atan(1/8), since (2 + i)(8 + i) = 5 (3 + 2i)
+        let limbs = 3;
+        let mut sum = ataninv_rational(1, 2, limbs);
+        sum.wrapping_add_assign(&ataninv_rational(1, 8, limbs));
+        assert!(ataninv_rational(2, 3, limbs).agreement(&sum) >= limbs - 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "atan(3/3) is not in (0, pi/4)")]
+    fn ataninv_rational_rejects_one() {
+        ataninv_rational(3, 3, 2);
+    }
+}
@@ -0,0 +1,77 @@
+/*
+ * pi from the Gregory-Leibniz series pi/4 = 1 - 1/3 + 1/5 - ..., accelerated with Euler's
+ * transform, for comparison with the arctan formulas.
+ *
+ * The partial sums S(n) of the plain series only gain about one digit per tenfold number of terms.
+ * Replacing the partial sums S(0), ..., S(L) by the means of neighbors, (S(n) + S(n+1))/2, cancels
+ * most of the alternating error, and repeating this L times leaves the single value
+ * sum over n of binomial(L, n) S(n) / 2^L. This is the partial sum of Euler's transform of the
+ * series, pi/4 = 1/2 sum over n of n! / (2n+1)!!, whose terms halve from one to the next. So each
+ * level of acceleration adds about one bit, and levels has to be about log2(10) = 3.32 times the
+ * number of decimal digits. With fewer levels, only the first levels / 3.32 digits are correct.
+ *
+ * All L+1 partial sums are kept, and the averaging passes take O(L^2) additions of full Numbers,
+ * so this is only practical for a few thousand digits.
+ */
+use super::*;
+use super::computation::limbs_for_precision;
+
+#[allow(dead_code)]
+pub fn compute_pi_leibniz_accelerated(digits: usize, levels: usize) -> Number {
+    // Fractional part of pi from levels + 1 partial sums of the series averaged levels times,
+    // with the precision of compute_pi. To stay below one, everything is computed for pi/8, the
+    // partial sums of 1/2 - 1/6 + 1/10 - ..., and the result is multiplied by 8 at the end. Each
+    // average truncates by less than one unit in the last limb, which the guard limb covers.
+    let limbs = limbs_for_precision(digits);
+    let mut sums: Vec<Number> = Vec::with_capacity(levels + 1);
+    let mut sum: Number = Number::zero_with_len(limbs);
+    for k in 0..=levels {
+        let term: Number = Number::from_inv_with_len(2 * (2*k as Digit + 1), limbs);
+        if k % 2 == 0 {
+            sum.wrapping_add_assign(&term);
+        } else {
+            sum.sub_assign(&term);
+        }
+        sums.push(sum.clone());
+    }
+    // After each pass, there is one mean less
+    for len in (1..=levels).rev() {
+        for n in 0..len {
+            let (head, tail) = sums.split_at_mut(n + 1);
+            head[n].wrapping_add_assign(&tail[0]);
+            head[n] /= 2;
+        }
+    }
+    let mut pi = sums.swap_remove(0);
+    let int_part = pi.wrapping_mul_small(8);
+    assert_eq!(int_part, 3, "Integer part of pi is wrong");
+    pi
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PI_DECIMALS: &str = "141592653589793238462643383279502884197169399375105820974944";
+
+    fn correct_decimals(pi: &Number) -> usize {
+        pi.decimal_chunks().collect::<String>().chars().zip(PI_DECIMALS.chars())
+            .take_while(|(a, b)| a == b)
+            .count()
+    }
+
+    #[test]
+    fn each_level_adds_about_one_bit() {
+        // levels / log2(10) digits are correct, which is between levels * 3/10 and levels / 3
+        for levels in [10, 20, 40, 80, 160] {
+            let correct = correct_decimals(&compute_pi_leibniz_accelerated(60, levels));
+            assert!(levels * 3 / 10 <= correct && correct <= levels / 3 + 2,
+                    "{} correct decimals with {} levels", correct, levels);
+        }
+    }
+
+    #[test]
+    fn enough_levels_give_all_digits() {
+        assert_eq!(correct_decimals(&compute_pi_leibniz_accelerated(60, 200)), 60);
+    }
+}
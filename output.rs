@@ -0,0 +1,369 @@
+/*
+ * Writing the computed value to files in the formats selected with --output path:format. The
+ * Number only holds the fractional part, the integer part is passed separately.
+ *
+ * decimal: The integer part, a dot and the decimal digits of the fraction, followed by a newline.
+ *          The digits are streamed to the file.
+ * raw:     The digits of the Number, most significant first, each as big endian bytes.
+ * digest:  The SHA-256 of the raw format as hexadecimal string, followed by a newline.
+ * ycruncher: Like decimal, but without the newline at the end, like the digit files of
+ *          y-cruncher, so both can be compared byte by byte. With ycruncher=width, a newline
+ *          follows every width digits after the dot, except at the very end.
+ * terminal: Like decimal, but in groups of TERMINAL_GROUP digits that are wrapped to lines of at
+ *          most width characters, with the lines after the first indented below the first digit.
+ *          With terminal=width, the width is given. Otherwise, if the file is a terminal, like
+ *          /dev/stdout, it is COLUMNS if set and the width of the terminal if not, and 80 for any
+ *          other file.
+ * mpfr:    The integer part, a dot and the digits of the fraction in lowercase hexadecimal,
+ *          Digit::BITS / 4 per limb. There is no newline, since mpfr_set_str rejects anything
+ *          after the number. A C program reads it into f with mpfr_set_str(f, buffer, 16,
+ *          MPFR_RNDN), where f needs Digit::BITS * limbs bits for the fraction plus the ones of
+ *          the integer part to hold it exactly.
+ * gmp:     The big integer floor(value 2^(Digit::BITS limbs)) in the layout of mpz_import with
+ *          order = -1 and endian = -1: the limbs least significant first, each as little endian
+ *          bytes of sizeof(Digit), where the last one is the integer part, so there are limbs + 1.
+ *          mpz_import(z, limbs + 1, -1, sizeof(Digit), -1, 0, data) reads it, and
+ *          mpfr_set_z_2exp(f, z, -Digit::BITS * limbs, MPFR_RNDN) turns it into the value.
+ */
+use super::*;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::str::FromStr;
+
+// Digits per group of the terminal format
+const TERMINAL_GROUP: usize = 10;
+
+// Line width of the terminal format if it is not given and not found out from the terminal
+const DEFAULT_WIDTH: usize = 80;
+
+pub enum Format {
+    Decimal,
+    Raw,
+    Digest,
+    YCruncher(Option<usize>),
+    Terminal(Option<usize>),
+    Mpfr,
+    Gmp,
+}
+
+pub struct OutputSpec {
+    path: String,
+    format: Format,
+}
+
+impl FromStr for OutputSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        // The format is after the last colon, so paths may contain colons.
+        let (path, format) = s.rsplit_once(':')
+            .ok_or_else(|| format!("Output '{}' is not of the form path:format", s))?;
+        let format = match format {
+            "decimal" => Format::Decimal,
+            "raw" => Format::Raw,
+            "digest" => Format::Digest,
+            "ycruncher" => Format::YCruncher(None),
+            "terminal" => Format::Terminal(None),
+            "mpfr" => Format::Mpfr,
+            "gmp" => Format::Gmp,
+            _ => {
+                let (name, width) = format.split_once('=')
+                    .ok_or_else(|| format!("Unknown output format '{}'", format))?;
+                let width = width.parse().ok().filter(|&width| width > 0)
+                    .ok_or_else(|| format!("Invalid line width in '{}'", format))?;
+                match name {
+                    "ycruncher" => Format::YCruncher(Some(width)),
+                    "terminal" => Format::Terminal(Some(width)),
+                    _ => return Err(format!("Unknown output format '{}'", format)),
+                }
+            },
+        };
+        Ok(OutputSpec { path: path.to_string(), format })
+    }
+}
+
+impl fmt::Display for OutputSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The same form as parsed by from_str
+        let format = match self.format {
+            Format::Decimal => "decimal".to_string(),
+            Format::Raw => "raw".to_string(),
+            Format::Digest => "digest".to_string(),
+            Format::YCruncher(None) => "ycruncher".to_string(),
+            Format::YCruncher(Some(width)) => format!("ycruncher={}", width),
+            Format::Terminal(None) => "terminal".to_string(),
+            Format::Terminal(Some(width)) => format!("terminal={}", width),
+            Format::Mpfr => "mpfr".to_string(),
+            Format::Gmp => "gmp".to_string(),
+        };
+        write!(f, "{}:{}", self.path, format)
+    }
+}
+
+impl<S: LimbStore> Number<S> {
+    pub fn write_raw(&self, w: &mut impl Write) -> io::Result<()> {
+        for i in 0..self.len() {
+            w.write_all(&self.digits.get(i).to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn digest(&self) -> String {
+        // SHA-256 of the raw format
+        let mut hasher = Sha256::new();
+        self.write_raw(&mut hasher).unwrap();
+        hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn count_ones(&self) -> u64 {
+        // Number of one bits in all limbs. Much weaker than the digest, but cheap enough to check
+        // after every copy or serialization, and the same for both digit sizes.
+        (0..self.len()).map(|i| self.digits.get(i).count_ones() as u64).sum()
+    }
+
+    pub fn to_mpfr_hex(&self) -> String {
+        // The value as unsigned fraction in the mpfr format, with 0 as integer part
+        format!("0.{}", self.to_hex_string())
+    }
+
+    pub fn write_gmp_limbs(&self, int_part: Digit, w: &mut impl Write) -> io::Result<()> {
+        // The gmp format, which is the raw format in reverse byte order with the integer part
+        for i in (0..self.len()).rev() {
+            w.write_all(&self.digits.get(i).to_le_bytes())?;
+        }
+        w.write_all(&int_part.to_le_bytes())
+    }
+}
+
+impl OutputSpec {
+    pub fn write(&self, int_part: Digit, frac: &Number) -> io::Result<()> {
+        let file = File::create(&self.path)?;
+        let width = match self.format {
+            Format::Terminal(width) => width.unwrap_or_else(|| terminal_width(&file)),
+            _ => DEFAULT_WIDTH,
+        };
+        let mut w = BufWriter::new(file);
+        match self.format {
+            Format::Decimal => {
+                write!(w, "{}.", int_part)?;
+                frac.write_decimal(&mut w)?;
+                writeln!(w)?;
+            },
+            Format::Raw => frac.write_raw(&mut w)?,
+            Format::Digest => writeln!(w, "{}", frac.digest())?,
+            Format::Terminal(_) => {
+                let prefix = format!("{}.", int_part);
+                // At least one group per line, even if it does not fit
+                let groups = ((width + 1).saturating_sub(prefix.len()) / (TERMINAL_GROUP + 1))
+                    .max(1);
+                let line = groups * TERMINAL_GROUP;
+                w.write_all(prefix.as_bytes())?;
+                // Digits written so far
+                let mut pos = 0;
+                for chunk in frac.decimal_chunks() {
+                    for &digit in chunk.as_bytes() {
+                        if pos > 0 && pos % line == 0 {
+                            writeln!(w)?;
+                            write!(w, "{:1$}", "", prefix.len())?;
+                        } else if pos > 0 && pos % TERMINAL_GROUP == 0 {
+                            w.write_all(b" ")?;
+                        }
+                        w.write_all(&[digit])?;
+                        pos += 1;
+                    }
+                }
+                writeln!(w)?;
+            },
+            Format::Mpfr => write!(w, "{:x}{}", int_part, &frac.to_mpfr_hex()[1..])?,
+            Format::Gmp => frac.write_gmp_limbs(int_part, &mut w)?,
+            Format::YCruncher(width) => {
+                write!(w, "{}.", int_part)?;
+                let width = width.unwrap_or(usize::MAX);
+                // Digits on the current line
+                let mut column = 0;
+                for chunk in frac.decimal_chunks() {
+                    let mut chunk = chunk.as_bytes();
+                    while !chunk.is_empty() {
+                        if column == width {
+                            writeln!(w)?;
+                            column = 0;
+                        }
+                        let len = min(width - column, chunk.len());
+                        w.write_all(&chunk[..len])?;
+                        chunk = &chunk[len..];
+                        column += len;
+                    }
+                }
+            },
+        }
+        w.flush()
+    }
+}
+
+fn terminal_width(file: &File) -> usize {
+    // Width of the terminal format without a given one: COLUMNS if it is set, so it can override
+    // the size of the terminal, which is asked next, and DEFAULT_WIDTH if the file is no terminal.
+    if !file.is_terminal() {
+        return DEFAULT_WIDTH;
+    }
+    env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok())
+        .filter(|&width| width > 0)
+        .or_else(|| window_columns(file))
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+// The request of ioctl that queries the size of a terminal into a Winsize
+#[cfg(target_os = "linux")]
+const TIOCGWINSZ: std::ffi::c_ulong = 0x5413;
+#[cfg(target_os = "macos")]
+const TIOCGWINSZ: std::ffi::c_ulong = 0x40087468;
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn window_columns(file: &File) -> Option<usize> {
+    // Number of columns of the terminal file is, or None if it is no terminal
+    use std::ffi::{c_int, c_ulong};
+    use std::os::fd::AsRawFd;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Winsize {
+        ws_row: u16,
+        ws_col: u16,
+        ws_xpixel: u16,
+        ws_ypixel: u16,
+    }
+
+    extern "C" {
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+
+    let mut size = Winsize::default();
+    // Safe since size outlives the call and has the layout of struct winsize
+    let result = unsafe { ioctl(file.as_raw_fd(), TIOCGWINSZ, &mut size as *mut Winsize) };
+    (result == 0 && size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn window_columns(_file: &File) -> Option<usize> {
+    // Without the request for the platform, only COLUMNS tells the width
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_path(name: &str) -> String {
+        env::temp_dir().join(format!("picalc-{}-{}", std::process::id(), name))
+            .to_string_lossy().into_owned()
+    }
+
+    fn write_terminal(width: usize) -> String {
+        // 1/7 with two limbs in the terminal format with the given width
+        let path = temp_path(&format!("terminal-{}", width));
+        let spec: OutputSpec = format!("{}:terminal={}", path, width).parse().unwrap();
+        spec.write(0, &Number::from_inv_with_len(7, 2)).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        content
+    }
+
+    #[test]
+    fn terminal_format_wraps_groups() {
+        let value: Number = Number::from_inv_with_len(7, 2);
+        let digits: String = value.decimal_chunks().collect();
+        for width in [1, 12, 13, 24, 25, 40, 80] {
+            let content = write_terminal(width);
+            let lines: Vec<&str> = content.lines().collect();
+            // As many groups as fit after the prefix, but at least one
+            let groups = ((width + 1).saturating_sub(2) / (TERMINAL_GROUP + 1)).max(1);
+            for (i, line) in lines.iter().enumerate() {
+                let (prefix, rest) = line.split_at(2);
+                assert_eq!(prefix, if i == 0 { "0." } else { "  " }, "width {}", width);
+                let line_groups: Vec<&str> = rest.split(' ').collect();
+                assert!(line_groups.iter().all(|group| group.len() == TERMINAL_GROUP
+                                               || i + 1 == lines.len()));
+                if i + 1 < lines.len() {
+                    assert_eq!(line_groups.len(), groups, "width {}", width);
+                    assert!(line.len() <= width.max(2 + TERMINAL_GROUP), "width {}", width);
+                }
+            }
+            let found: String = lines.iter().flat_map(|line| line[2..].split(' ')).collect();
+            assert_eq!(found, digits, "width {}", width);
+            assert!(content.ends_with('\n'));
+        }
+    }
+
+    #[test]
+    fn terminal_width_without_terminal() {
+        // Neither COLUMNS nor the size of a terminal matter for a regular file
+        let path = temp_path("width");
+        let file = File::create(&path).unwrap();
+        assert_eq!(window_columns(&file), None);
+        assert_eq!(terminal_width(&file), DEFAULT_WIDTH);
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn from_raw(raw: &[u8]) -> Number {
+        let digits: Vec<Digit> = raw.chunks(mem::size_of::<Digit>())
+            .map(|chunk| Digit::from_be_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Number::from_digits(&digits)
+    }
+
+    #[test]
+    fn count_ones_survives_raw_round_trip() {
+        // The same 128 bits for both digit sizes, floor(2^128 / 7) = 0x2492...49
+        let limbs = 128 / Digit::BITS as usize;
+        let x: Number = Number::from_inv_with_len(7, limbs);
+        assert_eq!(x.count_ones(), (u128::MAX / 7).count_ones() as u64);
+        let mut raw = Vec::new();
+        x.write_raw(&mut raw).unwrap();
+        assert_eq!(raw.len(), 16);
+        let read = from_raw(&raw);
+        assert_eq!((read.digits.clone(), read.count_ones()), (x.digits.clone(), x.count_ones()));
+        // A flipped bit changes the count by one
+        let mut corrupted = raw.clone();
+        corrupted[9] ^= 0x10;
+        assert_eq!(from_raw(&corrupted).count_ones().abs_diff(x.count_ones()), 1);
+        let zero: Number = Number::zero_with_len(3);
+        assert_eq!(zero.count_ones(), 0);
+        let digits: Number = Number::from_digits(&[Digit::MAX, 0, 5]);
+        assert_eq!(digits.count_ones(), Digit::BITS as u64 + 2);
+    }
+
+    fn write_format(format: &str, int_part: Digit, value: &Number) -> Vec<u8> {
+        let path = temp_path(format);
+        let spec: OutputSpec = format!("{}:{}", path, format).parse().unwrap();
+        assert_eq!(spec.to_string(), format!("{}:{}", path, format));
+        spec.write(int_part, value).unwrap();
+        let content = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        content
+    }
+
+    #[test]
+    fn mpfr_and_gmp_layouts() {
+        // 3 + floor(2^128 / 7) 2^-128, the same 128 bits for both digit sizes
+        let value: Number = Number::from_inv_with_len(7, 128 / Digit::BITS as usize);
+        let bits = u128::MAX / 7;
+        // Lowercase hexadecimal after the integer part, without a newline
+        let mpfr = write_format("mpfr", 3, &value);
+        assert_eq!(String::from_utf8(mpfr).unwrap(), format!("3.{:032x}", bits));
+        assert_eq!(write_format("mpfr", 0x2a, &value)[..3], *b"2a.");
+        // Limbs least significant first in little endian bytes, so the fraction is one little
+        // endian u128, followed by the integer part as one more limb
+        let gmp = write_format("gmp", 3, &value);
+        assert_eq!(gmp.len(), 16 + mem::size_of::<Digit>());
+        assert_eq!(u128::from_le_bytes(gmp[..16].try_into().unwrap()), bits);
+        assert_eq!(Digit::from_le_bytes(gmp[16..].try_into().unwrap()), 3);
+        // The same bytes as the raw format reversed, apart from the integer part
+        let mut raw = write_format("raw", 3, &value);
+        raw.reverse();
+        assert_eq!(gmp[..16], raw[..]);
+    }
+}
@@ -1,64 +1,347 @@
 use std::ops::DivAssign;
-use std::cmp::{min,max};
+use std::panic::{self, AssertUnwindSafe};
+use std::cmp::min;
+use std::mem;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
 use std::env;
+use std::time::{Duration, Instant};
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
 use std::vec::Vec;
-use crossbeam::{channel::{unbounded,Receiver,Sender}};
+use crossbeam::{channel::{bounded,select,unbounded,Receiver,Sender}};
 
-const DIGITS: usize = 20000;
+// Panic unless the Numbers a and b agree in all digits but the last guard_limbs of the shorter
+// one, for checks of one implementation against another. The message says where the decimal
+// digits start to differ, counted from one after the point, followed by the optional format
+// arguments and the diff_report. Defined before the modules, so they can use it too. Release
+// builds have no uses so far, only the refterm check of ataninv_threaded2_until.
+#[allow(unused_macros)]
+macro_rules! assert_numbers_agree {
+    ($a:expr, $b:expr, $guard_limbs:expr) => {
+        assert_numbers_agree!($a, $b, $guard_limbs, "")
+    };
+    ($a:expr, $b:expr, $guard_limbs:expr, $($arg:tt)+) => {{
+        let (a, b) = (&$a, &$b);
+        let required = min(a.len(), b.len()).saturating_sub($guard_limbs);
+        if a.agreement(b) < required {
+            panic!("{} and {} differ before digit {}, from decimal digit {} on. {}\n{}",
+                   stringify!($a), stringify!($b), required, a.decimal_agreement(b) + 1,
+                   format!($($arg)+), a.diff_report(b));
+        }
+    }};
+}
+
+mod aligned;
+mod atan_scratch;
+mod bench;
+mod checkpoint;
+mod computation;
+mod config;
+mod continued_fraction;
+mod decimal;
+mod decimal_limbs;
+mod decimal_split;
+mod distributed;
+mod division;
+mod formula_file;
+#[cfg(fuzzing)]
+pub mod fuzzing;
+mod gaussian;
+mod leibniz;
+mod output;
+mod profile;
+mod ramanujan;
+mod scan;
+mod stream;
+use config::Config;
+use output::OutputSpec;
+use profile::Op;
+
+// The digit size can be switched to u32 with the "u32-digits" feature, for targets where 128 bit
+// arithmetic is slow. DIGITS is adjusted so the precision in bits stays the same.
+#[cfg(not(feature = "u32-digits"))]
 type Digit = u64;
+#[cfg(not(feature = "u32-digits"))]
 type Double = u128;
+#[cfg(feature = "u32-digits")]
+type Digit = u32;
+#[cfg(feature = "u32-digits")]
+type Double = u64;
+#[cfg(not(fuzzing))]
+const DIGITS: usize = 20000 * 64 / Digit::BITS as usize;
+// The fuzz targets need many runs per second, so they use a small precision
+#[cfg(fuzzing)]
+const DIGITS: usize = 4 * 64 / Digit::BITS as usize;
+
+// Digits of the quotient that add_div_assign keeps at a time, small enough to stay in the L1
+// cache while the block is added.
+const ADD_DIV_BLOCK: usize = 64;
+
+// Different digits that Number::diff_report lists at most
+const DIFF_REPORT_LIMIT: usize = 8;
+
+/*
+ * LimbStore abstracts the storage of the digits of a Number, so the arithmetic below can run on
+ * other backends (mmap, aligned for SIMD, arena-allocated) without being duplicated. Number is
+ * generic over it, so every backend gets its own monomorphized copy and the default Vec backend
+ * compiles to the same code as indexing the Vec directly.
+ */
+trait LimbStore: Clone {
+    // Create a store of len digits, all set to zero.
+    fn zeroed(len: usize) -> Self;
+    fn len(&self) -> usize;
+    fn get(&self, i: usize) -> Digit;
+    fn set(&mut self, i: usize, val: Digit);
+}
+
+impl LimbStore for Vec<Digit> {
+    fn zeroed(len: usize) -> Self {
+        vec![0; len]
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    #[inline]
+    fn get(&self, i: usize) -> Digit {
+        self[i]
+    }
+
+    #[inline]
+    fn set(&mut self, i: usize, val: Digit) {
+        self[i] = val;
+    }
+}
+
+// Digits that limbs_differ compares before it checks for a difference
+const DIFF_BLOCK: usize = 64;
+
+// Error of the checked arithmetic methods if the result would not be representable.
+#[derive(Debug)]
+struct Overflow;
+
+// Return type of mul4, mul_small and add_assign. By default, they wrap around like wrapping_mul4,
+// wrapping_mul_small and wrapping_add_assign. With the checked feature, they are checked_mul4,
+// checked_mul_small and checked_add_assign, which return Overflow instead of leaving
+// [-0.5, 0.5). The computations of this crate rely on the wrapping, so they always call the
+// wrapping methods, whatever the feature.
+#[cfg(not(feature = "checked"))]
+type Checked<T> = T;
+#[cfg(feature = "checked")]
+type Checked<T> = Result<T, Overflow>;
+
+// The checked or the wrapping call, as selected by the checked feature. Only the selected one is
+// compiled, so without the feature, mul4 and the others are exactly the wrapping methods.
+#[cfg(not(feature = "checked"))]
+macro_rules! overflow_checked {
+    ($checked:expr, $wrapping:expr) => { $wrapping };
+}
+#[cfg(feature = "checked")]
+macro_rules! overflow_checked {
+    ($checked:expr, $wrapping:expr) => { $checked };
+}
+
+// Error if Numbers of different length are combined. The fields are only shown with Debug.
+#[allow(dead_code)]
+#[derive(Debug)]
+struct LengthMismatch {
+    expected: usize,
+    found: usize,
+}
 
 /*
  * Number represents a number between -0.5 (incl.) and 0.5 (excl.). It uses fixed precision
- * with DIGITS digits, each of base 2^64. For DIGITS = 10_000, this means 160_000 hexadecimal or
- * 640_000 binary digits. We only implement methods needed for the algorithm, which includes
+ * with DIGITS digits, each of base 2^Digit::BITS. For DIGITS = 10_000 and u64 digits, this means
+ * 160_000 hexadecimal or 640_000 binary digits. We only implement methods needed for the
+ * algorithm, which includes
  * a) addition and subtraction and
- * b) multiplication by 4 and division by a small (u64) number (only for positive Numbers).
+ * b) multiplication by 4 and division by a small (Digit) number (only for positive Numbers).
  */
 #[derive(Clone)]
-struct Number {
+struct Number<S: LimbStore = Vec<Digit>> {
     zeros: usize, // At least the first N digits are zeros
-    digits: Vec<Digit>,
+    digits: S,
 }
 
-impl Number {
-    fn zero() -> Number {
+impl<S: LimbStore> Number<S> {
+    fn zero() -> Self {
         // Create Number that equals zero.
+        Self::zero_with_len(DIGITS)
+    }
+
+    fn zero_with_len(len: usize) -> Self {
+        // Create Number with len digits that equals zero.
         Number {
-            digits: vec![0; DIGITS],
-            zeros: DIGITS,
+            digits: S::zeroed(len),
+            zeros: len,
         }
     }
 
-    fn from_inv(x: Digit) -> Number {
+    fn len(&self) -> usize {
+        // Number of digits
+        self.digits.len()
+    }
+
+    fn from_inv(x: Digit) -> Self {
         // Create number as inverse of given digit. Since 1.0 can not be represented, we can not
         // simply use the existing division method, although the code is quite similar.
+        Self::from_inv_with_len(x, DIGITS)
+    }
+
+    fn from_inv_with_len(x: Digit, len: usize) -> Self {
+        // Same as from_inv, with len digits
+        let mut result = Self::zero_with_len(len);
+        result.set_to_inv(x);
+        result
+    }
+
+    fn set_to_inv(&mut self, x: Digit) {
+        // self = 1/x, keeping the length. Like inv_digits, but the digits are written directly to
+        // the Number, so no second buffer is needed.
         let x = x as Double;
         let mut rem: Double = 1;
-        let mut result = Number::zero();
-        for i in 0..DIGITS {
+        for i in 0..self.len() {
             let nom = rem << Digit::BITS;
-            result.digits[i] = (nom / x) as Digit;
+            self.digits.set(i, (nom / x) as Digit);
             rem = nom % x;
         }
+        self.update_zeros();
+    }
+
+    fn set_to_zero(&mut self) {
+        // self = 0, keeping the length. The digits before zeros already are.
+        for i in self.zeros..self.len() {
+            self.digits.set(i, 0);
+        }
+        self.zeros = self.len();
+    }
+
+    #[allow(dead_code)]
+    fn from_u64_fraction(num: u64, den: u64) -> Self {
+        // num/den with DIGITS digits, see from_u64_fraction_with_len
+        Self::from_u64_fraction_with_len(num, den, DIGITS)
+    }
+
+    fn from_u64_fraction_with_len(num: u64, den: u64, len: usize) -> Self {
+        // num/den with len digits, truncated, by long division like from_inv_with_len. The
+        // value must be in the representable range, so num/den < 1/2. This always divides in
+        // u128, since num and den do not fit into u32 digits.
+        assert!(num < den - den / 2, "{}/{} is not below 1/2", num, den);
+        let mut result = Self::zero_with_len(len);
+        let den = den as u128;
+        let mut rem = num as u128;
+        for i in 0..len {
+            let nom = rem << Digit::BITS;
+            result.digits.set(i, (nom / den) as Digit);
+            rem = nom % den;
+        }
         result.update_zeros();
         result
     }
 
-    fn copy_from(&mut self, rhs: &Number) {
-        for i in 0..DIGITS {
-            self.digits[i] = rhs.digits[i];
+    #[allow(dead_code)]
+    fn from_f64(x: f64) -> Option<Self> {
+        // x with DIGITS digits, see from_f64_with_len
+        Self::from_f64_with_len(x, DIGITS)
+    }
+
+    fn from_f64_with_len(x: f64, len: usize) -> Option<Self> {
+        // x as signed value in [-0.5, 0.5) with len digits, or None if it is outside or not
+        // finite, for example as the start of a Newton iteration. Every f64 in the range is a
+        // binary fraction, so its bits are copied exactly, as far as len digits reach, and the
+        // ones below are truncated towards zero. A negative x is stored as 1 + x like after
+        // negate.
+        if !x.is_finite() || !(-0.5..0.5).contains(&x) {
+            return None;
+        }
+        // |x| = mantissa * 2^exp
+        let bits = x.abs().to_bits();
+        let (biased, fraction) = ((bits >> 52) as i64, bits & ((1 << 52) - 1));
+        let (mantissa, exp) = if biased == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | 1 << 52, biased - 1075)
+        };
+        let mut result = Self::zero_with_len(len);
+        for j in (0..53).filter(|&j| mantissa >> j & 1 == 1) {
+            // The bit with the value 2^(exp+j) = 2^-(pos+1), which is below the binary point
+            // since |x| < 1, so it is bit pos counted from the first one of the first digit
+            let pos = (-(exp + j)) as usize - 1;
+            let i = pos / Digit::BITS as usize;
+            if i < len {
+                let bit = Digit::BITS as usize - 1 - pos % Digit::BITS as usize;
+                result.digits.set(i, result.digits.get(i) | 1 << bit);
+            }
+        }
+        result.update_zeros();
+        if x < 0.0 {
+            result.negate();
+        }
+        Some(result)
+    }
+
+    #[allow(dead_code)]
+    fn from_inv_parallel(x: Digit, nthreads: usize) -> Self {
+        // Same as from_inv, but split into one block of digits per thread. The remainder before
+        // digit i is 2^(Digit::BITS*i) mod x, so each block can compute its initial remainder by
+        // modular exponentiation instead of waiting for the previous block.
+        let block = DIGITS.div_ceil(nthreads.max(1));
+        let blocks: Vec<Vec<Digit>> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..DIGITS).step_by(block)
+                .map(|start| scope.spawn(move || {
+                    let mut digits = vec![0; min(block, DIGITS - start)];
+                    inv_digits(x, pow_mod(x, start), &mut digits);
+                    digits
+                }))
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        Self::from_digits(&blocks.concat())
+    }
+
+    fn copy_from(&mut self, rhs: &Self) {
+        debug_assert_eq!(self.len(), rhs.len(), "copy_from between Numbers of different length");
+        for i in 0..self.len() {
+            self.digits.set(i, rhs.digits.get(i));
         }
         self.zeros = rhs.zeros;
     }
 
+    #[allow(dead_code)]
+    fn try_clone_into(&self, target: &mut Self) -> Result<(), LengthMismatch> {
+        // Copy the value into target without allocating, failing if the lengths do not match.
+        if self.len() != target.len() {
+            return Err(LengthMismatch { expected: target.len(), found: self.len() });
+        }
+        target.copy_from(self);
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    fn extend_precision(&mut self, new_len: usize) {
+        // Append zero digits so the Number has new_len digits, without changing its value. This
+        // allows continuing a computation at a higher precision.
+        assert!(new_len >= self.len(), "extend_precision can not reduce the precision");
+        let mut digits = S::zeroed(new_len);
+        for i in 0..self.len() {
+            digits.set(i, self.digits.get(i));
+        }
+        if self.is_zero() {
+            self.zeros = new_len;
+        }
+        self.digits = digits;
+    }
+
     fn update_zeros_min(&mut self, min: usize) {
         // Update how many leading digits are zeros, under the assumption that there are at least
         // min
-        self.zeros = DIGITS;
-        for i in min..DIGITS {
-            if self.digits[i] != 0 {
+        self.zeros = self.len();
+        for i in min..self.len() {
+            if self.digits.get(i) != 0 {
                 self.zeros = i;
                 break;
             }
@@ -70,126 +353,1007 @@ impl Number {
     }
 
     fn is_zero(&self) -> bool {
-        self.zeros == DIGITS
+        self.zeros == self.len()
+    }
+
+    fn agreement(&self, other: &Self) -> usize {
+        // Number of leading digits that are equal in both Numbers
+        (0..min(self.len(), other.len()))
+            .find(|&i| self.digits.get(i) != other.digits.get(i))
+            .unwrap_or(min(self.len(), other.len()))
+    }
+
+    #[allow(dead_code)]
+    fn limbs_differ(&self, other: &Self) -> bool {
+        // Whether the Numbers differ in any digit, or in length. The digits before both zeros are
+        // zero in both, and the others are compared in blocks of DIFF_BLOCK, whose XORs are ORed
+        // together without a branch per digit, so the compiler can vectorize them. For two
+        // equal Numbers of 20000 limbs, this takes 6 us, a loop that stops at the first different
+        // digit 17 us, and comparing the two Vecs, which only works for that LimbStore, 4 us.
+        // A difference in the first block is found in 20 ns instead of 4 ns.
+        if self.len() != other.len() {
+            return true;
+        }
+        let start = min(self.zeros, other.zeros);
+        (start..self.len()).step_by(DIFF_BLOCK).any(|block| {
+            (block..min(block + DIFF_BLOCK, self.len()))
+                .fold(0, |acc, i| acc | (self.digits.get(i) ^ other.digits.get(i))) != 0
+        })
+    }
+
+    fn wrapping_mul4(&mut self) -> Digit {
+        // Multiply value by 4. Returns the integer part that overflows, interpreting the value as
+        // an unsigned fraction in [0, 1) like mul_small.
+        self.mul_pow2(2)
+    }
+
+    #[allow(dead_code)]
+    fn mul4(&mut self) -> Checked<Digit> {
+        // wrapping_mul4, or checked_mul4 with the checked feature, see Checked
+        overflow_checked!(self.checked_mul4(), self.wrapping_mul4())
+    }
+
+    fn mul_pow2(&mut self, exp: u32) -> Digit {
+        // Multiply value by 2^exp, interpreting it as an unsigned fraction in [0, 1) like
+        // mul_small. Returns the integer part that overflows modulo 2^Digit::BITS, which is all of
+        // it for exp <= Digit::BITS.
+        let limbs = (exp / Digit::BITS) as usize;
+        let bits = exp % Digit::BITS;
+        let int_limbs = self.shift_limbs(limbs);
+        let int_bits = self.shl_bits(bits);
+        (int_limbs << bits) | int_bits
+    }
+
+    fn shift_limbs(&mut self, n: usize) -> Digit {
+        // Multiply value by 2^(Digit::BITS*n) by moving the digits n places up. Returns the lowest
+        // digit of the integer part that overflows.
+        if n == 0 {
+            return 0;
+        }
+        let int = if n <= self.len() { self.digits.get(n-1) } else { 0 };
+        for i in 0..self.len() {
+            self.digits.set(i, if i + n < self.len() { self.digits.get(i+n) } else { 0 });
+        }
+        self.update_zeros_min(self.zeros.saturating_sub(n));
+        int
+    }
+
+    fn shl_bits(&mut self, bits: u32) -> Digit {
+        // Multiply value by 2^bits with bits < Digit::BITS. Returns the integer part that
+        // overflows.
+        debug_assert!(bits < Digit::BITS, "shl_bits by a whole digit or more");
+        if bits == 0 || self.is_zero() {
+            return 0;
+        }
+        let int = self.digits.get(0) >> (Digit::BITS - bits);
+        for i in self.zeros.saturating_sub(1)..self.len() {
+            let next = if i + 1 < self.len() { self.digits.get(i+1) } else { 0 };
+            self.digits.set(i, (self.digits.get(i) << bits) | (next >> (Digit::BITS - bits)));
+        }
+        self.update_zeros_min(self.zeros.saturating_sub(1));
+        int
+    }
+
+    #[allow(dead_code)]
+    fn checked_mul4(&mut self) -> Result<Digit, Overflow> {
+        // Multiply value by 4 like wrapping_mul4, but return an error and leave the value
+        // unchanged if the result would leave the representable range instead of wrapping around.
+        // The result is representable if and only if the three leading bits are all equal.
+        let top = self.digits.get(0) >> (Digit::BITS - 3);
+        if top != 0 && top != 0b111 {
+            return Err(Overflow);
+        }
+        Ok(self.wrapping_mul4())
+    }
+
+    fn sign_bit(&self) -> Digit {
+        // 1 for the negative values of [-0.5, 0.5), 0 for the others
+        self.digits.get(0) >> (Digit::BITS - 1)
+    }
+
+    #[allow(dead_code)]
+    fn is_power_of_two_fraction(&self) -> Option<u32> {
+        // k if the value as unsigned fraction is exactly 2^-k, so 1 for 1/2, which means that
+        // exactly one bit is set, or None. Such a factor or divisor is a shift. The digits are
+        // scanned from zeros on, since mul_small may leave zeros before the first non-zero digit.
+        let first = (self.zeros..self.len()).find(|&i| self.digits.get(i) != 0)?;
+        if self.digits.get(first).count_ones() != 1
+                || (first+1..self.len()).any(|i| self.digits.get(i) != 0) {
+            return None;
+        }
+        Some(first as u32 * Digit::BITS + self.digits.get(first).leading_zeros() + 1)
+    }
+
+    fn approx_log2(&self) -> f64 {
+        // Approximate log2 of the absolute value in [-0.5, 0.5), from the two leading digits of
+        // its magnitude, or -inf for zero. The magnitude of a negative value is approximated by
+        // the complement of its digits, which is one unit in the last digit smaller.
+        if self.is_zero() {
+            return f64::NEG_INFINITY;
+        }
+        let neg = self.sign_bit() == 1;
+        let digit = |i: usize| {
+            let d = if i < self.len() { self.digits.get(i) } else { 0 };
+            if neg && i < self.len() { !d } else { d }
+        };
+        let bits = Digit::BITS as f64;
+        match (0..self.len()).find(|&i| digit(i) != 0) {
+            Some(i) => {
+                let lead = digit(i) as f64 + digit(i+1) as f64 / 2f64.powf(bits);
+                lead.log2() - bits * (i+1) as f64
+            },
+            // Only the last digit is one
+            None => -bits * self.len() as f64,
+        }
+    }
+
+    #[allow(dead_code)]
+    fn checked_mul_small(&mut self, m: Digit) -> Result<Digit, Overflow> {
+        // Multiply value by m like wrapping_mul_small, but in [-0.5, 0.5) like checked_mul4, and
+        // return an error and leave the value unchanged if the product is outside of it. As an
+        // unsigned fraction, a negative value v is v + 1, so wrapping_mul_small returns the
+        // integer part of v*m + m. The product is representable if and only if this is m - 1 plus
+        // the sign bit of the result for negative v, and the sign bit of the result negated for
+        // the others. Otherwise, the product of wrapping_mul_small is exact, so dividing it by m
+        // restores the value.
+        let neg = self.sign_bit();
+        let int = self.wrapping_mul_small(m);
+        if int as Double + self.sign_bit() as Double != neg as Double * m as Double {
+            self.add_int_div(int, m);
+            return Err(Overflow);
+        }
+        Ok(int)
+    }
+
+    #[allow(dead_code)]
+    fn checked_add_assign(&mut self, rhs: &Self) -> Result<(), Overflow> {
+        // self += rhs like wrapping_add_assign, but in [-0.5, 0.5) like checked_mul4, and return
+        // an error and leave the value unchanged if the sum is outside of it. That is the case if
+        // both have the same sign and the sum has the other one.
+        let neg = self.sign_bit();
+        self.wrapping_add_assign(rhs);
+        if neg == rhs.sign_bit() && self.sign_bit() != neg {
+            self.sub_assign(rhs);
+            return Err(Overflow);
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    fn saturating_add_assign(&mut self, rhs: &Self) {
+        // self += rhs in [-0.5, 0.5) like checked_add_assign, but a sum outside of it is clamped to
+        // the largest value below 0.5 or to -0.5 instead of returning an error. So there are three
+        // ways to handle an overflow:
+        // - wrapping_add_assign wraps around modulo one. This is right for the series, whose sums
+        //   are only needed modulo one, and for values that only leave the range in between, like
+        //   in combine_arctans and the decimal conversion. add_assign does the same unless the
+        //   checked feature is on, see Checked.
+        // - checked_add_assign fails, for values that must stay in range, where an overflow is a
+        //   bug or needs a different algorithm.
+        // - saturating_add_assign clamps, where a value beyond the range only means "very large",
+        //   like for a bound that is compared against later. The result is then no longer the
+        //   sum, so it can not be undone by subtracting rhs again.
+        let neg = self.sign_bit();
+        self.wrapping_add_assign(rhs);
+        if neg == rhs.sign_bit() && self.sign_bit() != neg {
+            // -0.5 is 100...0, the largest value below 0.5 is 011...1
+            let (top, rest) = if neg == 1 {
+                (1 << (Digit::BITS - 1), 0)
+            } else {
+                (Digit::MAX >> 1, Digit::MAX)
+            };
+            self.digits.set(0, top);
+            for i in 1..self.len() {
+                self.digits.set(i, rest);
+            }
+            self.zeros = 0;
+        }
+    }
+
+    #[allow(dead_code)]
+    fn is_at_upper_boundary(&self) -> bool {
+        // Whether the leading bit of the first digit is set. For a value that should be in
+        // [0, 0.5), like a sum of positive terms, this means it reached 0.5 or went beyond and
+        // wrapped around: the digits 100...0 are 0.5 itself, all other patterns with the leading
+        // bit set are values in (0.5, 1) as unsigned fractions, which are the negative values of
+        // the range [-0.5, 0.5) above. So a positive result that grew too large and a difference
+        // that dropped below zero are both detected, but not a value that wrapped by a whole one
+        // or more, like 1/3 after mul4.
+        self.digits.get(0) >> (Digit::BITS - 1) == 1
     }
 
-    fn mul4(&mut self) {
-        // Multiply value by 4
+    fn wrapping_mul_small(&mut self, m: Digit) -> Digit {
+        // Multiply value by m, interpreting it as an unsigned fraction in [0, 1), and return the
+        // integer part that overflows.
         let mut carry: Double = 0;
-        for i in (0..DIGITS).rev() {
-            carry += 4*self.digits[i] as Double;
-            self.digits[i] = carry as Digit;
+        for i in (self.zeros..self.len()).rev() {
+            carry += m as Double * self.digits.get(i) as Double;
+            self.digits.set(i, carry as Digit);
             carry >>= Digit::BITS;
         }
+        if self.zeros == 0 {
+            return carry as Digit;
+        }
+        // The carry fits into the leading zero digit
+        self.digits.set(self.zeros-1, carry as Digit);
+        self.update_zeros_min(self.zeros-1);
+        0
+    }
+
+    #[allow(dead_code)]
+    fn mul_small(&mut self, m: Digit) -> Checked<Digit> {
+        // wrapping_mul_small, or checked_mul_small with the checked feature, see Checked
+        overflow_checked!(self.checked_mul_small(m), self.wrapping_mul_small(m))
+    }
+
+    #[allow(dead_code)]
+    fn mul_small_then_add(&mut self, factor: Digit, addend: &Self) -> Digit {
+        // self = self * factor + addend in one pass, for a Horner step. Like in mul_small, both
+        // are interpreted as unsigned fractions and the integer part that overflows is returned.
+        // The digit of addend is added to the product together with the carry, which stays below
+        // factor + 1, so all of it fits into a Double.
+        let top = min(self.zeros, addend.zeros);
+        let mut carry: Double = 0;
+        for i in (top..self.len()).rev() {
+            let digit = self.digits.get(i);
+            carry += factor as Double * digit as Double + addend.digits.get(i) as Double;
+            self.digits.set(i, carry as Digit);
+            carry >>= Digit::BITS;
+        }
+        if top == 0 {
+            // Unlike in mul_small, zeros is kept exact if the leading digits become zero
+            self.update_zeros_min(0);
+            return carry as Digit;
+        }
+        self.digits.set(top-1, carry as Digit);
+        self.update_zeros_min(top-1);
+        0
+    }
+
+    fn combine_scaled(terms: &[(Digit, &Self)], len: usize) -> (Self, Double) {
+        // The sum over c*x for all pairs (c, x) with len digits each, interpreting each x as an
+        // unsigned fraction like mul_small, and the integer part of the sum. This is one pass over
+        // all terms instead of a mul_small into a copy and an add_assign for each one. The
+        // products of a digit position are added up split into their low and high digits, so
+        // neither sum overflows a Double, and the high digits and the carry out of the low ones
+        // go into the next position. The carry stays below the sum of the coefficients.
+        assert!(terms.iter().all(|(_, x)| x.len() == len), "Terms of different length");
+        let mut result = Self::zero_with_len(len);
+        let top = terms.iter().map(|(_, x)| x.zeros).min().unwrap_or(len);
+        let mut carry: Double = 0;
+        for i in (top..len).rev() {
+            let mut low = carry;
+            let mut high: Double = 0;
+            for (c, x) in terms {
+                let product = *c as Double * x.digits.get(i) as Double;
+                low += product as Digit as Double;
+                high += product >> Digit::BITS;
+            }
+            result.digits.set(i, low as Digit);
+            carry = (low >> Digit::BITS) + high;
+        }
+        // Above top, only the carry is left
+        for i in (0..top).rev() {
+            result.digits.set(i, carry as Digit);
+            carry >>= Digit::BITS;
+        }
+        result.update_zeros();
+        (result, carry)
+    }
+
+    fn from_digits(digits: &[Digit]) -> Self {
+        // Create a Number with the given digits
+        let mut result = Number { digits: S::zeroed(digits.len()), zeros: 0 };
+        for (i, &digit) in digits.iter().enumerate() {
+            result.digits.set(i, digit);
+        }
+        result.update_zeros();
+        result
+    }
+
+    #[allow(dead_code)]
+    fn mod_small(&self, m: Digit) -> Digit {
+        // The digits as one big integer, most significant first, modulo m. This is the value as
+        // an unsigned fraction in [0, 1), scaled by 2^(Digit::BITS*len), so it depends on the
+        // number of digits, and the representation of a negative value is taken as is. Numbers
+        // that are equal digit by digit have the same checksum for any m, and each digit changes
+        // it by a different multiple of a power of 2^Digit::BITS.
+        assert!(m > 0, "mod_small by zero");
+        let m = m as Double;
+        let mut rem: Double = 0;
+        for i in self.zeros..self.len() {
+            rem = ((rem << Digit::BITS) + self.digits.get(i) as Double) % m;
+        }
+        rem as Digit
+    }
+
+    #[allow(dead_code)]
+    fn mul(&self, rhs: &Self) -> Self {
+        // Product of both values, interpreting them as unsigned fractions in [0, 1) like
+        // mul_small. The result is truncated to the same number of digits. Digit i has the value
+        // 2^(-Digit::BITS*(i+1)), so the product of digits i and j goes to digit i+j+1 of the
+        // double-length product.
+        debug_assert_eq!(self.len(), rhs.len(), "mul between Numbers of different length");
+        let n = self.len();
+        let mut prod: Vec<Digit> = vec![0; 2*n];
+        for i in (self.zeros..n).rev() {
+            let a = self.digits.get(i) as Double;
+            let mut carry: Double = 0;
+            for j in (rhs.zeros..n).rev() {
+                carry += prod[i+j+1] as Double + a * rhs.digits.get(j) as Double;
+                prod[i+j+1] = carry as Digit;
+                carry >>= Digit::BITS;
+            }
+            // The rows below did not reach this far yet
+            prod[i+rhs.zeros] = carry as Digit;
+        }
+        // Truncate to the upper half
+        Self::from_digits(&prod[..n])
+    }
+
+    #[allow(dead_code)]
+    fn square(&self) -> Self {
+        // Same as self.mul(self), but the products of digits i and j with i != j appear twice, so
+        // they are only computed once and doubled, which saves about half of the multiplications.
+        let n = self.len();
+        let mut prod: Vec<Digit> = vec![0; 2*n];
+        for i in (self.zeros..n).rev() {
+            let a = self.digits.get(i) as Double;
+            let mut carry: Double = 0;
+            for j in (i+1..n).rev() {
+                carry += prod[i+j+1] as Double + a * self.digits.get(j) as Double;
+                prod[i+j+1] = carry as Digit;
+                carry >>= Digit::BITS;
+            }
+            prod[2*i+1] = carry as Digit;
+        }
+        // Double the cross products. They sum up to less than 1/2, so the leading bit is zero.
+        for i in 0..2*n-1 {
+            prod[i] = (prod[i] << 1) | (prod[i+1] >> (Digit::BITS - 1));
+        }
+        prod[2*n-1] <<= 1;
+        // Add the squares of the digits
+        let mut carry: Double = 0;
+        for i in (self.zeros..n).rev() {
+            let sq = self.digits.get(i) as Double * self.digits.get(i) as Double;
+            carry += prod[2*i+1] as Double + (sq as Digit) as Double;
+            prod[2*i+1] = carry as Digit;
+            carry >>= Digit::BITS;
+            carry += prod[2*i] as Double + (sq >> Digit::BITS);
+            prod[2*i] = carry as Digit;
+            carry >>= Digit::BITS;
+        }
+        // Truncate to the upper half
+        Self::from_digits(&prod[..n])
+    }
+
+    fn negate(&mut self) {
+        // self = -self, which is the two's complement. All digits down to the lowest non-zero one
+        // are inverted, the lowest non-zero digit is negated and the zero digits below stay zero.
+        if self.is_zero() {
+            return;
+        }
+        let mut i = self.len() - 1;
+        while self.digits.get(i) == 0 {
+            i -= 1;
+        }
+        self.digits.set(i, self.digits.get(i).wrapping_neg());
+        for j in 0..i {
+            self.digits.set(j, !self.digits.get(j));
+        }
         self.update_zeros();
     }
 
     fn set_to_div(&mut self, x: &Self, d: Digit) {
-        // self = x / d
+        // self = x / d, truncated like div_assign
+        let _timer = profile::Timer::new(Op::SetToDiv);
+        if d.is_power_of_two() {
+            self.set_to_shr(x, d.trailing_zeros());
+            return;
+        }
         let d = d as Double;
         let mut rem: Double = 0;
         for i in self.zeros..x.zeros {
-            self.digits[i] = 0;
+            self.digits.set(i, 0);
         }
 
-        for i in x.zeros..DIGITS {
-            let num = (rem << Digit::BITS) + x.digits[i] as Double;
-            self.digits[i] = (num / d) as Digit;
+        for i in x.zeros..self.len() {
+            let num = (rem << Digit::BITS) + x.digits.get(i) as Double;
+            self.digits.set(i, (num / d) as Digit);
             rem = num % d;
         }
         self.update_zeros_min(x.zeros);
     }
 
-    fn add_assign(&mut self, rhs: &Self) {
+    fn set_to_shr(&mut self, x: &Self, shift: u32) {
+        // self = x / 2^shift for shift < Digit::BITS, the same as set_to_div but with shifts
+        // instead of divisions. Each digit takes the low bits of the one above it in x.
+        for i in self.zeros..x.zeros {
+            self.digits.set(i, 0);
+        }
+        let mut prev: Double = 0;
+        for i in x.zeros..self.len() {
+            let digit = x.digits.get(i) as Double;
+            self.digits.set(i, ((prev << Digit::BITS | digit) >> shift) as Digit);
+            prev = digit;
+        }
+        self.update_zeros_min(x.zeros);
+    }
+
+    fn wrapping_add_assign(&mut self, rhs: &Self) {
         // self += rhs
         // These are not implemented with trait AddAssign because that one expects the rhs to be
         // copied or moved, but we want to borrow it.
+        let _timer = profile::Timer::new(Op::AddAssign);
         let mut carry: Double = 0;
-        for i in (rhs.zeros..DIGITS).rev() {
-            let res = carry + self.digits[i] as Double + rhs.digits[i] as Double;
-            self.digits[i] = res as Digit;
+        for i in (rhs.zeros..self.len()).rev() {
+            let res = carry + self.digits.get(i) as Double + rhs.digits.get(i) as Double;
+            self.digits.set(i, res as Digit);
             carry = res >> Digit::BITS;
         }
-        self.update_zeros_min(max(1, min(self.zeros, rhs.zeros))-1);
+        // Above rhs.zeros, only the carry is added. Once it is zero, the remaining digits do not
+        // change. This is a separate loop so the one above needs no check for it.
+        let mut i = rhs.zeros;
+        while carry != 0 && i > 0 {
+            i -= 1;
+            let res = carry + self.digits.get(i) as Double;
+            self.digits.set(i, res as Digit);
+            carry = res >> Digit::BITS;
+        }
+        self.update_zeros_after_change(i);
+    }
+
+    #[allow(dead_code)]
+    fn add_assign(&mut self, rhs: &Self) -> Checked<()> {
+        // wrapping_add_assign, or checked_add_assign with the checked feature, see Checked
+        overflow_checked!(self.checked_add_assign(rhs), self.wrapping_add_assign(rhs))
+    }
+
+    fn update_zeros_after_change(&mut self, top: usize) {
+        // Update zeros after only the digits from top on changed. If a digit above top is
+        // non-zero, so is the first one, and zeros stays the same. This is the usual case when
+        // summing a series, where the result is dense and only the terms get shorter. Tracking
+        // the first non-zero digit inside the carry loops instead makes them slower.
+        if self.zeros >= top {
+            self.update_zeros_min(top);
+        }
+    }
+
+    fn add_div_assign(&mut self, x: &Self, d: Digit, neg: bool) {
+        // self += x / d, or self -= x / d if neg, without a Number for x / d. The quotient is
+        // computed in blocks of ADD_DIV_BLOCK digits starting with the most significant one, and
+        // each block is added like in add_assign or subtracted like in sub_assign. A carry or
+        // borrow out of a block goes into the digits above, which are already done, so it is
+        // propagated upwards right away. It rarely passes more than one digit.
+        // Storing the digits of two sums alternately, to add the terms of two arctans like the
+        // ones of Machin's formula in one pass with fewer memory streams, was tried and dropped.
+        // For 20000 limbs, dividing into both in one pass took 212 us, against 355 us for two
+        // calls of this, but the same pass over two separate Numbers took 223 us, and for
+        // add_assign, 36 us both ways against 48 us. The gain comes from running two independent
+        // remainder and carry chains at once, not from the layout.
+        let _timer = profile::Timer::new(Op::AddDivAssign);
+        let d = d as Double;
+        let mut rem: Double = 0;
+        let mut quot = [0; ADD_DIV_BLOCK];
+        // The first digit that may have changed
+        let mut top = x.zeros;
+        for start in (x.zeros..self.len()).step_by(ADD_DIV_BLOCK) {
+            let block = start..min(start + ADD_DIV_BLOCK, self.len());
+            for (q, i) in quot.iter_mut().zip(block.clone()) {
+                let num = (rem << Digit::BITS) + x.digits.get(i) as Double;
+                *q = (num / d) as Digit;
+                rem = num % d;
+            }
+            // Subtracting is adding the inverted digits plus one
+            let inv = if neg { Digit::MAX } else { 0 };
+            let mut carry = neg as Double;
+            for (&q, i) in quot.iter().zip(block).rev() {
+                let res = carry + self.digits.get(i) as Double + (q ^ inv) as Double;
+                self.digits.set(i, res as Digit);
+                carry = res >> Digit::BITS;
+            }
+            // Without a carry or borrow, carry is 0 when adding and 1 when subtracting
+            let mut i = start;
+            while carry != neg as Double && i > 0 {
+                i -= 1;
+                let res = carry + self.digits.get(i) as Double + inv as Double;
+                self.digits.set(i, res as Digit);
+                carry = res >> Digit::BITS;
+            }
+            top = min(top, i);
+        }
+        self.update_zeros_after_change(top);
     }
 
     fn sub_assign(&mut self, rhs: &Self) {
         // self -= rhs
+        let _timer = profile::Timer::new(Op::SubAssign);
         let mut carry: Double = 1;
-        for i in (0..DIGITS).rev() {
-            if i < rhs.zeros && carry == 1 {
-                // The rest of the operations will not change anything, can return
-                self.update_zeros_min(min(self.zeros, i+1));
-                return;
-            }
-            let res = carry + self.digits[i] as Double + (!rhs.digits[i]) as Double;
-            self.digits[i] = res as Digit;
+        for i in (rhs.zeros..self.len()).rev() {
+            let res = carry + self.digits.get(i) as Double + (!rhs.digits.get(i)) as Double;
+            self.digits.set(i, res as Digit);
             carry = res >> Digit::BITS;
         }
-        self.update_zeros();
+        // Above rhs.zeros, each digit gets !0 plus the carry added. With a carry of 1, this leaves
+        // it unchanged and passes on the carry, so the remaining digits do not change. Without
+        // it, the digit is decremented and the borrow continues while the digit was zero.
+        let mut i = rhs.zeros;
+        while carry == 0 && i > 0 {
+            i -= 1;
+            let res = self.digits.get(i) as Double + Digit::MAX as Double;
+            self.digits.set(i, res as Digit);
+            carry = res >> Digit::BITS;
+        }
+        self.update_zeros_after_change(i);
     }
 
     #[allow(dead_code)]
-    fn print(&self) {
-        // Print Number as hexadecimal
-        for i in 0..DIGITS {
-            print!("{:016x} ", self.digits[i]);
+    fn round_to_limb(&mut self, limb: usize) {
+        // Round the value to its first limb digits and set the others to zero, to the nearest
+        // value with ties to an even last digit, instead of truncating. The discarded digits are
+        // above one half of the last kept one if their leading one is set and any other bit is,
+        // and exactly one half if only the leading bit is. Rounding up adds one to the last kept
+        // digit with the carry propagated like in add_assign, which wraps around to zero if all
+        // kept digits are Digit::MAX.
+        assert!(limb <= self.len(), "Can not round {} digits to {}", self.len(), limb);
+        if limb == self.len() {
+            return;
+        }
+        let half: Digit = 1 << (Digit::BITS - 1);
+        let first = self.digits.get(limb);
+        let rest = (limb+1..self.len()).any(|i| self.digits.get(i) != 0);
+        let above = first > half || (first == half && rest);
+        let odd = limb > 0 && self.digits.get(limb-1) & 1 == 1;
+        let up = above || (first == half && odd);
+        for i in limb..self.len() {
+            self.digits.set(i, 0);
+        }
+        let mut i = limb;
+        if up {
+            while i > 0 {
+                i -= 1;
+                let (digit, carry) = self.digits.get(i).overflowing_add(1);
+                self.digits.set(i, digit);
+                if !carry {
+                    break;
+                }
+            }
+        }
+        self.update_zeros_after_change(i);
+    }
+
+    fn write_hex(&self, w: &mut impl Write) -> io::Result<()> {
+        // Write Number as hexadecimal, four digits per line
+        for i in 0..self.len() {
+            write!(w, "{:01$x} ", self.digits.get(i), Digit::BITS as usize / 4)?;
             if i%4 == 3 {
-                println!("")
+                writeln!(w)?;
             }
         }
-        println!("")
+        writeln!(w)
+    }
+
+    fn to_hex_string(&self) -> String {
+        // The digits in hexadecimal without separators, most significant first, each padded with
+        // zeros to Digit::BITS / 4 characters, i.e. 16 per digit, or 8 with u32 digits. For
+        // example, the two digits 1 and 0xab give "000000000000000100000000000000ab".
+        let width = Digit::BITS as usize / 4;
+        let mut result = String::with_capacity(self.len() * width);
+        for i in 0..self.len() {
+            result.push_str(&format!("{:01$x}", self.digits.get(i), width));
+        }
+        result
+    }
+
+    fn diff_report(&self, other: &Self) -> String {
+        // Describe where two Numbers differ for debugging: how many digits differ, the position
+        // of the first different bit and the first DIFF_REPORT_LIMIT different digits in
+        // hexadecimal. The decimal digits may already differ a bit earlier than the bit position
+        // says, if a carry runs through a sequence of nines.
+        let len = min(self.len(), other.len());
+        let mut report = String::new();
+        if self.len() != other.len() {
+            report += &format!("Lengths differ: {} != {}, comparing the first {} digits\n",
+                               self.len(), other.len(), len);
+        }
+        let differing: Vec<usize> = (0..len)
+            .filter(|&i| self.digits.get(i) != other.digits.get(i))
+            .collect();
+        let Some(&first) = differing.first() else {
+            return report + &format!("All {} digits are equal\n", len);
+        };
+        let xor = self.digits.get(first) ^ other.digits.get(first);
+        let bit = first * Digit::BITS as usize + xor.leading_zeros() as usize;
+        report += &format!("{} of {} digits differ, the first is digit {} (bit {}, about decimal \
+                            digit {})\n", differing.len(), len, first, bit,
+                           (bit as f64 * 2f64.log10()) as usize);
+        let width = Digit::BITS as usize / 4;
+        for &i in differing.iter().take(DIFF_REPORT_LIMIT) {
+            report += &format!("  digit {:>8}: {:03$x} != {:03$x}\n",
+                               i, self.digits.get(i), other.digits.get(i), width);
+        }
+        if differing.len() > DIFF_REPORT_LIMIT {
+            report += &format!("  and {} more\n", differing.len() - DIFF_REPORT_LIMIT);
+        }
+        report
+    }
+
+    #[allow(dead_code)]
+    fn print(&self) {
+        // Print Number as hexadecimal
+        let mut out = BufWriter::new(io::stdout().lock());
+        self.write_hex(&mut out).unwrap();
+        out.flush().unwrap();
+    }
+}
+
+fn inv_digits(x: Digit, rem: Digit, digits: &mut [Digit]) {
+    // Fill digits with the digits of rem/x, which is less than one, by long division
+    let x = x as Double;
+    let mut rem = rem as Double;
+    for digit in digits {
+        let nom = rem << Digit::BITS;
+        *digit = (nom / x) as Digit;
+        rem = nom % x;
+    }
+}
+
+fn pow_mod(x: Digit, n: usize) -> Digit {
+    // 2^(Digit::BITS*n) mod x, except that it is 1 for n = 0 even if x = 1, which is where the
+    // long division in from_inv starts
+    let x = x as Double;
+    let mut base = (1 << Digit::BITS) % x;
+    let mut result: Double = 1;
+    let mut n = n;
+    while n > 0 {
+        if n & 1 == 1 {
+            result = result * base % x;
+        }
+        base = base * base % x;
+        n >>= 1;
     }
+    result as Digit
 }
 
-impl DivAssign<Digit> for Number {
+impl<S: LimbStore> DivAssign<Digit> for Number<S> {
     fn div_assign(&mut self, x: Digit) {
-        // self /= x
+        // self /= x, as the floor of the value times 2^(BITS*len) divided by x. Chained floor
+        // divisions are exact, floor(floor(v/a)/b) = floor(v/(ab)), so dividing by a and then by b
+        // gives the same digits as dividing by ab once. The series rely on this wherever a term is
+        // divided in several steps, so their results do not depend on how the steps are split.
+        let _timer = profile::Timer::new(Op::DivAssign);
         let x = x as Double;
         let mut rem: Double = 0;
-        for i in self.zeros..DIGITS {
-            let num = (rem << 64) + self.digits[i] as Double;
-            self.digits[i] = (num / x) as Digit;
+        for i in self.zeros..self.len() {
+            let num = (rem << Digit::BITS) + self.digits.get(i) as Double;
+            self.digits.set(i, (num / x) as Digit);
             rem = num % x;
         }
         self.update_zeros_min(self.zeros);
     }
 }
 
-fn ataninv_scalar(x: Digit) -> Number {
-    /* Scalar version of computing atan(1/x) as alternating sum over 1/(kx^k) with k iterating over
-     * odd numbers.
-     */
-    let x2 = x*x;
-    let mut result = Number::from_inv(x);
-    // refterm is always 1/x^n with some odd n that is not necessarily the same as k since we can
-    // sometimes get away with only one division - computing 1/(kx^k)=refterm/(kx^(k-n)).  Only if
-    // the denominator becomes too large for a u64, we update the refterm such that n=k.
-    let mut refterm = result.clone();
-    let mut tmp = Number::zero();
-    // the counting variable, k in the term 1/(kx^k)
-    let mut denom: Digit = 1;
-    // x^(k-n), this indicates how far refterm lags behind
-    let mut stepsize: Digit = 1;
-    let mut neg = true;
-    while !refterm.is_zero() {
-        denom += 2;
-        stepsize *= x2;
-        let mut divisor = denom as Double * stepsize as Double;
-        if divisor > Digit::MAX.into() {
-            refterm /= stepsize;
-            stepsize = 1;
-            divisor = denom as Double;
+impl Number {
+    fn div_assign_parallel(&mut self, x: Digit, nthreads: usize) {
+        // Same as self /= x, with the digits from zeros on split into nthreads blocks that are
+        // divided at the same time, one on the calling thread and the others on threads spawned
+        // for it, so nthreads threads run in total. The remainder that enters a block is the
+        // value of all digits before it modulo x, which follows from the remainders of the earlier
+        // blocks on their own: after a block of n digits with the remainder r on its own, the
+        // remainder is (rem 2^(Digit::BITS n) + r) mod x, with the power from pow_mod. So the
+        // blocks first find their own remainders in parallel, with a pass like the division that
+        // does not store the quotient, which are chained on the calling thread, and then all
+        // blocks are divided in parallel. This is twice the work of a division, so it can only pay
+        // off from three threads.
+        let _timer = profile::Timer::new(Op::DivAssign);
+        let start = self.zeros;
+        let block = (self.len() - start).div_ceil(nthreads.max(1)).max(1);
+        let x = x as Double;
+        let digits = &mut self.digits[start..];
+        let remainder = |chunk: &[Digit]| {
+            chunk.iter().fold(0, |rem, &d| ((rem << Digit::BITS) + d as Double) % x)
+        };
+        // The last block does not pass its remainder on
+        let blocks = digits.len().div_ceil(block);
+        let rems: Vec<Double> = thread::scope(|scope| {
+            let mut chunks = digits.chunks(block).take(blocks.saturating_sub(1));
+            let first = chunks.next();
+            let handles: Vec<_> = chunks.map(|chunk| scope.spawn(move || remainder(chunk)))
+                .collect();
+            first.map(remainder).into_iter()
+                .chain(handles.into_iter().map(|handle| handle.join().unwrap()))
+                .collect()
+        });
+        let mut rem: Double = 0;
+        let mut entering = vec![0];
+        for (chunk, &r) in digits.chunks(block).zip(&rems) {
+            rem = (rem * pow_mod(x as Digit, chunk.len()) as Double + r) % x;
+            entering.push(rem);
         }
-        tmp.set_to_div(&refterm, divisor as Digit);
-        if neg {
-            result.sub_assign(&tmp);
+        let divide = |chunk: &mut [Digit], mut rem: Double| {
+            for d in chunk {
+                let num = (rem << Digit::BITS) + *d as Double;
+                *d = (num / x) as Digit;
+                rem = num % x;
+            }
+        };
+        thread::scope(|scope| {
+            let mut chunks = digits.chunks_mut(block).zip(entering);
+            let first = chunks.next();
+            for (chunk, rem) in chunks {
+                scope.spawn(move || divide(chunk, rem));
+            }
+            if let Some((chunk, rem)) = first {
+                divide(chunk, rem);
+            }
+        });
+        self.update_zeros_min(start);
+    }
+}
+
+#[cfg(test)]
+fn ataninv_scalar_naive(x: Digit, limbs: usize) -> Number {
+    // Slow reference for ataninv_scalar_until without refterm: every term 1/(kx^k) is computed
+    // from scratch by dividing 1 by x k times and then by k. By the invariant of div_assign, both
+    // must agree in every digit. This needs O(k) divisions per term instead of one.
+    let mut result = Number::zero_with_len(limbs);
+    let mut k: Digit = 1;
+    loop {
+        let mut term = Number::from_inv_with_len(x, limbs);
+        for _ in 1..k {
+            term /= x;
+        }
+        if term.is_zero() {
+            return result;
+        }
+        term /= k;
+        if k % 4 == 3 {
+            result.sub_assign(&term);
         } else {
-            result.add_assign(&tmp);
+            result.wrapping_add_assign(&term);
         }
-        neg = !neg;
+        k += 2;
+    }
+}
+
+// Which Taylor series to sum. Both consist of the terms 1/(kx^k) with k iterating over odd
+// numbers, but for atan(1/x) the signs alternate while for atanh(1/x) all terms are added.
+#[derive(Clone, Copy, PartialEq)]
+enum Series {
+    Atan,
+    Atanh,
+}
+
+fn x_squared(x: Digit) -> Digit {
+    // x^2 for the series of atan(1/x) and atanh(1/x). All of them divide by x^2 as a Digit, so
+    // larger x are rejected here instead of wrapping around silently in release builds.
+    x.checked_mul(x)
+        .unwrap_or_else(|| panic!("x = {} is too large, x^2 does not fit into a Digit", x))
+}
+
+fn ataninv_scalar_until(x: Digit, limbs: usize, series: Series, deadline: Option<Instant>)
+        -> (Number, usize) {
+    /* Scalar version of computing atan(1/x) as alternating sum over 1/(kx^k) with k iterating over
+     * odd numbers, or atanh(1/x) as the same sum without alternating, with the given number of
+     * digits. If the deadline passes, the summation stops between two terms. Returns the result
+     * and the number of leading digits the remaining terms can no longer change, which is all of
+     * them unless the deadline stopped the summation.
+     */
+    let (result, stats) = ataninv_scalar_stats(x, limbs, series, deadline);
+    (result, stats.converged)
+}
+
+#[allow(dead_code)]
+struct AtanStats {
+    // Number of terms 1/(kx^k) that were added after 1/x
+    terms: usize,
+    // Same as the second return value of ataninv_scalar_until
+    converged: usize,
+    // Bound for the sum over all omitted terms. For atan, this is the last added term, since the
+    // terms of an alternating series decrease. For atanh, it is the last term divided by x^2-1,
+    // the sum of a geometric series. The truncation of each term adds an error of less than one
+    // unit in the last digit, so the result differs from the exact value by less than
+    // error_bound + terms + 1 units in the last digit. error_bound.zeros is the first digit the
+    // omission may affect.
+    error_bound: Number,
+}
+
+fn ataninv_scalar_stats(x: Digit, limbs: usize, series: Series, deadline: Option<Instant>)
+        -> (Number, AtanStats) {
+    // Same as ataninv_scalar_until, but also returns statistics including an error bound.
+    ataninv_scalar_observed(x, limbs, series, |_, _| {
+        deadline.is_none_or(|deadline| Instant::now() < deadline)
+    })
+}
+
+fn ataninv_scalar_observed(x: Digit, limbs: usize, series: Series,
+                           mut observe: impl FnMut(&Number, &Number) -> bool)
+        -> (Number, AtanStats) {
+    // Same as ataninv_scalar_stats, but instead of a deadline, observe is called before each term
+    // with the partial sum and refterm, and the summation stops if it returns false. The terms
+    // that are still missing decrease and are all smaller than refterm, so for atan, their sum is
+    // smaller than refterm too.
+    ataninv_scalar_resume(ScalarState::new(x, limbs, series),
+                          |state| observe(&state.result, &state.refterm))
+}
+
+struct ScalarState {
+    // The variables of the summation of ataninv_scalar_observed between two terms, which is all
+    // that is needed to continue it, see checkpoint.rs
+    x: Digit,
+    series: Series,
+    result: Number,
+    // refterm is always 1/x^n with some odd n that is not necessarily the same as k since we can
+    // sometimes get away with only one division - computing 1/(kx^k)=refterm/(kx^(k-n)).  Only if
+    // the denominator becomes too large for a Digit, we update the refterm such that n=k.
+    refterm: Number,
+    // the counting variable, k in the term 1/(kx^k)
+    denom: Digit,
+    // x^(k-n), this indicates how far refterm lags behind
+    stepsize: Digit,
+    neg: bool,
+    terms: usize,
+    // The divisor of the last added term
+    last_divisor: Digit,
+}
+
+impl ScalarState {
+    fn new(x: Digit, limbs: usize, series: Series) -> Self {
+        // The state before the first term after 1/x
+        Self::at_term(x, limbs, series, 0)
+    }
+
+    fn at_term(x: Digit, limbs: usize, series: Series, start_term: usize) -> Self {
+        // The state before term start_term, the one with the denominator 2k+1 for k = start_term,
+        // but with nothing summed yet, so the summation gives the sum of the terms from there on.
+        // For start_term = 0, this is the state of new, where 1/x is already summed. Otherwise,
+        // refterm starts as the previous power 1/x^(2k-1) from set_to_inv_odd_power, and terms
+        // and last_divisor are as if the terms before had been summed by ataninv_scalar_resume.
+        Self::at_term_in(x, series, start_term, Number::zero_with_len(limbs),
+                         Number::zero_with_len(limbs))
+    }
+
+    fn at_term_in(x: Digit, series: Series, start_term: usize, mut result: Number,
+                  mut refterm: Number) -> Self {
+        // Same as at_term, but overwriting the given Numbers of the same length instead of
+        // allocating new ones, see atan_scratch.rs
+        debug_assert_eq!(result.len(), refterm.len(), "Numbers of different length");
+        if start_term == 0 {
+            result.set_to_inv(x);
+            refterm.copy_from(&result);
+            return ScalarState {
+                x,
+                series,
+                result,
+                refterm,
+                denom: 1,
+                stepsize: 1,
+                neg: series == Series::Atan,
+                terms: 0,
+                last_divisor: 1,
+            };
+        }
+        let denom = Digit::try_from(2*start_term - 1).expect("The term does not fit into a Digit");
+        result.set_to_zero();
+        set_to_inv_odd_power(&mut refterm, x, start_term - 1);
+        ScalarState {
+            x,
+            series,
+            result,
+            refterm,
+            denom,
+            stepsize: 1,
+            neg: series == Series::Atan && start_term % 2 == 1,
+            terms: start_term - 1,
+            last_divisor: denom,
+        }
+    }
+}
+
+fn ataninv_scalar_resume(mut state: ScalarState, mut observe: impl FnMut(&ScalarState) -> bool)
+        -> (Number, AtanStats) {
+    // Continue the summation of ataninv_scalar_observed from the given state, with observe called
+    // before each term with the current state.
+    // result and refterm are the only Numbers, so this needs 2 * limbs digits of memory. Each term
+    // is added right while it is divided, without storing it.
+    let x2 = x_squared(state.x);
+    while !state.refterm.is_zero() {
+        if !observe(&state) {
+            break;
+        }
+        state.denom += 2;
+        if state.stepsize.checked_mul(x2).is_none() {
+            // Not even x^(k-n) fits, so let refterm catch up with the previous term first.
+            state.refterm /= state.stepsize;
+            state.stepsize = 1;
+        }
+        state.stepsize *= x2;
+        let mut divisor = state.denom as Double * state.stepsize as Double;
+        if divisor > Digit::MAX.into() {
+            state.refterm /= state.stepsize;
+            state.stepsize = 1;
+            divisor = state.denom as Double;
+        }
+        state.last_divisor = divisor as Digit;
+        state.result.add_div_assign(&state.refterm, state.last_divisor, state.neg);
+        if state.series == Series::Atan {
+            state.neg = !state.neg;
+        }
+        state.terms += 1;
+    }
+    let ScalarState { result, refterm, terms, last_divisor, series, .. } = state;
+    let converged = refterm.zeros;
+    // refterm is no longer needed, so it becomes the last added term, or 1/x if there was none
+    let mut error_bound = refterm;
+    error_bound /= last_divisor;
+    if series == Series::Atanh {
+        error_bound /= x2 - 1;
+    }
+    (result, AtanStats { terms, converged, error_bound })
+}
+
+struct AtanTerms {
+    // Iterator over the Taylor terms (-1)^k/((2k+1)x^(2k+1)) of atan(1/x), as they are summed by
+    // ataninv_scalar_observed. Negative terms are negated Numbers, so adding all of them with
+    // add_assign gives the same digits as ataninv_scalar_until. It ends at the first term that is
+    // zero at this precision, after which all terms vanish.
+    x: Digit,
+    x2: Digit,
+    limbs: usize,
+    // Same as in ataninv_scalar_observed, None before the first term 1/x
+    refterm: Option<Number>,
+    denom: Digit,
+    stepsize: Digit,
+    neg: bool,
+}
+
+#[allow(dead_code)]
+impl AtanTerms {
+    fn new(x: Digit, limbs: usize) -> Self {
+        // The terms with the given number of digits
+        assert!(x > 1, "atan(1/x) only converges for x > 1");
+        AtanTerms { x, x2: x_squared(x), limbs, refterm: None, denom: 1, stepsize: 1, neg: false }
+    }
+}
+
+impl Iterator for AtanTerms {
+    type Item = Number;
+
+    fn next(&mut self) -> Option<Number> {
+        let Some(refterm) = &mut self.refterm else {
+            let first: Number = Number::from_inv_with_len(self.x, self.limbs);
+            self.refterm = Some(first.clone());
+            self.neg = true;
+            return Some(first);
+        };
+        if refterm.is_zero() {
+            return None;
+        }
+        self.denom += 2;
+        if self.stepsize.checked_mul(self.x2).is_none() {
+            *refterm /= self.stepsize;
+            self.stepsize = 1;
+        }
+        self.stepsize *= self.x2;
+        let mut divisor = self.denom as Double * self.stepsize as Double;
+        if divisor > Digit::MAX.into() {
+            *refterm /= self.stepsize;
+            self.stepsize = 1;
+            divisor = self.denom as Double;
+        }
+        let mut term = Number::zero_with_len(refterm.len());
+        term.set_to_div(refterm, divisor as Digit);
+        if term.is_zero() {
+            // Make the iterator fused
+            *refterm = term;
+            return None;
+        }
+        if self.neg {
+            term.negate();
+        }
+        self.neg = !self.neg;
+        Some(term)
     }
-    result
 }
 
 
@@ -230,21 +1394,17 @@ fn calc(rcv: Receiver<(bool, Digit, Term)>, snd: Sender<Msg>,) {
     // thread, which sums them together.
     let mut result = Number::zero();
     let mut tmp = Number::zero();
-    loop {
-        let (neg, div, term) = match rcv.recv() {
-            Ok(x) => x,
-            Err(_) => break,
-        };
+    while let Ok((neg, div, term)) = profile::time(Op::Wait, || rcv.recv()) {
         tmp.set_to_div(&term.val, div);
         if tmp.is_zero() {
-            snd.send(Msg::Number(result)).unwrap();
+            profile::time(Op::Send, || snd.send(Msg::Number(result))).unwrap();
             break;
         }
-        snd.send(Msg::Term(term)).unwrap();
+        profile::time(Op::Send, || snd.send(Msg::Term(term))).unwrap();
         if neg {
             result.sub_assign(&tmp);
         } else {
-            result.add_assign(&tmp);
+            result.wrapping_add_assign(&tmp);
         };
     }
 }
@@ -269,18 +1429,19 @@ fn ataninv_threaded(x: Digit, nthreads: usize) -> Number {
     for _ in 0..nthreads+2 {
         terms.push(Term::init(&result));
     }
+    let mut handles = Vec::new();
     for _ in 0..nthreads {
         let rcv = rcv_thrd.clone();
         let snd = snd_thrd.clone();
-        thread::spawn(move || {
+        handles.push(thread::spawn(move || {
             calc(rcv, snd);
-        });
+        }));
     }
 
     drop(rcv_thrd);
     drop(snd_thrd);
 
-    let x2 = x*x;
+    let x2 = x_squared(x);
     // current power of x for the Taylor series
     let mut denom: Digit = 1;
     // x^(denom-refterm.denom)
@@ -292,11 +1453,11 @@ fn ataninv_threaded(x: Digit, nthreads: usize) -> Number {
         // that were given back by a processing thread.
         let mut term = match terms.pop() {
             Some(x) => x,
-            None => match rcv_main.recv() {
+            None => match profile::time(Op::Recv, || rcv_main.recv()) {
                 Ok(msg) => match msg {
                     Msg::Term(x) => x,
                     Msg::Number(x) => {
-                        result.add_assign(&x);
+                        result.wrapping_add_assign(&x);
                         continue;
                     },
                 },
@@ -306,6 +1467,12 @@ fn ataninv_threaded(x: Digit, nthreads: usize) -> Number {
 
         denom += 2;
         negative = !negative;
+        if stepsize.checked_mul(x2).is_none() {
+            // Not even x^(denom-refterm.denom) fits, let refterm catch up with the previous term.
+            refterm.val /= stepsize;
+            refterm.denom = denom - 2;
+            stepsize = 1;
+        }
         stepsize *= x2;
         if denom as Double * stepsize as Double > Digit::MAX.into() {
             refterm.val /= stepsize;
@@ -317,69 +1484,191 @@ fn ataninv_threaded(x: Digit, nthreads: usize) -> Number {
         }
         // Errors here are not a problem. All threads already encountered a zero term and
         // terminated. We prepared too many terms, but they will also be zero.
-        let _ = snd_main.send((negative, denom*stepsize, term));
+        let _ = profile::time(Op::Send, || snd_main.send((negative, denom*stepsize, term)));
+    }
+    drop(snd_main);
+    for handle in handles {
+        handle.join().unwrap();
     }
     result
 }
 
 
 // Alternate strategy - update refterm inside worker thread
+
+// Smallest number of digits per thread for which a refterm is divided with div_assign_parallel
+const REF_BLOCK_LIMBS: usize = 4096;
+// Fewest threads for which a refterm is divided with div_assign_parallel, which does twice the
+// work of a division
+const MIN_REF_THREADS: usize = 3;
+// Tasks per worker that ataninv_threaded2_until queues at most without a given capacity, so each
+// worker has the next one ready when it finishes the current one
+const TASK_CAPACITY_PER_THREAD: usize = 2;
+
 struct TaskParams {
     neg: bool,
     // Divisor
     div: Digit,
+    // Second divisor after div, 1 unless the product of both does not fit into a Digit
+    div2: Digit,
+    // Index of the term after 1/x, starting at 1, or 0 for a refterm
+    idx: Digit,
+    // Threads for the division of a refterm with div_assign_parallel, 1 for a term
+    threads: usize,
 }
 
+#[derive(Debug)]
 enum Task {
     UpdateTerm,
     UpdateRef,
 }
 
-fn worker(rcv: Receiver<(Task, TaskParams, Number)>,
-          snd: Sender<(Task, TaskParams, Number, bool)>) {
-    let mut finished = false;
-    while !finished {
-        let received = rcv.recv();
-        let (task, params, mut term) = match received {
-            Ok(x) => x,
-            Err(_) => break,
-        };
+#[cfg(test)]
+thread_local! {
+    // Index of a term whose divisor ataninv_threaded2_until replaces with 0 when it runs on this
+    // thread, so the worker of that term panics, or 0 for none
+    static ZERO_DIVISOR_IDX: std::cell::Cell<Digit> = const { std::cell::Cell::new(0) };
+}
 
-        term /= params.div;
-        finished = term.is_zero();
+// A finished task with its workspace and whether the result is zero, or the message of a task
+// that panicked
+type TaskResult = Result<(Task, TaskParams, Number, bool), String>;
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    // The message of panic! is a &str without and a String with format arguments
+    payload.downcast_ref::<&str>().copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+fn worker(rcv: Receiver<(Task, TaskParams, Number)>,
+          snd: Sender<TaskResult>,
+          snd_ref: Sender<TaskResult>) {
+    // The results of UpdateRef tasks are sent to snd_ref, the ones of UpdateTerm tasks to snd.
+    // A worker runs until the driver closes the channel. A zero result only tells the driver that
+    // no new windows are needed, the tasks that are still queued must be done anyway.
+    // If a task panics, its workspace is lost, so the driver would wait for it forever. Instead,
+    // the panic is caught and reported to the driver, and the worker stops.
+    while let Ok((task, params, mut term)) = profile::time(Op::Wait, || rcv.recv()) {
+        let divided = panic::catch_unwind(AssertUnwindSafe(|| {
+            if params.threads > 1 {
+                term.div_assign_parallel(params.div, params.threads);
+            } else {
+                term /= params.div;
+            }
+            if params.div2 != 1 {
+                term /= params.div2;
+            }
+        }));
+        if let Err(payload) = divided {
+            let message = format!("{:?} task with divisor {} * {} panicked: {}",
+                                  task, params.div, params.div2, panic_message(&*payload));
+            // If the driver is gone, there is nobody left to tell
+            let _ = snd.send(Err(message));
+            return;
+        }
+        let finished = term.is_zero();
 
-        snd.send((task, params, term, finished)).unwrap();
+        let snd = match task {
+            Task::UpdateRef => &snd_ref,
+            Task::UpdateTerm => &snd,
+        };
+        profile::time(Op::Send, || snd.send(Ok((task, params, term, finished)))).unwrap();
     }
 }
 
-fn ataninv_threaded2(x: Digit, nthreads: usize) -> Number {
-    let (snd_main, rcv_thrd) = unbounded();
+// The parameters are passed on as they are by ataninv_until and ataninv_capped
+#[allow(clippy::too_many_arguments)]
+fn ataninv_threaded2_until(x: Digit, limbs: usize, nthreads: usize, series: Series,
+        max_window: Option<usize>, overlap: bool, capacity: Option<usize>,
+        deadline: Option<Instant>, max_terms: Option<usize>, progress: Option<&AtomicUsize>)
+        -> (Number, usize, usize) {
+    // See ataninv_scalar_until for the parameters and the return value. Once the deadline passes,
+    // no new windows are created, but the tasks that were already handed out are still summed up.
+    // With max_terms, only that many terms after 1/x are handed out, see ataninv_capped.
+    // With progress, the number of terms after 1/x up to which all are summed is stored there
+    // whenever it grows, see PiComputation::run_with_callback.
+    // A window contains as many terms as the divisors fit into a Digit, but at most max_window.
+    // Smaller windows update refterm more often: each update is an additional division, but the
+    // next window can start earlier.
+    // With overlap, a new refterm is handled before the terms that finished earlier, so the next
+    // window can be handed out before summing them. This keeps more windows in flight, which
+    // needs more workspaces.
+    // At most capacity tasks wait in the channel to the workers, TASK_CAPACITY_PER_THREAD per
+    // worker by default. Once it is full, the driver blocks until a worker takes the next one, so
+    // a window of slow workers does not pile up. The channel back to the driver is not bounded:
+    // the workers must never wait for the driver while it waits for them.
+    // The refterm is on the critical path, the next window can not start before it is there. So
+    // workers that would be idle until then divide it together with div_assign_parallel. With
+    // the profile feature, ref_wait is the time the driver spends waiting for it.
+    // The result is the same for any number of workers, window and order in which the tasks
+    // finish, and the same as the one of ataninv_scalar_until: each term is the floor of 1/(kx^k)
+    // wherever refterm stood, see div_assign, and adding and subtracting modulo one is exact and
+    // commutative. The test
+    // ataninv_threaded2_agrees_with_scalar_for_any_threads_and_window checks this, and the ignored
+    // ataninv_threaded2_stress runs it with random settings many times.
+    // The third return value is the number of Numbers of limbs digits that were allocated: result,
+    // refterm and the workspaces, which are only created while all are in use and kept until the
+    // end, so this is also the peak. The check of refterm in debug builds needs one more.
+    let max_window = max_window.unwrap_or(usize::MAX);
+    assert!(max_window > 0, "The window must contain at least one term");
+    let max_terms = max_terms.unwrap_or(usize::MAX);
+    let (snd_main, rcv_thrd) = bounded(capacity.unwrap_or(TASK_CAPACITY_PER_THREAD * nthreads));
     let (snd_thrd, rcv_main) = unbounded();
+    // With overlap, new refterms get their own channel, so they do not queue up behind finished
+    // terms. Otherwise, they share the channel with the terms.
+    let (snd_thrd_ref, rcv_main_ref) = if overlap {
+        unbounded()
+    } else {
+        (snd_thrd.clone(), rcv_main.clone())
+    };
 
-    let x2 = x*x;
+    let x2 = x_squared(x);
 
+    let mut handles = Vec::new();
     for _ in 0..nthreads {
         let rcv = rcv_thrd.clone();
         let snd = snd_thrd.clone();
-        thread::spawn(move || { worker(rcv, snd); });
+        let snd_ref = snd_thrd_ref.clone();
+        handles.push(thread::spawn(move || { worker(rcv, snd, snd_ref); }));
     };
 
     drop(rcv_thrd);
     drop(snd_thrd);
+    drop(snd_thrd_ref);
 
-    let mut result = Number::from_inv(x);
+    let mut result = Number::from_inv_with_len(x, limbs);
     let mut terms = Vec::new();
+    let mut workspaces = 0;
 
     let mut refterm = result.clone();
     // Index of refterm power
     let mut refidx: Digit = 0;
+    // In debug builds, refterm is checked against 1/x^(2*refidx+1) computed independently by
+    // dividing by x alone. By the invariant of div_assign, both must agree in every digit.
+    #[cfg(debug_assertions)]
+    let (mut expected, mut expected_idx) = (refterm.clone(), 0);
 
     let mut awaiting_nextrefterm = false;
     let mut neg = false;
     let mut running = true;
+    let mut timed_out = false;
     let mut tasks = 0;
+    // Terms that were summed after the first one that is still missing, for progress
+    let mut summed = 0;
+    let mut summed_later = BTreeSet::new();
 
+    // Once a task returns zero, running becomes false. Since the divisors increase within a window
+    // and each window starts from the refterm of the previous one, all later terms and refterms
+    // are zero as well, so no new window is created. The tasks that were already handed out are
+    // still received and summed up. If the zero came from the UpdateRef task, refterm is not
+    // updated and awaiting_nextrefterm stays true, which does not matter anymore.
     while running || tasks > 0 {
+        if running && (deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                       || refidx as usize >= max_terms) {
+            running = false;
+            timed_out = true;
+        }
         if running && !awaiting_nextrefterm {
             // Compute all divisors that can be used from the current to the next refterm.
             // But create the task that computes the next refterm before creating the division
@@ -387,32 +1676,73 @@ fn ataninv_threaded2(x: Digit, nthreads: usize) -> Number {
             let mut divs = Vec::new();
 
             let mut div: Digit = 1;
-            while let Some(fulldiv) = div.checked_mul(x2*(2*refidx+3)) {
+            while let Some(fulldiv) = x2.checked_mul(2*refidx+3).and_then(|d| div.checked_mul(d))
+                    .filter(|_| divs.len() < max_window && (refidx as usize) < max_terms) {
                 div = div.checked_mul(x2).unwrap();
                 divs.push(fulldiv);
                 refidx += 1;
             }
+            // If not even x^2 (2k+1) fits for the next term, the window only has that term, which
+            // is divided by x^2 and then by 2k+1. This is the same floor, see div_assign.
+            let mut div2 = 1;
+            if divs.is_empty() {
+                div = x2;
+                div2 = 2*refidx + 3;
+                divs.push(x2);
+                refidx += 1;
+            }
             // Make sure there are enough workspaces for all needed tasks.
             for _ in terms.len()..=divs.len() {
-                terms.push(Number::zero());
+                terms.push(Number::zero_with_len(limbs));
+                workspaces += 1;
             }
             let mut term = terms.pop().unwrap();
             term.copy_from(&refterm);
-            if let Ok(_) = snd_main.send((Task::UpdateRef, TaskParams{neg: false, div: div}, term)) {
+            // The workers that are left without a task until the next window help with the
+            // refterm, if there are enough digits to make up for starting the threads. idle
+            // includes the worker of the refterm, which divides one block itself. The others
+            // wait for the next window meanwhile, so div_assign_parallel starts a thread for each
+            // of them, and no more than nthreads threads are busy at any time.
+            let idle = nthreads.saturating_sub(tasks + divs.len());
+            let threads = idle.min((limbs - refterm.zeros) / REF_BLOCK_LIMBS);
+            let threads = if threads >= MIN_REF_THREADS { threads } else { 1 };
+            let task = (Task::UpdateRef, TaskParams{neg: false, div, div2: 1, idx: 0, threads},
+                        term);
+            if profile::time(Op::Send, || snd_main.send(task)).is_ok() {
                 tasks += 1;
             }
-            for i in 0..divs.len() {
-                neg = !neg;
+            let first_idx = refidx + 1 - divs.len() as Digit;
+            for (idx, div) in (first_idx..).zip(divs) {
+                if series == Series::Atan {
+                    neg = !neg;
+                }
+                #[cfg(test)]
+                let div = if ZERO_DIVISOR_IDX.get() == idx { 0 } else { div };
                 let mut term = terms.pop().unwrap();
                 term.copy_from(&refterm);
-                if let Ok(_) = snd_main.send((Task::UpdateTerm, TaskParams{neg: neg, div: divs[i]}, term)) {
+                let task = (Task::UpdateTerm, TaskParams{neg, div, div2, idx, threads: 1}, term);
+                if profile::time(Op::Send, || snd_main.send(task)).is_ok() {
                     tasks += 1;
                 }
             }
             awaiting_nextrefterm = true;
         }
-        match rcv_main.recv() {
-            Ok((task, params, term, finished)) => {
+        if tasks == 0 {
+            // The deadline passed or max_terms was reached before the first window, so there is
+            // nothing to wait for
+            continue;
+        }
+        let op = if running && awaiting_nextrefterm { Op::RefWait } else { Op::Recv };
+        let received = if overlap {
+            rcv_main_ref.try_recv().or_else(|_| profile::time(op, || select! {
+                recv(rcv_main_ref) -> msg => msg,
+                recv(rcv_main) -> msg => msg,
+            }))
+        } else {
+            profile::time(op, || rcv_main.recv())
+        };
+        match received {
+            Ok(Ok((task, params, term, finished))) => {
                 tasks -= 1;
                 if finished {
                     running = false;
@@ -421,46 +1751,1818 @@ fn ataninv_threaded2(x: Digit, nthreads: usize) -> Number {
                     Task::UpdateRef => {
                         refterm.copy_from(&term);
                         awaiting_nextrefterm = false;
+                        #[cfg(debug_assertions)]
+                        {
+                            while expected_idx < refidx {
+                                expected /= x;
+                                expected /= x;
+                                expected_idx += 1;
+                            }
+                            assert_numbers_agree!(refterm, expected, 0,
+                                "refterm is not 1/x^{}", 2*refidx+1);
+                        }
                     },
                     Task::UpdateTerm => {
                         if params.neg {
                             result.sub_assign(&term)
                         } else {
-                            result.add_assign(&term)
+                            result.wrapping_add_assign(&term)
+                        }
+                        if let Some(progress) = progress {
+                            summed_later.insert(params.idx);
+                            while summed_later.remove(&(summed + 1)) {
+                                summed += 1;
+                            }
+                            progress.store(summed as usize, Ordering::Relaxed);
                         }
                     },
                 }
                 terms.push(term);
             },
-            Err(_) => break,
+            Ok(Err(message)) => {
+                // Stop the other workers before passing on the panic
+                drop(snd_main);
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+                panic!("Worker failed: {}", message);
+            },
+            Err(_) => panic!("All workers exited with {} tasks left", tasks),
         };
 
     };
+    drop(snd_main);
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    // The terms of the next window, which was not created, are all smaller than refterm
+    let converged = if timed_out { refterm.zeros } else { result.len() };
+    (result, converged, workspaces + 2)
+}
+
+#[allow(dead_code)]
+fn parallel_sum(generate: impl IntoIterator<Item = (bool, Number)>, nthreads: usize) -> Number {
+    // Sum up the terms of generate, subtracting the ones with the flag set like TaskParams.neg,
+    // with the additions spread over nthreads workers. Each worker keeps its own partial sum of
+    // the terms it receives, and the partial sums are added at the end. Addition modulo one is
+    // exact, so the result does not depend on which worker gets which term, and the terms may
+    // come in any order. All terms must have the same number of digits, and the sum of no terms
+    // is zero with DIGITS digits.
+    // The terms are generated on the calling thread, so this only pays off if they are cheap
+    // compared to adding them. ataninv_threaded2_until does not use it, since there the workers
+    // compute the terms as well, which is the expensive part. Summing the terms of AtanTerms
+    // with it gives the digits of ataninv_until, see the test parallel_sum_reproduces_arctan.
+    let mut terms = generate.into_iter();
+    let Some((neg, first)) = terms.next() else {
+        return Number::zero();
+    };
+    let len = first.len();
+    let mut result = Number::zero_with_len(len);
+    let add = |sum: &mut Number, neg: bool, term: &Number| {
+        if neg {
+            sum.sub_assign(term);
+        } else {
+            sum.wrapping_add_assign(term);
+        }
+    };
+    add(&mut result, neg, &first);
+    if nthreads == 0 {
+        for (neg, term) in terms {
+            add(&mut result, neg, &term);
+        }
+        return result;
+    }
+    // A bounded channel keeps the generator from running far ahead of the workers
+    let (snd, rcv) = bounded::<(bool, Number)>(2 * nthreads);
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..nthreads)
+            .map(|_| {
+                let rcv = rcv.clone();
+                scope.spawn(move || {
+                    let mut sum = Number::zero_with_len(len);
+                    for (neg, term) in rcv {
+                        add(&mut sum, neg, &term);
+                    }
+                    sum
+                })
+            })
+            .collect();
+        for term in terms {
+            snd.send(term).unwrap();
+        }
+        drop(snd);
+        for handle in handles {
+            result.wrapping_add_assign(&handle.join().unwrap());
+        }
+    });
     result
 }
 
-fn ataninv(x: Digit, nthreads: usize) -> Number {
+fn ataninv_until(x: Digit, limbs: usize, nthreads: usize, series: Series,
+        max_window: Option<usize>, overlap: bool, deadline: Option<Instant>)
+        -> (Number, usize, usize) {
+    // Like ataninv_threaded2_until, including the peak number of Numbers
     if nthreads == 0 {
-        ataninv_scalar(x)
+        let (result, converged) = ataninv_scalar_until(x, limbs, series, deadline);
+        // Only result and refterm
+        (result, converged, 2)
     } else {
-        ataninv_threaded2(x, nthreads)
+        ataninv_threaded2_until(x, limbs, nthreads, series, max_window, overlap, None, deadline,
+                                None, None)
     }
 }
 
-fn main() {
-    // Calculate pi using pi/4 = 4atan(1/5)-atan(1/239)
-    let args: Vec<String> = env::args().collect();
-    let nt = args[1].parse::<usize>().unwrap();
-    let (snd, rcv) = unbounded();
-    thread::spawn(move || {
-        snd.send(ataninv(239, nt)).unwrap();
+#[allow(dead_code)]
+fn ataninv_capped(x: Digit, limbs: usize, nthreads: usize, series: Series,
+        max_window: Option<usize>, overlap: bool, max_terms: usize) -> (Number, usize) {
+    // Like ataninv_until without a deadline, but summing at most max_terms terms after 1/x, so
+    // benchmarks do the same work in every run and configuration. The result is the partial sum
+    // of these terms, the same for any number of threads, and the second return value is the
+    // number of leading digits the remaining terms can no longer change, like for a deadline.
+    if nthreads == 0 {
+        let (result, stats) = ataninv_scalar_resume(ScalarState::new(x, limbs, series),
+                                                    |state| state.terms < max_terms);
+        (result, stats.converged)
+    } else {
+        let (result, converged, _) = ataninv_threaded2_until(x, limbs, nthreads, series,
+                                                             max_window, overlap, None, None,
+                                                             Some(max_terms), None);
+        (result, converged)
+    }
+}
+
+#[allow(dead_code)]
+fn ataninv_snapshots(x: Digit, limbs: usize, series: Series, ratio: usize)
+        -> (Number, Vec<(usize, Number)>) {
+    // Same as ataninv_scalar_until without deadline, but also returns copies of the partial sum
+    // after 1, ratio, ratio^2, ... terms after 1/x, each with its number of terms. For atan, the
+    // one after n terms is ataninv_range(x, 0, n + 1, limbs), so comparing them with a reference
+    // bisects the first term where a computation diverges, and they can be checked against the
+    // partial sums of distributed.rs.
+    // Each snapshot is a Number of limbs digits, and there are about log_ratio of the number of
+    // terms of them on top of result and refterm: with ratio 2 for atan(1/5) and 20000 limbs,
+    // 19 snapshots of 160 KB for 275,000 terms.
+    assert!(ratio >= 2, "The ratio must be at least 2");
+    let mut snapshots = Vec::new();
+    let mut next = 1;
+    let (result, _) = ataninv_scalar_resume(ScalarState::new(x, limbs, series), |state| {
+        if state.terms == next {
+            snapshots.push((state.terms, state.result.clone()));
+            next = next.saturating_mul(ratio);
+        }
+        true
     });
+    (result, snapshots)
+}
+
+fn set_to_inv_odd_power(result: &mut Number, x: Digit, j: usize) {
+    // result = 1/x^(2j+1), truncated like the refterms of the series, see div_assign. From 1/x
+    // on, it is divided by as many powers of x^2 at once as fit into a Digit.
+    let x2 = x_squared(x);
+    result.set_to_inv(x);
+    let mut skip = j;
+    while skip > 0 && !result.is_zero() {
+        let mut div = x2;
+        let mut powers = 1;
+        while powers < skip {
+            match div.checked_mul(x2) {
+                Some(next) => div = next,
+                None => break,
+            }
+            powers += 1;
+        }
+        *result /= div;
+        skip -= powers;
+    }
+}
+
+fn ataninv_range(x: Digit, start_term: usize, end_term: usize, limbs: usize) -> Number {
+    // Partial sum of the Taylor terms of atan(1/x) with start_term <= k < end_term, each term being
+    // (-1)^k/((2k+1) x^(2k+1)), with the given number of digits. The partial sums of consecutive
+    // ranges, for example computed on different machines, add up to ataninv_scalar_until.
+    // By the invariant of div_assign, every term is truncated like in the whole series and the sum
+    // of the ranges equals it in every digit.
+    if end_term <= start_term {
+        return Number::zero_with_len(limbs);
+    }
+    // Before term k, k - 1 terms after 1/x are summed, see ScalarState::at_term
+    let state = ScalarState::at_term(x, limbs, Series::Atan, start_term);
+    ataninv_scalar_resume(state, |state| state.terms + 1 < end_term).0
+}
+
+#[allow(dead_code)]
+fn ataninv_signed(x: i64, limbs: usize, nthreads: usize) -> Number {
+    // atan(1/x) for a signed x with the given number of digits. Since atan is odd, this is the
+    // negated atan(1/|x|) for negative x.
+    let abs = Digit::try_from(x.unsigned_abs()).expect("|x| does not fit into a Digit");
+    let mut result = ataninv_until(abs, limbs, nthreads, Series::Atan, None, false, None).0;
+    if x < 0 {
+        result.negate();
+    }
+    result
+}
+
+fn atan_term_count(x: Digit, limbs: usize) -> usize {
+    // Number of Taylor terms 1/((2k+1) x^(2k+1)) of atan(1/x), starting with k = 0, that do not
+    // vanish at the given precision, i.e. that are at least 2^-(Digit::BITS*limbs). This is
+    // estimated with logarithms, by solving (2k+1) ln(x) + ln(2k+1) = bits ln(2) for k. Since
+    // ln(2k+1) changes slowly, a few fixed point iterations starting without it suffice. For
+    // x = 2, 5, 18, 57 and 239 at 10 and 1000 limbs, this is exactly the number of non-zero
+    // terms of AtanTerms.
+    assert!(x > 1, "atan(1/x) only converges for x > 1");
+    let bits = (limbs * Digit::BITS as usize) as f64 * std::f64::consts::LN_2;
+    let lnx = (x as f64).ln();
+    let mut k = (bits / lnx - 1.0) / 2.0;
+    for _ in 0..4 {
+        k = ((bits - (2.0 * k.max(0.0) + 1.0).ln()) / lnx - 1.0) / 2.0;
+    }
+    if k < 0.0 { 0 } else { k as usize + 1 }
+}
+
+/*
+ * Threading model for computing several arctan terms at once: There are two levels. On the outer
+ * level, each arctan term gets its own driver thread, the first term uses the calling thread. On
+ * the inner level, the nthreads worker threads are split among the terms, proportionally to the
+ * number of Taylor terms each one needs. A driver with workers runs ataninv_threaded2_until,
+ * which only hands out tasks and sums up the results. A driver without workers computes its term
+ * with ataninv_scalar_until. So there are exactly nthreads worker threads plus one driver per
+ * arctan term.
+ */
+fn split_threads(xs: &[Digit], nthreads: usize) -> Vec<usize> {
+    // Distribute nthreads among the arctan terms. The number of Taylor terms for atan(1/x) is
+    // inversely proportional to log(x). The threads left after rounding down go to the terms with
+    // the largest remainders.
+    let weights: Vec<f64> = xs.iter().map(|&x| 1.0 / (x as f64).ln()).collect();
+    let total: f64 = weights.iter().sum();
+    let shares: Vec<f64> = weights.iter().map(|w| w / total * nthreads as f64).collect();
+    let mut counts: Vec<usize> = shares.iter().map(|&share| share as usize).collect();
+    let mut order: Vec<usize> = (0..xs.len()).collect();
+    order.sort_by(|&a, &b| {
+        let rem = |i: usize| shares[i] - counts[i] as f64;
+        rem(b).partial_cmp(&rem(a)).unwrap()
+    });
+    let left = nthreads - counts.iter().sum::<usize>();
+    for &i in order.iter().take(left) {
+        counts[i] += 1;
+    }
+    counts
+}
+
+#[allow(dead_code)]
+fn ataninv_batch_detailed(formula: &[(i64, Digit)], nthreads: usize) -> (Number, Vec<Number>) {
+    // Compute a Machin-like formula, the sum over c*atan(1/x) for all pairs (c, x), and return the
+    // sum together with each scaled term c*atan(1/x). Like with mul4, integer parts are dropped.
+    // Each term is a full Number of DIGITS digits (160 KB at the default precision) that is kept
+    // until the caller drops it, on top of the sum. Folding the terms right away would need only
+    // the sum.
+    let xs: Vec<Digit> = formula.iter().map(|&(_, x)| x).collect();
+    let mut terms = ataninv_batch(&xs, nthreads, None, false);
+    let mut sum = Number::zero();
+    for (term, &(c, _)) in terms.iter_mut().zip(formula) {
+        term.wrapping_mul_small(c.unsigned_abs() as Digit);
+        if c < 0 {
+            term.negate();
+        }
+        sum.wrapping_add_assign(term);
+    }
+    (sum, terms)
+}
+
+fn ataninv_batch(xs: &[Digit], nthreads: usize, max_window: Option<usize>, overlap: bool)
+        -> Vec<Number> {
+    // Compute atan(1/x) for all xs in parallel, using nthreads workers in total.
+    ataninv_batch_until(xs, DIGITS, nthreads, Series::Atan, max_window, overlap, None)
+        .into_iter().map(|(atan, _)| atan).collect()
+}
+
+fn ataninv_batch_until(xs: &[Digit], limbs: usize, nthreads: usize, series: Series,
+        max_window: Option<usize>, overlap: bool, deadline: Option<Instant>)
+        -> Vec<(Number, usize)> {
+    ataninv_batch_timed(xs, limbs, nthreads, series, max_window, overlap, deadline)
+        .into_iter().map(|(value, converged, _, _)| (value, converged)).collect()
+}
+
+fn ataninv_batch_timed(xs: &[Digit], limbs: usize, nthreads: usize, series: Series,
+        max_window: Option<usize>, overlap: bool, deadline: Option<Instant>)
+        -> Vec<(Number, usize, Duration, usize)> {
+    // Same as ataninv_batch_until, but also returns how long each term took from the start of the
+    // batch, and the peak number of Numbers it needed. The terms are computed at the same time,
+    // so these durations overlap, and the peaks add up.
+    let terms: Vec<(Digit, Series)> = xs.iter().map(|&x| (x, series)).collect();
+    ataninv_batch_series(&terms, limbs, nthreads, max_window, overlap, deadline)
+}
+
+fn ataninv_batch_series(terms: &[(Digit, Series)], limbs: usize, nthreads: usize,
+        max_window: Option<usize>, overlap: bool, deadline: Option<Instant>)
+        -> Vec<(Number, usize, Duration, usize)> {
+    // Same as ataninv_batch_timed, with a series for each x. atanh(1/x) needs as many terms as
+    // atan(1/x), so the threads are split the same way.
+    let xs: Vec<Digit> = terms.iter().map(|&(x, _)| x).collect();
+    let counts = split_threads(&xs, nthreads);
+    let start = Instant::now();
+    let timed = |(x, series), n| {
+        let (value, converged, buffers) =
+            ataninv_until(x, limbs, n, series, max_window, overlap, deadline);
+        (value, converged, start.elapsed(), buffers)
+    };
+    thread::scope(|scope| {
+        let handles: Vec<_> = terms.iter().zip(&counts).skip(1)
+            .map(|(&term, &n)| scope.spawn(move || timed(term, n)))
+            .collect();
+        let mut result = vec![timed(terms[0], counts[0])];
+        result.extend(handles.into_iter().map(|handle| handle.join().unwrap()));
+        result
+    })
+}
+
+fn peak_buffers(buffers: &[usize]) -> usize {
+    // Peak number of Numbers of a formula, given the peaks of all its arctans from
+    // ataninv_batch_timed. combine_arctans then needs one for each of the sums of the terms with
+    // positive and negative coefficients while all arctans are still alive.
+    buffers.iter().sum::<usize>().max(buffers.len() + 2)
+}
+
+fn buffer_bytes(buffers: usize, limbs: usize) -> usize {
+    // Memory of the digits of the given number of Numbers with limbs digits each
+    buffers * limbs * mem::size_of::<Digit>()
+}
+
+fn combine_arctans(terms: &[(i64, Number)]) -> (Number, i64) {
+    // Compute the sum over c*atan for all pairs (c, atan), with each atan interpreted as an
+    // unsigned fraction in [0, 1) like in mul_small. Returns the fractional part of the sum and
+    // its integer part, for example (pi - 3, 3) for [(16, atan(1/5)), (-4, atan(1/239))].
+    // The terms with positive and with negative coefficients are summed with combine_scaled, and
+    // the integer part of the difference is the one of the positive sum minus the one of the
+    // negative sum, minus one if the fractional parts borrow.
+    let len = terms.first().map_or(DIGITS, |(_, atan)| atan.len());
+    let part = |neg: bool| {
+        let scaled: Vec<(Digit, &Number)> = terms.iter()
+            .filter(|(c, _)| (*c < 0) == neg)
+            .map(|(c, atan)| (c.unsigned_abs() as Digit, atan))
+            .collect();
+        Number::combine_scaled(&scaled, len)
+    };
+    let (mut frac, pos) = part(false);
+    let (sub, neg) = part(true);
+    let borrow = (0..len).map(|i| (frac.digits[i], sub.digits[i])).find(|(a, b)| a != b)
+        .is_some_and(|(a, b)| a < b);
+    frac.sub_assign(&sub);
+    (frac, pos as i64 - neg as i64 - borrow as i64)
+}
+
+fn evaluate_machin_like(terms: &[(i64, Digit)], use_atanh: bool, limbs: usize, nthreads: usize)
+        -> (Number, i64) {
+    // Compute the sum over c*atan(1/x), or c*atanh(1/x) if use_atanh is set, for all pairs
+    // (c, x), with the given number of digits and using nthreads workers in total. Returns the
+    // fractional and the integer part like combine_arctans. For example [(16, 5), (-4, 239)]
+    // gives pi with atan, and [(2, 3)] gives ln(2) with atanh.
+    let series = if use_atanh { Series::Atanh } else { Series::Atan };
+    let xs: Vec<Digit> = terms.iter().map(|&(_, x)| x).collect();
+    let values = ataninv_batch_until(&xs, limbs, nthreads, series, None, false, None);
+    let scaled: Vec<(i64, Number)> = terms.iter().zip(values)
+        .map(|(&(c, _), (value, _))| (c, value))
+        .collect();
+    combine_arctans(&scaled)
+}
 
-    let mut pi = ataninv(5, nt);
-    pi.mul4();
-    pi.sub_assign(&rcv.recv().unwrap());
-    // Note that this takes the number outside the representable range by creating a value larger
-    // than one, which overflows and drops the integer part, but that one is known to be 3.
-    pi.mul4();
+#[allow(dead_code)]
+fn compute_pi_timed(duration: Duration, nthreads: usize) -> (Number, usize) {
+    // Compute as many digits of pi as possible in roughly the given time. Returns the fractional
+    // part of pi like main computes it and the number of correct decimal digits, up to a carry
+    // into the last one. The series are only stopped between two terms, so the result is a valid
+    // Number, just with fewer meaningful digits.
+    let deadline = Instant::now() + duration;
+    let mut atans = ataninv_batch_until(&[5, 239], DIGITS, nthreads, Series::Atan, None, false,
+                                        Some(deadline));
+    let (atan239, converged239) = atans.pop().unwrap();
+    let (atan5, converged5) = atans.pop().unwrap();
+    let (pi, int_part) = combine_arctans(&[(16, atan5), (-4, atan239)]);
+    assert_eq!(int_part, 3, "Integer part of pi is wrong");
+    // The series tails are below one unit in the last converged digit. The combination scales
+    // them by 16 and 4 and every term adds a truncation error of one unit in the last digit, so
+    // one digit less is still correct.
+    let bits = min(converged5, converged239).saturating_sub(1) * Digit::BITS as usize;
+    (pi, (bits as f64 * 2f64.log10()) as usize)
+}
+
+fn check_decimal_digits(pi: &Number, extra_limbs: usize) -> usize {
+    // Recompute the fractional part of pi with extra_limbs more digits and return how many of the
+    // decimal digits of pi agree with it. Without guard digits, the truncation of every term
+    // accumulates in the last limb, so some of the last decimal digits may be wrong. This repeats
+    // the whole computation with ataninv_range, so it is more expensive than the computation
+    // itself and only done on request.
+    let limbs = pi.len() + extra_limbs;
+    let (reference, _) = combine_arctans(&[
+        (16, ataninv_range(5, 0, usize::MAX, limbs)),
+        (-4, ataninv_range(239, 0, usize::MAX, limbs)),
+    ]);
+    pi.decimal_agreement(&reference)
+}
+
+fn group_thousands(n: usize) -> String {
+    // Format n with commas between groups of three digits
+    let digits = n.to_string();
+    let mut result = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            result.push(',');
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn precision_info() -> String {
+    // Describe the precision this binary was built with, which is the one of the main path
+    // without --digits. It has no guard limbs, all DIGITS limbs are part of the result. With
+    // --digits, limbs_for_precision adds computation::GUARD_LIMBS on top of the requested digits.
+    let bits = DIGITS * Digit::BITS as usize;
+    let decimal = Number::<Vec<Digit>>::zero().decimal_digits();
+    format!("{} limbs \u{d7} {} bits = {} bits \u{2248} {} decimal digits\n\
+             limb base: 2^{}\n\
+             guard limbs: 0 for the fixed DIGITS main path, {} with --digits\n",
+            group_thousands(DIGITS), Digit::BITS, group_thousands(bits), group_thousands(decimal),
+            Digit::BITS, computation::GUARD_LIMBS)
+}
+
+fn subcommand_arg<T: FromStr>(args: &[String], i: usize, name: &str, default: Option<T>) -> T {
+    // Argument i of a subcommand of main, or default if there are fewer. Like the other errors on
+    // the command line, a missing argument without a default or one that does not parse panics.
+    match args.get(i) {
+        Some(arg) => arg.parse().unwrap_or_else(|_| panic!("Invalid {} '{}'", name, arg)),
+        None => default.unwrap_or_else(|| panic!("Missing {}", name)),
+    }
+}
+
+fn main() {
+    // Calculate pi using pi/4 = 4atan(1/5)-atan(1/239), or another formula of computation::Algo
+    // Usage: picalc <nthreads> [--output path:format]... [--window <terms>] [--overlap]
+    //                          [--check-digits] [--timing] [--config path]
+    //                          [--digits <n>] [--algorithm machin|gauss]
+    //                          [--range <first>:<last>] [--digit <n>]
+    //        picalc [--config path] [options]...   if the config file sets threads
+    //        picalc info
+    //        picalc bench <name> [<digits>] [<nthreads>]   see bench.rs
+    //        picalc stream
+    //        picalc range-worker   compute the partial sum requested on stdin, see distributed.rs
+    //        picalc analyze <digits> [<extra limbs>] [<nthreads>]   see analyze_precision
+    //        picalc vote <digits> <algorithm>,... [<nthreads>]   see compute_pi_voted
+    //        picalc formulas <path> [<digits>] [<nthreads>]   see formula_file.rs
+    let args: Vec<String> = env::args().skip(1).collect();
+    let first = args.first().expect("Missing number of threads");
+    if first == "info" {
+        print!("{}", precision_info());
+        return;
+    }
+    if first == "stream" {
+        // Print the digits while they are computed, with ataninv_scalar_observed
+        stream::stream_pi(DIGITS, &mut io::stdout()).unwrap();
+        return;
+    }
+    if first == "bench" {
+        let name: String = subcommand_arg(&args, 1, "benchmark", None);
+        let report = bench::run_benchmark(&name, subcommand_arg(&args, 2, "digits", Some(100000)),
+                                          subcommand_arg(&args, 3, "number of threads", Some(0)))
+            .unwrap_or_else(|err| panic!("{}", err));
+        print!("{}", report);
+        return;
+    }
+    if first == "analyze" {
+        let diff = computation::analyze_precision(
+            subcommand_arg(&args, 1, "digits", Some(1000)),
+            subcommand_arg(&args, 2, "extra limbs", Some(2)),
+            subcommand_arg(&args, 3, "number of threads", Some(0)));
+        println!("{} limbs compared with {} more", diff.limbs, diff.extra_limbs);
+        println!("equal limbs: {} of {}", diff.equal_limbs, diff.limbs);
+        println!("equal decimal digits: {} of {}, {} guaranteed", diff.equal_digits,
+                 diff.decimal_digits, diff.correct_digits);
+        print!("{}", diff.report);
+        return;
+    }
+    if first == "vote" {
+        let digits = subcommand_arg(&args, 1, "digits", None);
+        let names: String = subcommand_arg(&args, 2, "algorithms", None);
+        let algos: Vec<computation::Algo> = names.split(',')
+            .map(|algo| algo.parse().unwrap_or_else(|err| panic!("{}", err)))
+            .collect();
+        let nthreads = subcommand_arg(&args, 3, "number of threads", Some(0));
+        let voted = computation::compute_pi_voted(digits, nthreads, &algos);
+        println!("{} computations, {} guaranteed digits", algos.len(), voted.correct_digits);
+        println!("disputed limbs: {:?}", voted.disputed);
+        println!("without majority: {:?}", voted.undecided);
+        println!("pi = {}.{}...", voted.int_part, voted.frac.leading_digits_decimal(20));
+        println!("digest: {}", voted.frac.digest());
+        return;
+    }
+    if first == "formulas" {
+        let path: String = subcommand_arg(&args, 1, "formula file", None);
+        let checks = formula_file::check_formula_file(
+            &path, subcommand_arg(&args, 2, "digits", Some(1000)),
+            subcommand_arg(&args, 3, "number of threads", Some(0)))
+            .unwrap_or_else(|err| panic!("{}", err));
+        for check in &checks {
+            let counts: Vec<String> = check.term_counts.iter().map(usize::to_string).collect();
+            println!("line {}: {} {} = {} pi/4, error 2^{:.1} with bound 2^{:.1}, terms {}",
+                     check.line, if check.pass { "pass" } else { "FAIL" },
+                     formula_file::format_formula(&check.terms), check.multiple, check.error_log2,
+                     check.bound_log2, counts.join(" "));
+        }
+        let passed = checks.iter().filter(|check| check.pass).count();
+        println!("{} of {} formulas pass", passed, checks.len());
+        return;
+    }
+    if first == "range-worker" {
+        distributed::compute_range_request(&mut io::stdin().lock(), &mut io::stdout().lock())
+            .unwrap_or_else(|err| panic!("{}", err));
+        return;
+    }
+    // The config file is read first, wherever --config is, so the other arguments override it
+    let mut config = match args.iter().position(|arg| arg == "--config") {
+        Some(i) => {
+            let path = args.get(i+1).expect("Missing argument to --config");
+            Config::from_file(path).unwrap_or_else(|err| panic!("{}", err))
+        },
+        None => Config::default(),
+    };
+    let mut args = args.iter().map(String::as_str).peekable();
+    if let Some(nt) = args.next_if(|arg| !arg.starts_with("--")) {
+        config.threads = Some(nt.parse::<usize>().unwrap());
+    }
+    let mut cli_outputs = Vec::new();
+    let mut set = |key: &str, value: &str| {
+        config.set(key, value).unwrap_or_else(|err| panic!("{}", err));
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| panic!("Missing argument to {}", arg));
+        match arg {
+            "--config" => { value(); },
+            "--check-digits" => set("check_digits", "true"),
+            "--timing" => set("timing", "true"),
+            "--overlap" => set("overlap", "true"),
+            "--window" => set("window", value()),
+            "--digits" => set("digits", value()),
+            "--algorithm" => set("algorithm", value()),
+            "--range" => set("range", value()),
+            "--digit" => {
+                let n = value();
+                set("range", &format!("{}:{}", n, n));
+            },
+            "--output" => cli_outputs.push(value().parse::<OutputSpec>().unwrap()),
+            _ => panic!("Unknown argument '{}'", arg),
+        }
+    }
+    if !cli_outputs.is_empty() {
+        config.outputs = cli_outputs;
+    }
+    let Config { threads, limbs, algo, max_window, overlap, check_digits, timing, outputs, range } =
+        config;
+    let nt = threads.expect("Missing number of threads");
+    let formula = algo.formula();
+    let xs: Vec<Digit> = formula.iter().map(|&(_, x)| x).collect();
+    let (atans, (times, buffers)): (Vec<Number>, (Vec<Duration>, Vec<usize>)) =
+        ataninv_batch_timed(&xs, limbs, nt, Series::Atan, max_window, overlap, None)
+            .into_iter().map(|(atan, _, time, buffers)| (atan, (time, buffers))).unzip();
+    // Note that 16*atan(1/5) is outside the representable range. combine_arctans drops the
+    // integer part from the result and returns it separately, it must be 3.
+    let start = Instant::now();
+    let scaled: Vec<(i64, Number)> = formula.iter().zip(atans)
+        .map(|(&(c, _), atan)| (c, atan))
+        .collect();
+    let (pi, int_part) = combine_arctans(&scaled);
+    assert_eq!(int_part, 3, "Integer part of pi is wrong");
+    let time_combine = start.elapsed();
     //pi.print();
+    let mut output_times = Vec::new();
+    for output in &outputs {
+        let start = Instant::now();
+        output.write(int_part as Digit, &pi).unwrap();
+        output_times.push(start.elapsed());
+    }
+    if let Some((first, last)) = range {
+        println!("{}", pi.decimal_range(first, last));
+    }
+    if timing {
+        // All arctans run at the same time, each line is the time since the start of its phase
+        for (&x, time) in xs.iter().zip(&times) {
+            eprintln!("{:<30} {:>10.3} s", format!("atan(1/{})", x), time.as_secs_f64());
+        }
+        eprintln!("{:<30} {:>10.3} s", "combine", time_combine.as_secs_f64());
+        for (output, time) in outputs.iter().zip(&output_times) {
+            eprintln!("{:<30} {:>10.3} s", output.to_string(), time.as_secs_f64());
+        }
+        let peak = peak_buffers(&buffers);
+        eprintln!("{:<30} {:>10} Numbers, {} bytes", "peak memory", peak,
+                  group_thousands(buffer_bytes(peak, limbs)));
+    }
+    if check_digits {
+        // Two more limbs cover the accumulated truncation error of all terms many times over
+        let correct = check_decimal_digits(&pi, 2);
+        if correct < pi.decimal_digits() {
+            eprintln!("Warning: only the first {} of {} decimal digits are correct, the guard \
+                       margin is too small", correct, pi.decimal_digits());
+        }
+    }
+
+    profile::print_summary();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, RecvTimeoutError};
+
+    fn number(digits: &[Digit]) -> Number {
+        Number::from_digits(digits)
+    }
+
+    fn assert_zeros_valid(n: &Number) {
+        // zeros is a lower bound for the first non-zero digit
+        assert!((0..n.zeros.min(n.len())).all(|i| n.digits[i] == 0),
+                "zeros = {} but a digit before it is not zero", n.zeros);
+    }
+
+    fn random_digits(seed: &mut u64, len: usize, leading_zeros: usize) -> Vec<Digit> {
+        // Digits that are mostly 0, 1 and MAX, so carries and borrows run across many of them,
+        // from a xorshift generator
+        (0..len).map(|i| {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            match (i < leading_zeros, *seed % 4) {
+                (true, _) => 0,
+                (_, 0) => 0,
+                (_, 1) => 1,
+                (_, 2) => Digit::MAX,
+                _ => (*seed >> 8) as Digit,
+            }
+        }).collect()
+    }
+
+    fn reference_sub(a: &[Digit], b: &[Digit]) -> Vec<Digit> {
+        // a - b digit by digit from the last one, dropping the borrow out of the first one
+        let mut result = vec![0; a.len()];
+        let mut borrow = false;
+        for i in (0..a.len()).rev() {
+            let (diff, b1) = a[i].overflowing_sub(b[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as Digit);
+            result[i] = diff;
+            borrow = b1 || b2;
+        }
+        result
+    }
+
+    fn reference_add(a: &[Digit], b: &[Digit]) -> Vec<Digit> {
+        // a + b digit by digit from the last one, dropping the carry out of the first one
+        let mut result = vec![0; a.len()];
+        let mut carry = false;
+        for i in (0..a.len()).rev() {
+            let (sum, c1) = a[i].overflowing_add(b[i]);
+            let (sum, c2) = sum.overflowing_add(carry as Digit);
+            result[i] = sum;
+            carry = c1 || c2;
+        }
+        result
+    }
+
+    fn check_sub(a: &[Digit], b: &[Digit]) {
+        let mut result = number(a);
+        let rhs = number(b);
+        result.sub_assign(&rhs);
+        assert_eq!(result.digits, reference_sub(a, b), "{:x?} - {:x?}", a, b);
+        assert_zeros_valid(&result);
+    }
+
+    #[test]
+    fn sub_assign_without_borrow_into_leading_zeros_of_rhs() {
+        // rhs.zeros = 2, and the early return starts right there
+        check_sub(&[5, 7, 9, 3], &[0, 0, 4, 1]);
+        // The last digit borrows, but digit 2 absorbs it
+        check_sub(&[5, 0, 2, 3], &[0, 0, 1, 4]);
+    }
+
+    #[test]
+    fn sub_assign_with_borrow_across_leading_zeros_of_rhs() {
+        // Digit 2 passes the borrow on into digit 1, which is zero and passes it further
+        check_sub(&[5, 0, 0, 3], &[0, 0, 1, 4]);
+        // The borrow stops in the first digit above rhs.zeros, which becomes zero
+        check_sub(&[0, 1, 0, 3], &[0, 0, 1, 4]);
+        check_sub(&[1, 0, 0, 0, 0], &[0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn sub_assign_wraps_below_zero() {
+        check_sub(&[0, 0, 1], &[0, 0, 2]);
+        check_sub(&[0, 1, 0], &[0, 2, 0]);
+        check_sub(&[0, 0, 0], &[0, 0, 1]);
+        check_sub(&[3, 0, 0, 7], &[4, 0, 0, 8]);
+    }
+
+    #[test]
+    fn sub_assign_of_zero_and_from_zero() {
+        check_sub(&[1, 2, 3], &[0, 0, 0]);
+        check_sub(&[0, 0, 0], &[0, 5, 0]);
+    }
+
+    #[test]
+    fn sub_assign_matches_reference_on_random_digits() {
+        let mut seed = 0x9e3779b97f4a7c15;
+        for len in 1..8 {
+            for rhs_zeros in 0..=len {
+                for self_zeros in 0..=len {
+                    for _ in 0..20 {
+                        let a = random_digits(&mut seed, len, self_zeros);
+                        let b = random_digits(&mut seed, len, rhs_zeros);
+                        check_sub(&a, &b);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn add_assign_keeps_carry_above_leading_zeros_of_rhs() {
+        // The carry out of the only non-zero digit of rhs runs through two digits of MAX
+        let mut result = number(&[0, Digit::MAX, Digit::MAX]);
+        result.wrapping_add_assign(&number(&[0, 0, 1]));
+        assert_eq!(result.digits, [1, 0, 0]);
+        assert_zeros_valid(&result);
+        let mut seed = 0x2545f4914f6cdd1d;
+        for len in 1..8 {
+            for rhs_zeros in 0..=len {
+                for _ in 0..50 {
+                    let a = random_digits(&mut seed, len, 0);
+                    let b = random_digits(&mut seed, len, rhs_zeros);
+                    let mut result = number(&a);
+                    result.wrapping_add_assign(&number(&b));
+                    assert_eq!(result.digits, reference_add(&a, &b), "{:x?} + {:x?}", a, b);
+                    assert_zeros_valid(&result);
+                }
+            }
+        }
+    }
+
+    // 2^31 + 1 with 64 bit digits, the largest kind of x whose square still fits
+    const LARGE_X: Digit = (1 << (Digit::BITS / 2 - 1)) + 1;
+
+    #[test]
+    fn ataninv_threaded2_agrees_with_scalar_for_any_threads_and_window() {
+        for x in [5, 239, LARGE_X] {
+            let limbs = 8;
+            let (expected, _) = ataninv_scalar_until(x, limbs, Series::Atan, None);
+            for nthreads in 1..=8 {
+                for max_window in [None, Some(1), Some(2), Some(5)] {
+                    for overlap in [false, true] {
+                        let (result, converged, _) = ataninv_threaded2_until(
+                            x, limbs, nthreads, Series::Atan, max_window, overlap, None, None,
+                            None, None);
+                        assert_eq!(result.digits, expected.digits,
+                                   "x = {}, {} threads, window {:?}, overlap {}",
+                                   x, nthreads, max_window, overlap);
+                        assert_eq!(converged, limbs);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ataninv_threaded2_completes_with_small_capacity() {
+        // Capacity 0 hands each task over directly, so the driver waits for a worker at every
+        // task. A deadlock would leave the receiver without a result.
+        let limbs = 8;
+        let (expected, _) = ataninv_scalar_until(5, limbs, Series::Atan, None);
+        for capacity in [0, 1, 2] {
+            for nthreads in [1, 2, 5] {
+                for (max_window, overlap) in [(None, false), (Some(1), false), (None, true)] {
+                    let (sender, receiver) = mpsc::channel();
+                    thread::spawn(move || {
+                        let (result, _, _) = ataninv_threaded2_until(
+                            5, limbs, nthreads, Series::Atan, max_window, overlap, Some(capacity),
+                            None, None, None);
+                        sender.send(result).unwrap();
+                    });
+                    let settings = format!("capacity {}, {} threads, window {:?}, overlap {}",
+                                           capacity, nthreads, max_window, overlap);
+                    let result = receiver.recv_timeout(Duration::from_secs(10))
+                        .unwrap_or_else(|err| panic!("No result with {}: {}", settings, err));
+                    assert_eq!(result.digits, expected.digits, "{}", settings);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Worker failed: UpdateTerm task with divisor 0 * 1 panicked: attempt \
+                               to divide by zero")]
+    fn ataninv_threaded2_reports_worker_panic() {
+        // The third term is in the first window, which is handed out before any result comes back
+        ZERO_DIVISOR_IDX.set(3);
+        ataninv_threaded2_until(5, 8, 2, Series::Atan, None, false, None, None, None, None);
+    }
+
+    #[test]
+    fn ataninv_threaded2_finishing_in_first_window() {
+        // At one limb, these series end within the first window, so the zero comes back while the
+        // other tasks of the window are still queued. They must be summed all the same. It takes
+        // a single limb: with two, x^(2k+1) (2k+1) can not exceed the precision while x^(2k) (2k+1)
+        // fits a Digit, so no series would end before the second window.
+        let limbs = 1;
+        let xs: [Digit; 3] = if Digit::BITS == 64 { [1000, 10_000, LARGE_X] }
+                             else { [100, 3000, LARGE_X] };
+        for x in xs {
+            // The window takes terms while x^(2k) (2k+1) fits, but at least one
+            let x2 = x_squared(x);
+            let window = (1..).take_while(|&k| {
+                x2.checked_pow(k).and_then(|d| d.checked_mul(2*k as Digit + 1)).is_some()
+            }).count().max(1);
+            let mut next_refterm = Number::zero_with_len(limbs);
+            set_to_inv_odd_power(&mut next_refterm, x, window);
+            assert!(next_refterm.is_zero(), "x = {} needs more than one window", x);
+            let (expected, _) = ataninv_scalar_until(x, limbs, Series::Atan, None);
+            for nthreads in 1..=4 {
+                for overlap in [false, true] {
+                    let progress = AtomicUsize::new(0);
+                    let (result, converged, _) = ataninv_threaded2_until(
+                        x, limbs, nthreads, Series::Atan, None, overlap, None, None, None,
+                        Some(&progress));
+                    assert_eq!(result.digits, expected.digits, "x = {}, {} threads, overlap {}",
+                               x, nthreads, overlap);
+                    assert_eq!(converged, limbs);
+                    // Every term of the window was summed
+                    assert_eq!(progress.load(Ordering::Relaxed), window);
+                }
+            }
+        }
+    }
+
+    // 2^32 - 1 with 64 bit digits, the largest x whose square fits
+    const MAX_X: Digit = (1 << (Digit::BITS / 2)) - 1;
+
+    #[test]
+    fn ataninv_threaded2_with_largest_x() {
+        for limbs in [1, 2, 4] {
+            let (expected, _) = ataninv_scalar_until(MAX_X, limbs, Series::Atan, None);
+            for nthreads in 1..=3 {
+                let (result, _, _) = ataninv_threaded2_until(MAX_X, limbs, nthreads, Series::Atan,
+                                                             None, false, None, None, None,
+                                                             None);
+                assert_eq!(result.digits, expected.digits, "{} limbs, {} threads", limbs, nthreads);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "u32-digits"),
+               should_panic(expected = "x = 4294967297 is too large, x^2 does not fit into a \
+                                        Digit"))]
+    #[cfg_attr(feature = "u32-digits",
+               should_panic(expected = "x = 65537 is too large, x^2 does not fit into a Digit"))]
+    fn ataninv_threaded2_rejects_too_large_x() {
+        ataninv_threaded2_until(MAX_X + 2, 4, 2, Series::Atan, None, false, None, None, None,
+                                None);
+    }
+
+    // SHA-256 of the raw output of pi with DIGITS digits, which is the same for u64 and u32 digits
+    // since both write the limbs as big endian, and of the decimal output. The first 385,314 of
+    // the 385,318 decimal digits agree with an independent computation, the last ones are
+    // truncated.
+    const GOLDEN_RAW: &str = "3b9ebdc01589db459bd4509f6250aadc9cf538bf27740463463c9905b7fa6c70";
+    const GOLDEN_DECIMAL: &str = "dcc443b9e4b84bb557143bde087f32b7ee3225756859bf13b2920612f9131115";
+
+    #[test]
+    #[ignore = "takes about a minute, run with cargo test --release -- --ignored"]
+    fn pi_matches_golden_digests() {
+        // Makes sure a change does not alter the output at the full default precision
+        use sha2::{Digest, Sha256};
+        let (pi, int_part) = evaluate_machin_like(&[(16, 5), (-4, 239)], false, DIGITS, 0);
+        assert_eq!(int_part, 3);
+        assert_eq!(pi.digest(), GOLDEN_RAW);
+        // Like the decimal output
+        let mut decimal = b"3.".to_vec();
+        pi.write_decimal(&mut decimal).unwrap();
+        decimal.push(b'\n');
+        let digest: String = Sha256::digest(&decimal).iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert_eq!(digest, GOLDEN_DECIMAL);
+    }
+
+    #[test]
+    fn ataninv_scalar_until_agrees_with_naive() {
+        for x in [2, 3, 5, 18, 57, 239, LARGE_X] {
+            for limbs in [1, 2, 5, 12] {
+                let (expected, _) = ataninv_scalar_until(x, limbs, Series::Atan, None);
+                assert_eq!(ataninv_scalar_naive(x, limbs).digits, expected.digits,
+                           "x = {}, {} limbs", x, limbs);
+            }
+        }
+    }
+
+    #[test]
+    fn div_assign_parallel_agrees_with_div_assign() {
+        let mut seed = 0x853c49e6748fea9b;
+        for len in [1, 2, 5, 16, 33] {
+            for leading_zeros in [0, 1, len / 2] {
+                for x in [3, 239, LARGE_X, Digit::MAX] {
+                    for nthreads in 1..=5 {
+                        let digits = random_digits(&mut seed, len, leading_zeros);
+                        let mut expected = number(&digits);
+                        expected /= x;
+                        let mut result = number(&digits);
+                        result.div_assign_parallel(x, nthreads);
+                        assert_eq!(result.digits, expected.digits, "{:x?} / {}, {} threads",
+                                   digits, x, nthreads);
+                        assert_zeros_valid(&result);
+                    }
+                }
+            }
+        }
+    }
+
+    fn signed(n: &Number) -> i128 {
+        // The value of a Number with one digit in [-0.5, 0.5), in units of 2^-Digit::BITS
+        let d = n.digits[0] as i128;
+        if d >> (Digit::BITS - 1) == 1 { d - (1 << Digit::BITS) } else { d }
+    }
+
+    fn in_range(v: i128) -> bool {
+        (-(1 << (Digit::BITS - 1))..1 << (Digit::BITS - 1)).contains(&v)
+    }
+
+    #[test]
+    fn checked_arithmetic_fails_without_changing_value() {
+        // With one digit, the exact results are i128 values, so it is known which ones fit
+        let mut seed = 0xda942042e4dd58b5;
+        for _ in 0..2000 {
+            let a = number(&random_digits(&mut seed, 1, 0));
+            let b = number(&random_digits(&mut seed, 1, 0));
+            let m = random_digits(&mut seed, 1, 0)[0] >> (seed % Digit::BITS as u64);
+            let mut result = a.clone();
+            let mut wrapped = a.clone();
+            wrapped.wrapping_mul4();
+            let ok = result.checked_mul4().is_ok();
+            assert_eq!(ok, in_range(4 * signed(&a)), "4 * {:x?}", a.digits);
+            assert_eq!(&result.digits, if ok { &wrapped.digits } else { &a.digits });
+            let mut result = a.clone();
+            let mut wrapped = a.clone();
+            wrapped.wrapping_mul_small(m);
+            let ok = result.checked_mul_small(m).is_ok();
+            assert_eq!(ok, in_range(m as i128 * signed(&a)), "{} * {:x?}", m, a.digits);
+            assert_eq!(&result.digits, if ok { &wrapped.digits } else { &a.digits });
+            let mut result = a.clone();
+            let mut wrapped = a.clone();
+            wrapped.wrapping_add_assign(&b);
+            let ok = result.checked_add_assign(&b).is_ok();
+            assert_eq!(ok, in_range(signed(&a) + signed(&b)), "{:x?} + {:x?}", a.digits, b.digits);
+            assert_eq!(&result.digits, if ok { &wrapped.digits } else { &a.digits });
+        }
+    }
+
+    #[test]
+    fn arithmetic_follows_checked_feature() {
+        // 1/4 + 1/4 and 4/4 leave the range, 3 * -1/8 does not
+        let quarter = || number(&[1 << (Digit::BITS - 2), 0]);
+        let eighth = number(&[7 << (Digit::BITS - 3), 0]);
+        let mut product = eighth.clone();
+        let (mut sum, mut four) = (quarter(), quarter());
+        #[cfg(not(feature = "checked"))]
+        {
+            sum.add_assign(&quarter());
+            assert_eq!(sum.digits, [1 << (Digit::BITS - 1), 0]);
+            assert_eq!(four.mul4(), 1);
+            assert_eq!(four.digits, [0, 0]);
+            assert_eq!(product.mul_small(3), 2);
+        }
+        #[cfg(feature = "checked")]
+        {
+            assert!(sum.add_assign(&quarter()).is_err());
+            assert_eq!(sum.digits, quarter().digits);
+            assert!(four.mul4().is_err());
+            assert_eq!(four.digits, quarter().digits);
+            assert_eq!(product.mul_small(3).unwrap(), 2);
+        }
+        // -3/8 as unsigned fraction, which is 21/8 - 2
+        assert_eq!(product.digits, [5 << (Digit::BITS - 3), 0]);
+    }
+
+    #[test]
+    fn checked_arithmetic_keeps_value_of_many_digits() {
+        // An error restores every digit, not only the leading one
+        let quarter = || number(&[1 << (Digit::BITS - 2), 0, 7, Digit::MAX]);
+        let mut value = quarter();
+        assert!(value.checked_mul4().is_err());
+        assert_eq!(value.digits, quarter().digits);
+        assert!(value.checked_mul_small(3).is_err());
+        assert_eq!(value.digits, quarter().digits);
+        assert!(value.checked_add_assign(&quarter()).is_err());
+        assert_eq!(value.digits, quarter().digits);
+        assert_zeros_valid(&value);
+        // -0.25 twice is -0.5, which is still in range
+        let mut value = number(&[3 << (Digit::BITS - 2), 0, 0, 0]);
+        value.checked_add_assign(&value.clone()).unwrap();
+        assert_eq!(value.digits, [1 << (Digit::BITS - 1), 0, 0, 0]);
+    }
+
+    #[test]
+    fn try_clone_into_copies_or_rejects_length() {
+        let source = number(&[0, 3, Digit::MAX]);
+        let mut target = number(&[7, 7, 7]);
+        source.try_clone_into(&mut target).unwrap();
+        assert_eq!(target.digits, source.digits);
+        assert_eq!(target.zeros, 1);
+        let mut short = number(&[7, 7]);
+        let err = source.try_clone_into(&mut short).unwrap_err();
+        assert_eq!((err.expected, err.found), (2, 3));
+        // target is left alone
+        assert_eq!(short.digits, [7, 7]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "copy_from between Numbers of different length")]
+    fn copy_from_rejects_length_in_debug_builds() {
+        number(&[1, 2]).copy_from(&number(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn extend_precision_keeps_value() {
+        let mut x = number(&[0, 5, Digit::MAX]);
+        x.extend_precision(3);
+        assert_eq!(x.digits, [0, 5, Digit::MAX]);
+        x.extend_precision(5);
+        assert_eq!(x.digits, [0, 5, Digit::MAX, 0, 0]);
+        assert_eq!(x.zeros, 1);
+        // Dividing the longer value gives the leading digits of the quotient at the old precision
+        let mut third: Number = Number::from_inv_with_len(3, 2);
+        third.extend_precision(4);
+        third /= 5;
+        let fifteenth: Number = Number::from_inv_with_len(15, 4);
+        assert_eq!(third.digits[..2], fifteenth.digits[..2]);
+        let mut zero: Number = Number::zero_with_len(2);
+        zero.extend_precision(4);
+        assert!(zero.is_zero());
+        assert_eq!(zero.len(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "extend_precision can not reduce the precision")]
+    fn extend_precision_rejects_fewer_digits() {
+        number(&[1, 2, 3]).extend_precision(2);
+    }
+
+    fn reference_mul(a: &[Digit], b: &[Digit]) -> Vec<Digit> {
+        // The upper half of the double-length product, adding each product of two digits to the
+        // result on its own and propagating its carry through all digits above
+        let n = a.len();
+        let mut prod = vec![0; 2*n];
+        for (i, &a) in a.iter().enumerate() {
+            for (j, &b) in b.iter().enumerate() {
+                let p = a as Double * b as Double;
+                let mut k = i + j + 1;
+                let mut carry = p;
+                while carry != 0 {
+                    let sum = prod[k] as Double + (carry as Digit) as Double;
+                    prod[k] = sum as Digit;
+                    carry = (carry >> Digit::BITS) + (sum >> Digit::BITS);
+                    if k == 0 {
+                        break;
+                    }
+                    k -= 1;
+                }
+            }
+        }
+        prod.truncate(n);
+        prod
+    }
+
+    #[test]
+    fn mul_and_square_agree_with_reference() {
+        let mut seed = 0x6a09e667f3bcc909;
+        for len in [1, 2, 3, 6] {
+            for zeros in 0..len {
+                for _ in 0..30 {
+                    let a = random_digits(&mut seed, len, zeros);
+                    let b = random_digits(&mut seed, len, len - 1 - zeros);
+                    let product = number(&a).mul(&number(&b));
+                    assert_eq!(product.digits, reference_mul(&a, &b), "{:x?} * {:x?}", a, b);
+                    assert_zeros_valid(&product);
+                    let square = number(&a).square();
+                    assert_eq!(square.digits, reference_mul(&a, &a), "{:x?}^2", a);
+                    assert_zeros_valid(&square);
+                }
+            }
+        }
+        // 1/2 * 1/2 = 1/4 and the largest value squared
+        let half = number(&[1 << (Digit::BITS - 1), 0]);
+        assert_eq!(half.mul(&half).digits, [1 << (Digit::BITS - 2), 0]);
+        assert_eq!(number(&[Digit::MAX]).square().digits, [Digit::MAX - 1]);
+    }
+
+    #[test]
+    fn combine_arctans_agrees_with_integer_sum() {
+        // With one digit, the sum of c*d over the digits d is exact in an i128, and its integer
+        // part is the one of the values as unsigned fractions
+        let mut seed = 0xbb67ae8584caa73b;
+        for count in 1..6 {
+            for _ in 0..200 {
+                let terms: Vec<(i64, Number)> = (0..count).map(|_| {
+                    let c = (random_digits(&mut seed, 1, 0)[0] % 201) as i64 - 100;
+                    (c, number(&random_digits(&mut seed, 1, 0)))
+                }).collect();
+                let sum: i128 = terms.iter().map(|(c, x)| *c as i128 * x.digits[0] as i128).sum();
+                let (frac, int_part) = combine_arctans(&terms);
+                assert_eq!(frac.digits[0] as i128, sum.rem_euclid(1 << Digit::BITS));
+                assert_eq!(int_part as i128, sum.div_euclid(1 << Digit::BITS));
+                assert_zeros_valid(&frac);
+            }
+        }
+    }
+
+    #[test]
+    fn combine_arctans_gives_pi() {
+        let limbs = 192 / Digit::BITS as usize;
+        let (atan5, _) = ataninv_scalar_until(5, limbs, Series::Atan, None);
+        let (atan239, _) = ataninv_scalar_until(239, limbs, Series::Atan, None);
+        let (pi, int_part) = combine_arctans(&[(16, atan5), (-4, atan239)]);
+        assert_eq!(int_part, 3);
+        let digits: String = pi.decimal_chunks().collect();
+        assert!(digits.starts_with("14159265358979323846264338327950288"), "{}", digits);
+    }
+
+    #[test]
+    fn ataninv_signed_is_odd() {
+        for x in [2, 5, 239] {
+            for nthreads in [0, 2] {
+                let positive = ataninv_signed(x, 4, nthreads);
+                let (expected, _) = ataninv_scalar_until(x as Digit, 4, Series::Atan, None);
+                assert_eq!(positive.digits, expected.digits);
+                let mut sum = ataninv_signed(-x, 4, nthreads);
+                assert_eq!(sum.sign_bit(), 1, "atan(-1/{}) is not negative", x);
+                sum.wrapping_add_assign(&positive);
+                assert!(sum.is_zero(), "atan(-1/{}) is not -atan(1/{})", x, x);
+            }
+        }
+    }
+
+    #[test]
+    fn ataninv_range_sums_up_to_whole_series() {
+        for x in [2, 5, 239] {
+            let limbs = 4;
+            let (expected, _) = ataninv_scalar_until(x, limbs, Series::Atan, None);
+            let terms = atan_term_count(x, limbs);
+            for split in [1, 2, 3, terms / 2, terms, terms + 5] {
+                let mut sum = ataninv_range(x, 0, split, limbs);
+                sum.wrapping_add_assign(&ataninv_range(x, split, usize::MAX, limbs));
+                assert_eq!(sum.digits, expected.digits, "x = {}, split at {}", x, split);
+            }
+            // Three ranges, one of them empty
+            let mut sum = ataninv_range(x, 0, 2, limbs);
+            sum.wrapping_add_assign(&ataninv_range(x, 2, 2, limbs));
+            sum.wrapping_add_assign(&ataninv_range(x, 2, usize::MAX, limbs));
+            assert_eq!(sum.digits, expected.digits);
+            assert!(ataninv_range(x, 3, 1, limbs).is_zero());
+            // The first term alone is 1/x
+            let first: Number = Number::from_inv_with_len(x, limbs);
+            assert_eq!(ataninv_range(x, 0, 1, limbs).digits, first.digits);
+        }
+    }
+
+    #[test]
+    fn evaluate_machin_like_gives_pi_and_ln2() {
+        let limbs = 192 / Digit::BITS as usize;
+        let decimals = |x: &Number| -> String { x.decimal_chunks().collect() };
+        for nthreads in [0, 3] {
+            let (pi, int_part) = evaluate_machin_like(&[(16, 5), (-4, 239)], false, limbs,
+                                                      nthreads);
+            assert_eq!(int_part, 3);
+            assert!(decimals(&pi).starts_with("14159265358979323846264338327950288"));
+            // Machin's and Euler's formula for pi/4 differ in the truncation errors only
+            let (euler, int_part) = evaluate_machin_like(&[(4, 2), (4, 3)], false, limbs,
+                                                         nthreads);
+            assert_eq!(int_part, 3);
+            assert!(euler.agreement(&pi) >= limbs - 1);
+            let (ln2, int_part) = evaluate_machin_like(&[(2, 3)], true, limbs, nthreads);
+            assert_eq!(int_part, 0);
+            assert!(decimals(&ln2).starts_with("69314718055994530941723212145817656"));
+            let (other, _) = evaluate_machin_like(&[(18, 26), (-2, 4801), (8, 8749)], true, limbs,
+                                                  nthreads);
+            assert!(other.agreement(&ln2) >= limbs - 1);
+        }
+    }
+
+    #[test]
+    fn mod_small_agrees_with_u128() {
+        // 128 bits of digits are one u128
+        let len = 128 / Digit::BITS as usize;
+        let mut seed = 0x3c6ef372fe94f82b;
+        for zeros in 0..=len {
+            for _ in 0..50 {
+                let digits = random_digits(&mut seed, len, zeros);
+                let value = digits.iter().fold(0u128, |acc, &d| (acc << Digit::BITS) | d as u128);
+                for m in [1, 2, 3, 10, 239, Digit::MAX - 58, Digit::MAX] {
+                    assert_eq!(number(&digits).mod_small(m) as u128, value % m as u128,
+                               "{:x?} mod {}", digits, m);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "mod_small by zero")]
+    fn mod_small_rejects_zero() {
+        number(&[1]).mod_small(0);
+    }
+
+    #[test]
+    fn atan_terms_sum_up_to_scalar_result() {
+        for x in [2, 5, 18, 57, 239, LARGE_X] {
+            for limbs in [1, 3, 10] {
+                let mut terms = AtanTerms::new(x, limbs);
+                let mut sum: Number = Number::zero_with_len(limbs);
+                let mut count = 0;
+                for (k, term) in terms.by_ref().enumerate() {
+                    // The sign alternates after the positive 1/x, which is 0.5 and so looks
+                    // negative for x = 2
+                    if k > 0 {
+                        assert_eq!(term.sign_bit() == 1, k % 2 == 1, "x = {}, term {}", x, k);
+                    }
+                    sum.wrapping_add_assign(&term);
+                    count += 1;
+                }
+                // Fused after the first zero term
+                assert!(terms.next().is_none());
+                let (expected, _) = ataninv_scalar_until(x, limbs, Series::Atan, None);
+                assert_eq!(sum.digits, expected.digits, "x = {}, {} limbs", x, limbs);
+                if limbs == 10 && x != LARGE_X {
+                    assert_eq!(count, atan_term_count(x, limbs), "x = {}", x);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "atan(1/x) only converges for x > 1")]
+    fn atan_terms_rejects_x_one() {
+        AtanTerms::new(1, 4);
+    }
+
+    #[test]
+    fn parallel_sum_reproduces_arctan() {
+        // The terms of atan(1/x) with the odd ones as positive Numbers that are subtracted, in
+        // their order and reversed
+        for x in [2, 5, 239] {
+            let limbs = 6;
+            let terms: Vec<(bool, Number)> = AtanTerms::new(x, limbs).enumerate()
+                .map(|(k, mut term)| {
+                    if k % 2 == 1 {
+                        term.negate();
+                    }
+                    (k % 2 == 1, term)
+                })
+                .collect();
+            for nthreads in 0..4 {
+                let (expected, _, _) = ataninv_until(x, limbs, nthreads, Series::Atan, None, false,
+                                                     None);
+                assert_eq!(parallel_sum(terms.clone(), nthreads).digits, expected.digits,
+                           "x = {}, {} threads", x, nthreads);
+                let reversed = terms.iter().rev().cloned();
+                assert_eq!(parallel_sum(reversed, nthreads).digits, expected.digits,
+                           "x = {}, {} threads, reversed", x, nthreads);
+            }
+        }
+        assert!(parallel_sum(Vec::new(), 2).is_zero());
+    }
+
+    #[test]
+    fn mul_pow2_agrees_with_u128() {
+        // 128 bits of digits are one u128 fraction, shifted by whole limbs, single bits and both,
+        // up to past the end of the digits
+        let len = 128 / Digit::BITS as usize;
+        let mut seed = 0x510e527fade682d1;
+        for zeros in 0..=len {
+            for _ in 0..20 {
+                let digits = random_digits(&mut seed, len, zeros);
+                let value = digits.iter().fold(0u128, |acc, &d| (acc << Digit::BITS) | d as u128);
+                for exp in 0..=128 + Digit::BITS + 3 {
+                    let (int, frac) = match exp {
+                        0 => (0, value),
+                        1..=127 => (value >> (128 - exp), value << exp),
+                        _ => (value << (exp - 128), 0),
+                    };
+                    let mut x = number(&digits);
+                    assert_eq!(x.mul_pow2(exp), int as Digit, "{:x?} * 2^{}", digits, exp);
+                    let expected: Vec<Digit> = (0..len)
+                        .map(|i| (frac >> (128 - Digit::BITS as usize * (i + 1))) as Digit)
+                        .collect();
+                    assert_eq!(x.digits, expected, "{:x?} * 2^{}", digits, exp);
+                    assert_zeros_valid(&x);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zeros_after_change_matches_update_zeros() {
+        // add_assign, sub_assign and add_div_assign only rescan from the first digit they changed,
+        // which must give the same zeros as a full scan. The values have leading zeros in both
+        // operands, and carries and borrows into and out of them.
+        let mut seed = 0x9b05688c2b3e6c1f;
+        for len in 1..=6 {
+            for zeros_a in 0..=len {
+                for zeros_b in 0..=len {
+                    for _ in 0..10 {
+                        let a = number(&random_digits(&mut seed, len, zeros_a));
+                        let b = number(&random_digits(&mut seed, len, zeros_b));
+                        let d = [1, 3, Digit::MAX][seed as usize % 3];
+                        let mut results = [a.clone(), a.clone(), a.clone(), a.clone()];
+                        results[0].wrapping_add_assign(&b);
+                        results[1].sub_assign(&b);
+                        results[2].add_div_assign(&b, d, false);
+                        results[3].add_div_assign(&b, d, true);
+                        for result in &results {
+                            let mut rescanned = result.clone();
+                            rescanned.update_zeros();
+                            assert_eq!(result.zeros, rescanned.zeros,
+                                       "{:x?} and {:x?} / {}", a.digits, b.digits, d);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn from_u64_fraction_of_quarter_and_third() {
+        let quarter: Number = Number::from_u64_fraction_with_len(1, 4, 3);
+        assert_eq!(quarter.digits, [1 << (Digit::BITS - 2), 0, 0]);
+        assert_eq!(quarter.zeros, 0);
+        // 0x5555... truncated in the last digit
+        let third: Number = Number::from_u64_fraction_with_len(1, 3, 3);
+        assert_eq!(third.digits, [Digit::MAX / 3; 3]);
+        // Beyond u32 digits, 2^62 / (2^64 - 1) = 2^-2 + 2^-66 + 2^-130 + ...
+        let large: Number =
+            Number::from_u64_fraction_with_len(1 << 62, u64::MAX, 128 / Digit::BITS as usize);
+        let value = large.digits.iter().fold(0u128, |acc, &d| (acc << Digit::BITS) | d as u128);
+        assert_eq!(value, 0x4000_0000_0000_0000_4000_0000_0000_0000);
+        let zero: Number = Number::from_u64_fraction_with_len(0, 7, 2);
+        assert!(zero.is_zero());
+        let third: Number = Number::from_u64_fraction(1, 3);
+        assert_eq!(third.len(), DIGITS);
+    }
+
+    #[test]
+    #[should_panic(expected = "1/2 is not below 1/2")]
+    fn from_u64_fraction_rejects_half() {
+        let _: Number = Number::from_u64_fraction_with_len(1, 2, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "3/5 is not below 1/2")]
+    fn from_u64_fraction_rejects_above_half() {
+        let _: Number = Number::from_u64_fraction_with_len(3, 5, 2);
+    }
+
+    #[test]
+    fn upper_boundary_after_over_multiplying() {
+        let limbs = 3;
+        let value = |x: Digit| -> Number { Number::from_inv_with_len(x, limbs) };
+        // 1/8 * 4 is 0.5 itself, 1/5 * 4 and 1/7 * 4 are above it
+        for (x, boundary) in [(8, true), (5, true), (7, true), (9, false), (16, false)] {
+            let mut y = value(x);
+            assert!(!y.is_at_upper_boundary());
+            assert_eq!(y.wrapping_mul4(), 0);
+            assert_eq!(y.is_at_upper_boundary(), boundary, "4/{}", x);
+        }
+        // Just below 0.5, and a difference below zero
+        assert!(!number(&[Digit::MAX >> 1, Digit::MAX, Digit::MAX]).is_at_upper_boundary());
+        let mut diff = Number::zero_with_len(limbs);
+        diff.sub_assign(&value(7));
+        assert!(diff.is_at_upper_boundary());
+        // 1/3 * 4 wraps by a whole one to 1/3, which is not detected
+        let mut third = value(3);
+        assert_eq!(third.wrapping_mul4(), 1);
+        assert!(!third.is_at_upper_boundary());
+    }
+
+    fn rounded(digits: &[Digit], limb: usize) -> Number {
+        let mut x = number(digits);
+        x.round_to_limb(limb);
+        assert_zeros_valid(&x);
+        x
+    }
+
+    #[test]
+    fn round_to_limb_rounds_to_nearest() {
+        const HALF: Digit = 1 << (Digit::BITS - 1);
+        // Below and above one half of the last kept digit
+        assert_eq!(rounded(&[5, 7, HALF - 1, Digit::MAX], 2).digits, [5, 7, 0, 0]);
+        assert_eq!(rounded(&[5, 7, HALF, 1], 2).digits, [5, 8, 0, 0]);
+        assert_eq!(rounded(&[5, 7, Digit::MAX, 0], 2).digits, [5, 8, 0, 0]);
+        // Up across a limb boundary, and wrapping around with all kept digits Digit::MAX
+        assert_eq!(rounded(&[5, Digit::MAX, Digit::MAX, HALF + 1], 3).digits, [6, 0, 0, 0]);
+        assert_eq!(rounded(&[Digit::MAX, Digit::MAX, HALF + 1], 2).digits, [0, 0, 0]);
+        // Into leading zeros, which zeros has to follow
+        let x = rounded(&[0, 0, Digit::MAX, HALF], 3);
+        assert_eq!((x.digits.clone(), x.zeros), (vec![0, 1, 0, 0], 1));
+        // To zero digits, and to all of them
+        assert_eq!(rounded(&[HALF + 1, 0], 0).digits, [0, 0]);
+        assert_eq!(rounded(&[3, HALF], 2).digits, [3, HALF]);
+        assert!(rounded(&[0, 0, HALF - 1], 2).is_zero());
+    }
+
+    #[test]
+    fn round_to_limb_ties_to_even() {
+        const HALF: Digit = 1 << (Digit::BITS - 1);
+        // Exactly one half, so the last kept digit becomes even
+        assert_eq!(rounded(&[5, 7, HALF, 0], 2).digits, [5, 8, 0, 0]);
+        assert_eq!(rounded(&[5, 6, HALF, 0], 2).digits, [5, 6, 0, 0]);
+        assert_eq!(rounded(&[5, Digit::MAX, HALF, 0], 2).digits, [6, 0, 0, 0]);
+        // Without a kept digit, the tie goes to zero
+        assert_eq!(rounded(&[HALF, 0], 0).digits, [0, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can not round 2 digits to 3")]
+    fn round_to_limb_rejects_more_digits() {
+        number(&[1, 2]).round_to_limb(3);
+    }
+
+    #[test]
+    fn combine_scaled_agrees_with_mul_small_and_add_assign() {
+        // The integer part of the iterated sum is the ones of mul_small plus one for each
+        // add_assign that wrapped around, which is when the sum became smaller
+        let mut seed = 0x1f83d9abfb41bd6b;
+        for len in 1..=4 {
+            for count in 0..=5 {
+                for _ in 0..20 {
+                    let xs: Vec<Number> = (0..count)
+                        .map(|i| number(&random_digits(&mut seed, len, i % (len + 1))))
+                        .collect();
+                    let coefficients: Vec<Digit> = random_digits(&mut seed, count, 0).iter()
+                        .map(|&c| c.max(2))
+                        .collect();
+                    let mut expected: Number = Number::zero_with_len(len);
+                    let mut int_part: Double = 0;
+                    for (&c, x) in coefficients.iter().zip(&xs) {
+                        let mut scaled = x.clone();
+                        int_part += scaled.wrapping_mul_small(c) as Double;
+                        let before = expected.digits.clone();
+                        expected.wrapping_add_assign(&scaled);
+                        int_part += (expected.digits < before) as Double;
+                    }
+                    let terms: Vec<(Digit, &Number)> = coefficients.iter().copied().zip(&xs)
+                        .collect();
+                    let (result, int) = Number::combine_scaled(&terms, len);
+                    assert_eq!((&result.digits, int), (&expected.digits, int_part),
+                               "{:?}", coefficients);
+                    assert_zeros_valid(&result);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn combine_scaled_carries_into_integer_part() {
+        // Digit::MAX times the largest fraction, twice, needs more than a Digit for the integer
+        // part
+        let x = number(&[Digit::MAX; 3]);
+        let (result, int) = Number::combine_scaled(&[(Digit::MAX, &x), (Digit::MAX, &x)], 3);
+        // 2 (2^BITS - 1) (1 - 2^-3BITS) = 2^(BITS+1) - 2 - 2^(1-2BITS) + 2^(1-3BITS)
+        assert_eq!(int, (1 << (Digit::BITS + 1)) - 3);
+        assert_eq!(result.digits, [Digit::MAX, Digit::MAX - 1, 2]);
+    }
+
+    #[test]
+    fn approx_log2_of_powers_and_fractions() {
+        let limbs = 3;
+        let bits = Digit::BITS as f64;
+        let negated = |x: &Number| {
+            let mut result = Number::zero_with_len(x.len());
+            result.sub_assign(x);
+            result
+        };
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        for (x, expected) in [(4, -2.0), (256, -8.0), (3, -3f64.log2()), (1000, -1000f64.log2())] {
+            let value: Number = Number::from_inv_with_len(x, limbs);
+            assert!(close(value.approx_log2(), expected), "1/{}: {}", x, value.approx_log2());
+            // -1/x has the same magnitude, up to one unit in the last digit
+            assert!(close(negated(&value).approx_log2(), expected), "-1/{}", x);
+        }
+        // 2^-70 is in the second digit for u64 digits and the third for u32 digits
+        let mut tiny = number(&[0; 3]);
+        tiny.digits[69 / Digit::BITS as usize] = 1 << (Digit::BITS - 1 - 69 % Digit::BITS);
+        tiny.update_zeros();
+        assert!(close(tiny.approx_log2(), -70.0));
+        // The smallest magnitudes, positive and negative, and zero
+        assert_eq!(number(&[0, 0, 1]).approx_log2(), -3.0 * bits);
+        assert_eq!(number(&[Digit::MAX; 3]).approx_log2(), -3.0 * bits);
+        assert_eq!(number(&[0; 3]).approx_log2(), f64::NEG_INFINITY);
+        // -0.5 is the largest magnitude
+        assert!(close(number(&[1 << (Digit::BITS - 1), 0, 0]).approx_log2(), -1.0));
+    }
+
+    #[test]
+    fn mul_small_then_add_agrees_with_two_steps() {
+        // mul_small and add_assign, plus one for the integer part if add_assign wrapped around
+        let mut seed = 0x5be0cd19137e2179;
+        for len in 1..=5 {
+            for zeros in 0..=len {
+                for _ in 0..40 {
+                    let x = random_digits(&mut seed, len, zeros);
+                    let addend = number(&random_digits(&mut seed, len, len - zeros));
+                    let factor = random_digits(&mut seed, 1, 0)[0];
+                    let mut expected = number(&x);
+                    let mut int_part = expected.wrapping_mul_small(factor);
+                    let before = expected.digits.clone();
+                    expected.wrapping_add_assign(&addend);
+                    int_part = int_part.wrapping_add((expected.digits < before) as Digit);
+                    let mut result = number(&x);
+                    let int = result.mul_small_then_add(factor, &addend);
+                    assert_eq!((&result.digits, int), (&expected.digits, int_part),
+                               "{:x?} * {} + {:x?}", x, factor, addend.digits);
+                    // zeros is exact, not only a lower bound
+                    let mut rescanned = result.clone();
+                    rescanned.update_zeros();
+                    assert_eq!(result.zeros, rescanned.zeros);
+                }
+            }
+        }
+        // The largest integer part, (2^BITS - 1) (1 - 2^-BITS) + 1 - 2^-BITS
+        let mut x = number(&[Digit::MAX]);
+        assert_eq!(x.mul_small_then_add(Digit::MAX, &number(&[Digit::MAX])), Digit::MAX);
+        assert_eq!(x.digits, [0]);
+    }
+
+    #[test]
+    fn saturating_add_assign_clamps_at_boundary() {
+        // With one digit, the exact sum is an i128, clamped to the range
+        let half: i128 = 1 << (Digit::BITS - 1);
+        let mut seed = 0x6a09e667f3bcc908;
+        for _ in 0..2000 {
+            let a = number(&random_digits(&mut seed, 1, 0));
+            let b = number(&random_digits(&mut seed, 1, 0));
+            let mut result = a.clone();
+            result.saturating_add_assign(&b);
+            let expected = (signed(&a) + signed(&b)).clamp(-half, half - 1);
+            assert_eq!(signed(&result), expected, "{:x?} + {:x?}", a.digits, b.digits);
+            assert_zeros_valid(&result);
+        }
+        // Right at the boundary: the largest value plus zero stays, plus the smallest step clamps
+        let largest = || number(&[Digit::MAX >> 1, Digit::MAX, Digit::MAX]);
+        let unit = number(&[0, 0, 1]);
+        let mut value = largest();
+        value.saturating_add_assign(&Number::zero_with_len(3));
+        assert_eq!(value.digits, largest().digits);
+        value.saturating_add_assign(&unit);
+        assert_eq!(value.digits, largest().digits);
+        // -0.5 minus the smallest step clamps to -0.5
+        let mut value = number(&[1 << (Digit::BITS - 1), 0, 0]);
+        value.saturating_add_assign(&number(&[Digit::MAX; 3]));
+        assert_eq!(value.digits, [1 << (Digit::BITS - 1), 0, 0]);
+        // Clamping a value that started at zero sets all digits and zeros
+        let mut value = number(&[0, 0, 0]);
+        value.saturating_add_assign(&largest());
+        value.saturating_add_assign(&unit);
+        assert_eq!((value.digits.clone(), value.zeros), (largest().digits, 0));
+    }
+
+    fn to_f64(n: &Number) -> f64 {
+        // The signed value of n, exact if its bits span at most 53 places
+        let mut magnitude = n.clone();
+        let neg = n.sign_bit() == 1;
+        if neg {
+            magnitude.negate();
+        }
+        let value: f64 = (0..n.len())
+            .map(|i| magnitude.digits[i] as f64 * 2f64.powi(-(Digit::BITS as i32) * (i as i32 + 1)))
+            .sum();
+        if neg { -value } else { value }
+    }
+
+    #[test]
+    fn from_f64_round_trips() {
+        // Within 128 bits, every one of these is exact
+        let limbs = 128 / Digit::BITS as usize;
+        for x in [0.0, 0.25, 1.0 / 3.0, -0.1, 0.49999999999999994, -0.5, 1e-20, -1e-15,
+                  2f64.powi(-100), -2f64.powi(-127), std::f64::consts::PI - 3.0] {
+            let n: Number = Number::from_f64_with_len(x, limbs).unwrap();
+            assert_eq!(to_f64(&n), x, "{:e}", x);
+            assert_zeros_valid(&n);
+        }
+        // Bits beyond the digits are truncated towards zero, also for negative values
+        let x = 2f64.powi(-40) + 2f64.powi(-92);
+        let positive: Number = Number::from_f64_with_len(x, 64 / Digit::BITS as usize).unwrap();
+        let negative: Number = Number::from_f64_with_len(-x, 64 / Digit::BITS as usize).unwrap();
+        assert_eq!((to_f64(&positive), to_f64(&negative)), (2f64.powi(-40), -2f64.powi(-40)));
+        // Subnormals are far below the digits
+        let tiny: Number = Number::from_f64_with_len(5e-324, limbs).unwrap();
+        assert!(tiny.is_zero());
+        let quarter: Number = Number::from_f64(0.25).unwrap();
+        assert_eq!(quarter.len(), DIGITS);
+    }
+
+    #[test]
+    fn from_f64_rejects_non_finite_and_out_of_range() {
+        for x in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 0.5, 1.0, -0.5000000000000001, -3.0] {
+            assert!(Number::<Vec<Digit>>::from_f64_with_len(x, 2).is_none(), "{}", x);
+        }
+    }
+
+    #[test]
+    fn power_of_two_fractions() {
+        let limbs = 3;
+        let bits = Digit::BITS;
+        // 1/4, 1/2 at the boundary of the signed range, and every single bit
+        let quarter: Number = Number::from_inv_with_len(4, limbs);
+        assert_eq!(quarter.is_power_of_two_fraction(), Some(2));
+        let half: Number = Number::from_inv_with_len(2, limbs);
+        assert_eq!(half.is_power_of_two_fraction(), Some(1));
+        for k in 1..=limbs as u32 * bits {
+            let mut digits = vec![0; limbs];
+            digits[((k - 1) / bits) as usize] = 1 << (bits - 1 - (k - 1) % bits);
+            assert_eq!(number(&digits).is_power_of_two_fraction(), Some(k));
+        }
+        // 1/3, zero, two bits in one digit and in two digits
+        let third: Number = Number::from_inv_with_len(3, limbs);
+        assert_eq!(third.is_power_of_two_fraction(), None);
+        assert_eq!(number(&[0; 3]).is_power_of_two_fraction(), None);
+        assert_eq!(number(&[0, 3, 0]).is_power_of_two_fraction(), None);
+        assert_eq!(number(&[0, 1, 1]).is_power_of_two_fraction(), None);
+        // After mul_small, the bit moves into the digit before, and 1/2 * 2 leaves no bit in the
+        // fraction, but zeros stays below the first non-zero digit
+        let mut x = number(&[0, 1 << (bits - 1), 0]);
+        x.wrapping_mul_small(2);
+        assert_eq!(x.is_power_of_two_fraction(), Some(bits));
+        let mut x = number(&[1 << (bits - 1), 0, 0]);
+        x.wrapping_mul_small(2);
+        assert_eq!(x.is_power_of_two_fraction(), None);
+    }
+
+    #[test]
+    fn limbs_differ_finds_any_different_digit() {
+        // Over several blocks, with leading zeros and zeros bounds that differ
+        let mut seed = 0x9e3779b97f4a7c15;
+        let len = 3 * DIFF_BLOCK + 5;
+        let digits = random_digits(&mut seed, len, 10);
+        let a = number(&digits);
+        let mut b = number(&digits);
+        b.zeros = 0;
+        assert!(!a.limbs_differ(&b) && !b.limbs_differ(&a));
+        for i in [0, 9, 10, DIFF_BLOCK - 1, DIFF_BLOCK, 2 * DIFF_BLOCK + 1, len - 1] {
+            let mut c = number(&digits);
+            c.digits[i] ^= 1 << (i as u32 % Digit::BITS);
+            c.zeros = min(c.zeros, i);
+            assert!(a.limbs_differ(&c) && c.limbs_differ(&a), "digit {}", i);
+            assert_zeros_valid(&c);
+        }
+        // Numbers of different length differ, even with the same value
+        assert!(number(&[1, 0]).limbs_differ(&number(&[1])));
+        assert!(!number(&[0, 0]).limbs_differ(&number(&[0, 0])));
+    }
+
+    #[test]
+    fn scalar_state_at_term_resumes_both_series() {
+        let limbs = 4;
+        for series in [Series::Atan, Series::Atanh] {
+            for x in [2, 10, 239] {
+                let (expected, _) = ataninv_scalar_until(x, limbs, series, None);
+                for m in [1, 2, 7, 20] {
+                    // refterm is 1/x^(2m-1), divided by x one at a time
+                    let state = ScalarState::at_term(x, limbs, series, m);
+                    let mut power: Number = Number::from_inv_with_len(x, limbs);
+                    for _ in 1..2*m - 1 {
+                        power /= x;
+                    }
+                    assert_eq!(state.refterm.digits, power.digits, "x = {}, m = {}", x, m);
+                    assert!(state.result.is_zero());
+                    assert_eq!((state.denom, state.terms), (2*m as Digit - 1, m - 1));
+                    // Terms 0..m from new, then m.. from at_term
+                    let (mut sum, _) = ataninv_scalar_resume(ScalarState::new(x, limbs, series),
+                                                             |state| state.terms + 1 < m);
+                    sum.wrapping_add_assign(&ataninv_scalar_resume(state, |_| true).0);
+                    assert_eq!(sum.digits, expected.digits, "x = {}, m = {}", x, m);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn assert_numbers_agree_reports_first_difference() {
+        let a: Number = Number::from_inv_with_len(3, 4);
+        // Only the last digit differs, or the second one
+        let mut last = a.clone();
+        last.digits[3] ^= 1;
+        assert_numbers_agree!(a, last, 1);
+        assert_numbers_agree!(a, last, 1, "x = {}", 3);
+        let mut second = a.clone();
+        second.digits[1] ^= 1;
+        assert_numbers_agree!(a, second, 3);
+        let message = *panic::catch_unwind(|| assert_numbers_agree!(a, second, 1, "x = {}", 3))
+            .unwrap_err().downcast::<String>().unwrap();
+        let expected = format!("a and second differ before digit 3, from decimal digit {} on. {}",
+                               a.decimal_agreement(&second) + 1, "x = 3");
+        assert!(message.starts_with(&expected), "{}", message);
+        // Numbers of different length agree in the digits of the shorter one
+        assert_numbers_agree!(a, Number::from_inv_with_len(3, 6), 0);
+    }
+
+    #[test]
+    fn to_hex_string_pads_every_digit() {
+        let width = Digit::BITS as usize / 4;
+        let x = number(&[1, 0xab, 0, Digit::MAX]);
+        let expected = format!("{:0w$x}{:0w$x}{:0w$x}{}", 1, 0xab, 0, "f".repeat(width), w = width);
+        assert_eq!(x.to_hex_string(), expected);
+        assert_eq!(x.to_hex_string().len(), 4 * width);
+        #[cfg(not(feature = "u32-digits"))]
+        assert_eq!(number(&[1, 0xab]).to_hex_string(), "000000000000000100000000000000ab");
+        #[cfg(feature = "u32-digits")]
+        assert_eq!(number(&[1, 0xab]).to_hex_string(), "00000001000000ab");
+        assert_eq!(number(&[]).to_hex_string(), "");
+    }
+
+    #[test]
+    fn mul4_recovers_integer_part_of_pi() {
+        // pi/4 = 4 atan(1/5) - atan(1/239) is below 1, so only the last mul4 overflows
+        let limbs = 4;
+        let (mut quarter, _) = ataninv_scalar_until(5, limbs, Series::Atan, None);
+        assert_eq!(quarter.wrapping_mul4(), 0);
+        quarter.sub_assign(&ataninv_scalar_until(239, limbs, Series::Atan, None).0);
+        let mut pi = quarter.clone();
+        assert_eq!(pi.wrapping_mul4(), 3);
+        assert_eq!(pi.leading_digits_decimal(6), "141592");
+        // The same as mul_small by 4
+        assert_eq!(quarter.wrapping_mul_small(4), 3);
+        assert_eq!(quarter.digits, pi.digits);
+    }
+
+    #[test]
+    #[ignore = "takes about 5 s, run with cargo test --release -- --ignored"]
+    fn ataninv_threaded2_stress() {
+        // Run ataninv_threaded2_until many times with random thread counts, windows, overlap and
+        // task capacities at a tiny precision, to shake out data races and deadlocks, in
+        // particular around recycling the term buffers and awaiting_nextrefterm. Every thread
+        // count up to 64 runs once with each window, with and without overlap, then
+        // PICALC_STRESS_ITERATIONS random runs follow, 1000 by default. Each result must equal
+        // the scalar one, and a run that takes longer than 10 s counts as a deadlock. With the
+        // thread sanitizer, any data race it reports fails the test too. That needs a nightly
+        // toolchain with the rust-src component, so the standard library is built with the
+        // sanitizer as well:
+        //
+        //     RUSTFLAGS=-Zsanitizer=thread cargo +nightly test -Zbuild-std \
+        //         --target x86_64-unknown-linux-gnu --release ataninv_threaded2_stress -- --ignored
+        //
+        // This has only been run without the sanitizer so far.
+        let iterations: usize = env::var("PICALC_STRESS_ITERATIONS")
+            .map_or(1000, |n| n.parse().expect("Invalid number of iterations"));
+        let windows = [None, Some(1), Some(2), Some(3), Some(5), Some(8)];
+        let limbs = 4;
+        let x = 5;
+        let (expected, _) = ataninv_scalar_until(x, limbs, Series::Atan, None);
+        let capacities = [None, Some(0), Some(1), Some(4)];
+        let sweep = (1..=64).flat_map(|nthreads| {
+            windows.iter().flat_map(move |&window| {
+                [false, true].map(|overlap| (nthreads, window, overlap, None))
+            })
+        });
+        let mut seed: u64 = 0x2545f4914f6cdd1d;
+        let mut random = move |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as usize
+        };
+        let randomized: Vec<_> = (0..iterations)
+            .map(|_| {
+                (1 + random(64), windows[random(windows.len())], random(2) == 1,
+                 capacities[random(capacities.len())])
+            })
+            .collect();
+        for (nthreads, max_window, overlap, capacity) in sweep.chain(randomized) {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                let (result, _, _) = ataninv_threaded2_until(
+                    x, limbs, nthreads, Series::Atan, max_window, overlap, capacity, None, None,
+                    None);
+                sender.send(result).unwrap();
+            });
+            let settings = format!("{} threads, window {:?}, overlap {}, capacity {:?}",
+                                   nthreads, max_window, overlap, capacity);
+            let result = match receiver.recv_timeout(Duration::from_secs(10)) {
+                Ok(result) => result,
+                Err(RecvTimeoutError::Timeout) => panic!("No result after 10 s with {}", settings),
+                Err(RecvTimeoutError::Disconnected) => panic!("Failed with {}", settings),
+            };
+            assert_eq!(result.digits, expected.digits, "{}", settings);
+        }
+    }
+
+    #[test]
+    fn precision_info_describes_main_path() {
+        let info = precision_info();
+        let lines: Vec<_> = info.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(&format!("{} limbs", group_thousands(DIGITS))), "{}", info);
+        assert_eq!(lines[1], format!("limb base: 2^{}", Digit::BITS));
+        assert_eq!(lines[2], "guard limbs: 0 for the fixed DIGITS main path, 1 with --digits");
+    }
 }
@@ -0,0 +1,123 @@
+/*
+ * Optional instrumentation, enabled with the "profile" feature. Each thread counts the calls and
+ * accumulates the time spent per operation in thread-local counters, which are merged into a
+ * global total when the thread exits. print_summary() prints the totals to stderr.
+ *
+ * Without the feature, Timer is an empty struct without Drop, so all of this compiles to nothing.
+ */
+
+#[derive(Clone, Copy)]
+pub enum Op {
+    SetToDiv,
+    DivAssign,
+    AddAssign,
+    SubAssign,
+    AddDivAssign,
+    Send,
+    Recv,
+    // A worker waiting for its next task
+    Wait,
+    // The driver of ataninv_threaded2_until waiting while it can not hand out the next window
+    // before the new refterm arrives, instead of Recv
+    RefWait,
+}
+
+#[cfg(feature = "profile")]
+mod imp {
+    use super::Op;
+    use std::cell::RefCell;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    const NAMES: [&str; 9] = [
+        "set_to_div", "div_assign", "add_assign", "sub_assign", "add_div", "send", "recv", "wait",
+        "ref_wait",
+    ];
+
+    type Counters = [(u64, Duration); NAMES.len()];
+
+    static TOTALS: Mutex<Counters> = Mutex::new([(0, Duration::ZERO); NAMES.len()]);
+
+    struct Local(Counters);
+
+    impl Local {
+        fn flush(&mut self) {
+            let mut totals = TOTALS.lock().unwrap();
+            for (total, local) in totals.iter_mut().zip(self.0.iter_mut()) {
+                total.0 += local.0;
+                total.1 += local.1;
+                *local = (0, Duration::ZERO);
+            }
+        }
+    }
+
+    impl Drop for Local {
+        fn drop(&mut self) {
+            self.flush();
+        }
+    }
+
+    thread_local! {
+        static LOCAL: RefCell<Local> =
+            const { RefCell::new(Local([(0, Duration::ZERO); NAMES.len()])) };
+    }
+
+    pub struct Timer {
+        op: Op,
+        start: Instant,
+    }
+
+    impl Timer {
+        pub fn new(op: Op) -> Self {
+            Timer { op, start: Instant::now() }
+        }
+    }
+
+    impl Drop for Timer {
+        fn drop(&mut self) {
+            let elapsed = self.start.elapsed();
+            LOCAL.with(|local| {
+                let entry = &mut local.borrow_mut().0[self.op as usize];
+                entry.0 += 1;
+                entry.1 += elapsed;
+            });
+        }
+    }
+
+    pub fn print_summary() {
+        // Only threads that already exited have been merged, so make sure all workers were joined
+        // before calling this.
+        LOCAL.with(|local| local.borrow_mut().flush());
+        let totals = TOTALS.lock().unwrap();
+        eprintln!("{:<12} {:>12} {:>12}", "operation", "calls", "time [s]");
+        for (name, (calls, time)) in NAMES.iter().zip(totals.iter()) {
+            eprintln!("{:<12} {:>12} {:>12.3}", name, calls, time.as_secs_f64());
+        }
+    }
+}
+
+#[cfg(not(feature = "profile"))]
+mod imp {
+    use super::Op;
+
+    pub struct Timer;
+
+    impl Timer {
+        #[inline(always)]
+        pub fn new(_op: Op) -> Self {
+            Timer
+        }
+    }
+
+    #[inline(always)]
+    pub fn print_summary() {}
+}
+
+pub use imp::{Timer, print_summary};
+
+#[inline(always)]
+pub fn time<T>(op: Op, f: impl FnOnce() -> T) -> T {
+    // Time a single expression, mostly used for the channel operations.
+    let _timer = Timer::new(op);
+    f()
+}
@@ -0,0 +1,238 @@
+/*
+ * 1/pi with Ramanujan's series from 1914:
+ *
+ *     1/pi = 2 sqrt(2) / 9801 * sum over k >= 0 of (4k)! (1103 + 26390k) / ((k!)^4 396^(4k))
+ *
+ * The ratio of consecutive terms tends to 4^4 / 396^4, so each term adds log10(396^4 / 256), about
+ * 7.98 decimal digits. Computing the terms one by one like the arctan series would need several
+ * passes over all digits per term, which is slower than Machin's formula despite the fewer terms.
+ * Instead, the sum of the first n terms is found exactly as a fraction T/Q of big integers by
+ * binary splitting. With p(k) = (4k-3)(4k-2)(4k-1)(4k), q(k) = k^4 396^4 and p(0) = q(0) = 1, the
+ * terms are c(k) p(1)...p(k) / (q(1)...q(k)) with c(k) = 1103 + 26390k, and for a range [a, b)
+ * split at m,
+ *
+ *     P(a, b) = P(a, m) P(m, b)
+ *     Q(a, b) = Q(a, m) Q(m, b)
+ *     T(a, b) = T(a, m) Q(m, b) + P(a, m) T(m, b)
+ *
+ * with P = p(a), Q = q(a) and T = c(a) p(a) for a single term, so the sum is T(0, n) / Q(0, n).
+ * Only the final division by Q and the multiplication by sqrt(2) are done at the target precision,
+ * with div_limbs and Number::mul.
+ *
+ * The big integers are Vecs of digits, least significant first like in division.rs, and are
+ * multiplied by schoolbook multiplication, so the whole computation still takes O(len^2).
+ */
+use super::*;
+use super::computation::limbs_for_precision;
+use super::division::div_limbs;
+
+// Decimal digits that each term adds, log10(396^4 / 4^4)
+const DIGITS_PER_TERM: f64 = 7.98;
+
+pub fn trimmed(mut a: Vec<Digit>) -> Vec<Digit> {
+    // Without leading zero digits, but at least one digit
+    while a.len() > 1 && a.last() == Some(&0) {
+        a.pop();
+    }
+    a
+}
+
+pub fn mul_digit(a: &[Digit], m: Digit) -> Vec<Digit> {
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry: Double = 0;
+    for &d in a {
+        carry += d as Double * m as Double;
+        result.push(carry as Digit);
+        carry >>= Digit::BITS;
+    }
+    result.push(carry as Digit);
+    trimmed(result)
+}
+
+pub fn mul_big(a: &[Digit], b: &[Digit]) -> Vec<Digit> {
+    let mut result = vec![0; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry: Double = 0;
+        for (j, &y) in b.iter().enumerate() {
+            carry += result[i+j] as Double + x as Double * y as Double;
+            result[i+j] = carry as Digit;
+            carry >>= Digit::BITS;
+        }
+        result[i + b.len()] = carry as Digit;
+    }
+    trimmed(result)
+}
+
+fn add_big(a: &[Digit], b: &[Digit]) -> Vec<Digit> {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry: Double = 0;
+    for (i, &d) in a.iter().enumerate() {
+        carry += d as Double + b.get(i).map_or(0, |&d| d as Double);
+        result.push(carry as Digit);
+        carry >>= Digit::BITS;
+    }
+    result.push(carry as Digit);
+    trimmed(result)
+}
+
+fn half(a: &[Digit]) -> Vec<Digit> {
+    // floor(a / 2)
+    let result = (0..a.len())
+        .map(|i| (a[i] >> 1) | (a.get(i+1).map_or(0, |&d| d << (Digit::BITS - 1))))
+        .collect();
+    trimmed(result)
+}
+
+fn isqrt(n: &[Digit]) -> Vec<Digit> {
+    // floor(sqrt(n)). The square root of the leading half of the digits, shifted back, is off by
+    // less than one unit in its last digit, so after one Newton step from there, the result is
+    // above the square root by only a few units, and the steps from above continue until they
+    // stop decreasing. Each level costs about four times as much as the one before, so the total
+    // is a small multiple of the divisions at the full length.
+    let n = trimmed(n.to_vec());
+    if n.len() <= 2 {
+        let value = n.iter().rev().fold(0, |acc: Double, &d| (acc << Digit::BITS) | d as Double);
+        // The f64 estimate is corrected in both directions
+        let mut r = (value as f64).sqrt() as Double;
+        while r.checked_mul(r).is_none_or(|square| square > value) {
+            r -= 1;
+        }
+        while (r + 1).checked_mul(r + 1).is_some_and(|square| square <= value) {
+            r += 1;
+        }
+        return trimmed(vec![r as Digit, (r >> Digit::BITS) as Digit]);
+    }
+    let shift = (n.len() / 4).max(1);
+    let mut x = vec![0; shift];
+    x.extend(isqrt(&n[2*shift..]));
+    // Newton steps x = (x + n/x) / 2, from above after the first one
+    let step = |x: &[Digit]| half(&add_big(x, &trimmed(div_limbs(&n, x))));
+    x = step(&x);
+    loop {
+        let next = step(&x);
+        if next.len() > x.len() || (next.len() == x.len() && next.iter().rev().ge(x.iter().rev())) {
+            return x;
+        }
+        x = next;
+    }
+}
+
+struct Split {
+    p: Vec<Digit>,
+    q: Vec<Digit>,
+    t: Vec<Digit>,
+}
+
+fn leaf(k: Digit) -> Split {
+    // P, Q and T for the single term k
+    let (p, q) = if k == 0 {
+        (vec![1], vec![1])
+    } else {
+        let p = [4*k - 3, 4*k - 2, 4*k - 1, 4*k].iter().fold(vec![1], |acc, &f| mul_digit(&acc, f));
+        // 396^2 fits into a u32 Digit, 396^4 does not
+        let q = [k, k, k, k, 396*396, 396*396].iter().fold(vec![1], |acc, &f| mul_digit(&acc, f));
+        (p, q)
+    };
+    let c = 1103 + 26390 * k;
+    let t = mul_digit(&p, c);
+    Split { p, q, t }
+}
+
+fn split(a: Digit, b: Digit, nthreads: usize) -> Split {
+    // P, Q and T for the terms a..b, with the left half on another thread as long as there are
+    // threads left. P of the whole range is only needed if this is not the last range, but
+    // computing it anyway is cheap compared to T.
+    if b - a == 1 {
+        return leaf(a);
+    }
+    let m = a + (b - a) / 2;
+    let (left, right) = if nthreads > 1 {
+        thread::scope(|scope| {
+            let left = scope.spawn(|| split(a, m, nthreads / 2));
+            let right = split(m, b, nthreads - nthreads / 2);
+            (left.join().unwrap(), right)
+        })
+    } else {
+        (split(a, m, 1), split(m, b, 1))
+    };
+    Split {
+        t: add_big(&mul_big(&left.t, &right.q), &mul_big(&left.p, &right.t)),
+        p: mul_big(&left.p, &right.p),
+        q: mul_big(&left.q, &right.q),
+    }
+}
+
+#[allow(dead_code)]
+pub fn compute_inv_pi_ramanujan(digits: usize, nthreads: usize) -> Number {
+    // 1/pi with at least the given number of correct decimal digits, with the same number of
+    // limbs as compute_inv_pi. The terms that are left out sum to less than one unit in the last
+    // limb, and the division, sqrt(2) and the product each truncate by less than one unit.
+    let limbs = limbs_for_precision(digits);
+    let bits = limbs * Digit::BITS as usize;
+    let terms = (bits as f64 * 2f64.log10() / DIGITS_PER_TERM) as Digit + 2;
+    let Split { q, t, .. } = split(0, terms, nthreads.max(1));
+    // y = T / (9801 Q), which is about 1103 / 9801, so it has no integer part
+    let mut scaled_t = vec![0; limbs];
+    scaled_t.extend(t);
+    let y = div_limbs(&scaled_t, &mul_digit(&q, 9801));
+    assert!(y[limbs..].iter().all(|&d| d == 0), "T / (9801 Q) is not below one");
+    let y_digits: Vec<Digit> = y[..limbs].iter().rev().copied().collect();
+    let mut y: Number = Number::from_digits(&y_digits);
+    // sqrt(2) - 1 is the fractional part of the square root of 2 * 2^(2 * bits), which has one
+    // more digit for the integer part 1
+    let mut two = vec![0; 2*limbs];
+    two.push(2);
+    let root_digits: Vec<Digit> = isqrt(&two)[..limbs].iter().rev().copied().collect();
+    let root: Number = Number::from_digits(&root_digits);
+    // 1/pi = 2 y sqrt(2) = 2 (y + y (sqrt(2) - 1))
+    let prod = y.mul(&root);
+    y.wrapping_add_assign(&prod);
+    let int_part = y.wrapping_mul_small(2);
+    assert_eq!(int_part, 0, "Integer part of 1/pi is wrong");
+    y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::computation::compute_inv_pi;
+
+    fn sub_one(a: &[Digit]) -> Vec<Digit> {
+        // a - 1 for a > 0
+        let mut borrow = true;
+        trimmed(a.iter().map(|&d| {
+            let (diff, b) = d.overflowing_sub(borrow as Digit);
+            borrow = b;
+            diff
+        }).collect())
+    }
+
+    #[test]
+    fn isqrt_of_squares_and_their_neighbours() {
+        // Roots of one to five digits, which are found directly or by Newton steps from the
+        // root of the leading digits. Everything from r^2 to r^2 + 2r has the root r.
+        let roots: [&[Digit]; 6] = [&[1], &[2], &[Digit::MAX], &[3, 1], &[Digit::MAX; 3],
+                                    &[12345, 0, 678, 9, 1]];
+        for &r in &roots {
+            let square = mul_big(r, r);
+            assert_eq!(isqrt(&square), r, "sqrt of {:x?}", square);
+            assert_eq!(isqrt(&add_big(&square, &mul_digit(r, 2))), r, "sqrt of {:x?} + 2r", square);
+            assert_eq!(isqrt(&sub_one(&square)), sub_one(r), "sqrt of {:x?} - 1", square);
+        }
+        assert_eq!(isqrt(&[0]), [0]);
+    }
+
+    #[test]
+    fn ramanujan_agrees_with_machin() {
+        for digits in [60, 300] {
+            for nthreads in [0, 3] {
+                let ramanujan = compute_inv_pi_ramanujan(digits, nthreads);
+                let machin = compute_inv_pi(digits, nthreads);
+                assert_eq!(ramanujan.len(), machin.len());
+                assert!(ramanujan.agreement(&machin) >= machin.len() - 1,
+                        "{} digits with {} threads", digits, nthreads);
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+/*
+ * add_assign and mul4 as data-parallel passes over the limbs, run on the CPU. Each pass computes
+ * every limb from the buffers of the previous passes only, like a kernel with one invocation per
+ * limb, so the limbs of a pass could be spread over SIMD lanes or threads. The results are
+ * bit-identical to wrapping_add_assign and wrapping_mul4.
+ *
+ * mul4 only needs the top bits of the next limb, so each limb is computed independently. The carry
+ * of add_assign runs through all limbs, so it is found with a parallel prefix scan: each limb
+ * either generates a carry (its sum overflows) or propagates one (its sum is all ones), and
+ * log2(len) passes combine these flags over segments of doubling length, from the least
+ * significant limb upwards.
+ */
+use super::*;
+
+#[derive(Clone, Copy, Default)]
+struct Carry {
+    // Whether a segment of limbs generates a carry out of its top, or propagates an incoming one
+    generate: bool,
+    propagate: bool,
+}
+
+impl Carry {
+    fn above(self, lower: Carry) -> Carry {
+        // Flags of this segment combined with the less significant segment right below it
+        Carry {
+            generate: self.generate || (self.propagate && lower.generate),
+            propagate: self.propagate && lower.propagate,
+        }
+    }
+}
+
+fn dispatch<T: Copy + Default>(len: usize, kernel: impl Fn(usize) -> T) -> Vec<T> {
+    // Run kernel for each index, with one invocation per limb. Each pass
+    // only reads the buffers of the previous ones, so the order does not matter.
+    (0..len).map(kernel).collect()
+}
+
+pub fn add_assign_scan(target: &mut Number, rhs: &Number) {
+    // target += rhs, like Number::add_assign
+    let len = target.len();
+    let sums = dispatch(len, |i| target.digits[i].overflowing_add(rhs.digits[i]));
+    let mut carries = dispatch(len, |i| {
+        let (sum, overflow) = sums[i];
+        Carry { generate: overflow, propagate: sum == Digit::MAX }
+    });
+    // After the pass with distance d, carries[i] holds the flags of limbs i..i+2d
+    let mut d = 1;
+    while d < len {
+        carries = dispatch(len, |i| {
+            if i + d < len { carries[i].above(carries[i+d]) } else { carries[i] }
+        });
+        d *= 2;
+    }
+    let digits = dispatch(len, |i| {
+        let carry_in = i + 1 < len && carries[i+1].generate;
+        sums[i].0.wrapping_add(carry_in as Digit)
+    });
+    target.digits = digits;
+    target.update_zeros();
+}
+
+pub fn mul4_scan(target: &mut Number) -> Digit {
+    // target *= 4 like Number::mul4, returning the integer part that overflows
+    let len = target.len();
+    let shift = Digit::BITS - 2;
+    let int = target.digits[0] >> shift;
+    let digits = dispatch(len, |i| {
+        let next = if i + 1 < len { target.digits[i+1] >> shift } else { 0 };
+        (target.digits[i] << 2) | next
+    });
+    target.digits = digits;
+    target.update_zeros();
+    int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_number(seed: &mut u64, len: usize) -> Number {
+        // Mostly all ones, so carries run across many limbs, from a xorshift generator
+        let digits: Vec<Digit> = (0..len).map(|_| {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            match *seed % 8 {
+                0 => 0,
+                1 | 2 => (*seed >> 8) as Digit,
+                _ => Digit::MAX,
+            }
+        }).collect();
+        Number::from_digits(&digits)
+    }
+
+    #[test]
+    fn add_assign_scan_agrees_with_add_assign() {
+        let mut seed = 0x9e3779b97f4a7c15;
+        for len in [1, 2, 3, 7, 8, 9, 64, 100] {
+            for _ in 0..50 {
+                let (a, b) = (random_number(&mut seed, len), random_number(&mut seed, len));
+                let mut expected = a.clone();
+                expected.wrapping_add_assign(&b);
+                let mut result = a.clone();
+                add_assign_scan(&mut result, &b);
+                assert_eq!(result.digits, expected.digits, "{:x?} + {:x?}", a.digits, b.digits);
+                assert!(result.digits[..result.zeros.min(len)].iter().all(|&d| d == 0));
+            }
+        }
+        // A carry from the last limb through all others
+        let mut ones = Number::from_digits(&[0, Digit::MAX, Digit::MAX, Digit::MAX]);
+        add_assign_scan(&mut ones, &Number::from_digits(&[0, 0, 0, 1]));
+        assert_eq!(ones.digits, [1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn mul4_scan_agrees_with_mul4() {
+        let mut seed = 0x2545f4914f6cdd1d;
+        for len in [1, 2, 3, 9, 100] {
+            for _ in 0..50 {
+                let a = random_number(&mut seed, len);
+                let mut expected = a.clone();
+                let int = expected.wrapping_mul4();
+                let mut result = a.clone();
+                assert_eq!(mul4_scan(&mut result), int, "4 * {:x?}", a.digits);
+                assert_eq!(result.digits, expected.digits, "4 * {:x?}", a.digits);
+                assert!(result.digits[..result.zeros.min(len)].iter().all(|&d| d == 0));
+            }
+        }
+    }
+}
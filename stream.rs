@@ -0,0 +1,85 @@
+/*
+ * Streaming output for picalc stream: the decimal digits of pi are printed while atan(1/5) is
+ * summed, each one as soon as it can no longer change.
+ *
+ * atan(1/239) is computed first. After that, pi is 16 s - 4 atan(1/239) + 16 r, where s is the
+ * partial sum of atan(1/5) and r the sum of the terms that are still missing, which is below
+ * refterm in magnitude. All operations are exact modulo one, so the final result lies within
+ * 16 refterm of the partial result, and decimal digits that both ends of this interval share are
+ * also the digits of the final result.
+ */
+use super::*;
+
+// Finding the final digits converts the leading digits to decimal, which gets more expensive the
+// more of them are final. Waiting this many times as long as the last check took keeps the
+// overhead at about a fifth of the runtime.
+const CHECK_RATIO: u32 = 4;
+
+fn final_digits(partial: &Number, refterm: &Number) -> String {
+    // Decimal digits that all values within 16 refterm of partial share
+    let mut margin = refterm.clone();
+    margin.wrapping_mul_small(16);
+    // The fractional part of pi is far from 0 and 1, so lower and upper do not wrap around as long
+    // as the first digit of margin is zero.
+    if margin.zeros == 0 {
+        return String::new();
+    }
+    // The digits beyond the first non-zero one of margin are not needed. Truncating lower only
+    // decreases it, upper is increased by one unit in the last remaining digit.
+    let len = min(partial.len(), margin.zeros + 2);
+    let mut lower = partial.clone();
+    lower.sub_assign(&margin);
+    let mut upper = partial.clone();
+    upper.wrapping_add_assign(&margin);
+    let lower: Number = Number::from_digits(&lower.digits[..len]);
+    let mut upper: Number = Number::from_digits(&upper.digits[..len]);
+    let mut unit = vec![0; len];
+    unit[len-1] = 1;
+    upper.wrapping_add_assign(&Number::from_digits(&unit));
+    let mut result = String::new();
+    for (a, b) in lower.decimal_chunks().zip(upper.decimal_chunks()) {
+        let equal = a.bytes().zip(b.bytes()).take_while(|(a, b)| a == b).count();
+        result.push_str(&a[..equal]);
+        if equal < a.len() {
+            break;
+        }
+    }
+    result
+}
+
+pub fn stream_pi(limbs: usize, w: &mut impl Write) -> io::Result<()> {
+    // Write pi with the given number of limbs as "3.1415...", flushing whenever more digits are
+    // final. The digits are the same as the ones of the decimal output format.
+    let (atan239, _) = ataninv_scalar_until(239, limbs, Series::Atan, None);
+    write!(w, "3.")?;
+    w.flush()?;
+    let mut written = String::new();
+    let mut next_check = Instant::now();
+    let mut error = None;
+    let (atan5, _) = ataninv_scalar_observed(5, limbs, Series::Atan, |sum, refterm| {
+        let start = Instant::now();
+        if start < next_check {
+            return true;
+        }
+        let (partial, _) = combine_arctans(&[(16, sum.clone()), (-4, atan239.clone())]);
+        let digits = final_digits(&partial, refterm);
+        if digits.len() > written.len() {
+            debug_assert!(digits.starts_with(&written), "Digits changed after they were written");
+            let new = &digits[written.len()..];
+            if let Err(err) = w.write_all(new.as_bytes()).and_then(|_| w.flush()) {
+                error = Some(err);
+                return false;
+            }
+            written = digits;
+        }
+        next_check = Instant::now() + start.elapsed() * CHECK_RATIO;
+        true
+    });
+    if let Some(err) = error {
+        return Err(err);
+    }
+    let (pi, _) = combine_arctans(&[(16, atan5), (-4, atan239)]);
+    let digits: String = pi.decimal_chunks().collect();
+    debug_assert!(digits.starts_with(&written), "Digits changed after they were written");
+    writeln!(w, "{}", &digits[written.len()..])
+}